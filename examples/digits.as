@@ -0,0 +1,25 @@
+# Print the decimal digits on the console
+# The console is mapped at 0xFF00: every byte written there goes to stdout
+LI A, 0x00
+LI B, 0xFF
+MSRL A
+MSRH B
+
+LI A, 0x30
+# Count D up from -10: the zero flag is only meaningful after an addition
+LI D, 0xf6
+
+:loop
+    MEMW A
+    INC A
+    INC D
+    JZR .done
+    PJMP :loop
+    JMP
+
+.done
+LI A, 0x0a
+MEMW A
+HLT
+
+# expect stdout="0123456789\n"
@@ -0,0 +1,33 @@
+# Copy the keyboard to the console until the input is over
+# The keyboard data is mapped at 0xFF01 and its status at 0xFF02, the console at 0xFF00
+LI B, 0xFF
+MSRH B
+
+:poll
+    LI A, 0x02
+    MSRL A
+    MEMR A
+    # Status 0x02: the input is over
+    ADDI A, 0xfe
+    JZR .done
+    # Status 0x01: a byte is available, otherwise poll again
+    INC A
+    JZR .available
+    PJMP :poll
+    JMP
+
+.available
+    LI A, 0x01
+    MSRL A
+    MEMR C
+    ZERO A
+    MSRL A
+    MEMW C
+    PJMP :poll
+    JMP
+
+.done
+HLT
+
+# input="hello\n"
+# expect stdout="hello\n"
@@ -14,4 +14,7 @@ LI B, 0x00
     JMP
 
 .halt
-HLT
\ No newline at end of file
+HLT
+
+# expect A=0xe9
+# expect B=0x79
@@ -0,0 +1,42 @@
+# Report to the emulator through the host calls, mapped from 0xFF10
+# 0xFF10 exit, 0xFF11 print, 0xFF12-0xFF13 dump address, 0xFF14 dump, 0xFF15 expected, 0xFF16 assert
+LI B, 0xFF
+MSRH B
+
+# 7 + 5, printed in hexadecimal
+LI C, 0x07
+ADDI C, 0x05
+LI A, 0x11
+MSRL A
+MEMW C
+
+# Dump the first 4 bytes of the program
+ZERO D
+LI A, 0x12
+MSRL A
+MEMW D
+LI A, 0x13
+MSRL A
+MEMW D
+LI A, 0x14
+MSRL A
+LI D, 0x04
+MEMW D
+
+# The sum must be 0x0c, or the program exits with status 0xff
+LI A, 0x15
+MSRL A
+LI D, 0x0c
+MEMW D
+LI A, 0x16
+MSRL A
+MEMW C
+
+# Exit with status 0
+LI A, 0x10
+MSRL A
+ZERO D
+MEMW D
+
+# expect stdout="0c\n0000: 21 ff 1d 22\n"
+# expect exit=0
@@ -0,0 +1,31 @@
+# Fill the framebuffer with a pattern
+# The framebuffer is 32x32 pixels at 0xF800, one RGB332 byte per pixel, row after row
+LI C, 0x00
+LI D, 0xF8
+
+:loop
+    MSRL C
+    MSRH D
+    MV A, C
+    XOR A, D
+    MEMW A
+    INC C
+    JZR .page
+    PJMP :loop
+    JMP
+
+# Stop after the last page of the framebuffer, 0xFB
+.page
+    INC D
+    MV B, D
+    ADDI B, 0x04
+    JZR .done
+    PJMP :loop
+    JMP
+
+.done
+HLT
+
+# expect mem[0xF800]=0xf8
+# expect mem[0xF9A5]=0x5c
+# expect mem[0xFBFF]=0x04
@@ -1,15 +1,13 @@
 use crate::assemble::assembly_line;
+use crate::assemble::assembly_line::AssemblyLine;
 use crate::assemble::binary_program::BinaryProgram;
 use crate::assemble::intermediate_assembly::IntermediateAssembly;
+use crate::assemble::symbols::Symbols;
 
 pub fn assemble(input: &'static str) -> eyre::Result<()> {
     println!("Assembling:\n-----\n{}\n-----", input);
 
-    let (_, assembly) = assembly_line::parse_instructions(input.trim())?;
-
-    let intermediate_assembly = IntermediateAssembly::try_from(assembly)?;
-
-    let assembled_instructions = BinaryProgram::try_from(intermediate_assembly)?;
+    let assembled_instructions = assemble_program(input)?;
 
     println!("Machine code");
     for (index, byte) in assembled_instructions.0.iter().enumerate() {
@@ -19,6 +17,30 @@ pub fn assemble(input: &'static str) -> eyre::Result<()> {
     Ok(())
 }
 
+pub fn assemble_program(input: &'static str) -> eyre::Result<BinaryProgram> {
+    let (program, _) = assemble_with_symbols(input)?;
+    Ok(program)
+}
+
+/// Same as `assemble_program`, but keeps what the debugging tools need to talk in terms of the source.
+pub fn assemble_with_symbols(input: &'static str) -> eyre::Result<(BinaryProgram, Symbols)> {
+    let trimmed = input.trim();
+    let (_, assembly) = assembly_line::parse_instructions(trimmed)?;
+    let (line_numbers, assembly): (Vec<usize>, Vec<AssemblyLine>) = assembly.into_iter().unzip();
+    // The lines trimmed off the start still count.
+    let skipped_lines = input[..input.len() - input.trim_start().len()].matches('\n').count();
+
+    let intermediate_assembly = IntermediateAssembly::try_from(assembly)?;
+    let lines = intermediate_assembly
+        .instruction_addresses()
+        .into_iter()
+        .zip(line_numbers.into_iter().map(|line| line + skipped_lines))
+        .collect();
+    let symbols = Symbols::new(BinaryProgram::compute_labels_addresses(&intermediate_assembly)?, lines);
+
+    Ok((BinaryProgram::try_from(intermediate_assembly)?, symbols))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -44,6 +66,7 @@ mod tests {
         HLT";
 
         let (_, assembly) = assembly_line::parse_instructions(input.trim())?;
+        let assembly: Vec<AssemblyLine> = assembly.into_iter().map(|(_, line)| line).collect();
         let intermediate_assembly = IntermediateAssembly::try_from(assembly)?;
         let program = BinaryProgram::try_from(intermediate_assembly)?;
 
@@ -2,7 +2,7 @@ use crate::assemble::assembly_instruction::AssemblyInstruction;
 use nom::branch::alt;
 use nom::bytes::complete::{is_not, tag};
 use nom::character::complete::{alphanumeric1, multispace0, multispace1};
-use nom::combinator::{eof, map, opt};
+use nom::combinator::{consumed, eof, map, opt};
 use nom::multi::{many0, many1, separated_list0};
 use nom::sequence::{delimited, tuple};
 use nom::IResult;
@@ -13,6 +13,15 @@ pub enum Label {
     Relative(String),
 }
 
+impl core::fmt::Display for Label {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Label::Absolute(name) => write!(f, ":{}", name),
+            Label::Relative(name) => write!(f, ".{}", name),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AssemblyLine {
     pub label: Option<Label>,
@@ -49,14 +58,20 @@ impl AssemblyLine {
     }
 }
 
-pub fn parse_instructions(input: &str) -> IResult<&str, Vec<AssemblyLine>> {
+/// Parse the instructions, along with the line of the input each one is written on (starting from 1).
+pub fn parse_instructions(input: &str) -> IResult<&str, Vec<(usize, AssemblyLine)>> {
+    let line_number = |consumed: &str| {
+        let end = consumed.as_ptr() as usize + consumed.len() - input.as_ptr() as usize;
+        input[..end].matches('\n').count() + 1
+    };
+
     delimited(
         many0(alt((parse_comment, multispace1))),
         separated_list0(
             many1(alt((multispace1, parse_comment))),
-            AssemblyLine::parse,
+            map(consumed(AssemblyLine::parse), move |(consumed, line)| (line_number(consumed), line)),
         ),
-        eof,
+        tuple((many0(alt((multispace1, parse_comment))), eof)),
     )(input)
 }
 
@@ -1,13 +1,13 @@
 use crate::assemble::assembly_line::Label;
 use crate::assemble::intermediate_assembly::{IntermediateAssembly, IntermediateElement};
-use crate::constants::machine_instruction::MachineInstruction;
+use crate::constants::machine_instruction::{MachineInstruction, NOP_OPCODE};
 use eyre::{bail, Report};
 use std::collections::HashMap;
 
 pub struct BinaryProgram(pub Vec<u8>);
 
 impl BinaryProgram {
-    fn compute_labels_addresses(assembly: &IntermediateAssembly) -> eyre::Result<HashMap<Label, u16>> {
+    pub fn compute_labels_addresses(assembly: &IntermediateAssembly) -> eyre::Result<HashMap<Label, u16>> {
         let mut addresses: HashMap<Label, u16> = HashMap::new();
         let mut current_index = 0;
 
@@ -31,7 +31,7 @@ impl TryFrom<IntermediateAssembly> for BinaryProgram {
         let instructions = {
             let mut map: HashMap<MachineInstruction, u8> = (0..=0xFF).map(|value| (MachineInstruction::from(value), value)).collect();
             // Even if all unused instructions are essentially NOPs, for consistency we manually chose one.
-            map.insert(MachineInstruction::NOP, 0b11_11_11_10);
+            map.insert(MachineInstruction::NOP, NOP_OPCODE);
             map
         };
 
@@ -151,6 +151,20 @@ impl TryFrom<Vec<AssemblyLine>> for IntermediateAssembly {
 }
 
 impl IntermediateAssembly {
+    /// The address of each instruction, in order: every assembly line starts with exactly one.
+    pub fn instruction_addresses(&self) -> Vec<u16> {
+        let mut addresses = Vec::new();
+        let mut current_index = 0;
+        for line in self.0.iter() {
+            if let IntermediateElement::Instruction(_) = line.assembly {
+                addresses.push(current_index);
+            }
+            current_index += line.assembly.content_length();
+        }
+        addresses
+    }
+
+    #[allow(unused)]
     pub fn len(&self) -> usize {
         self.0.iter().map(|x| x.assembly.content_length()).fold(0, |acc, x| acc + (x as usize))
     }
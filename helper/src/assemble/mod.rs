@@ -1,6 +1,8 @@
+#[allow(clippy::module_inception)]
 pub mod assemble;
 pub mod assembly_instruction;
 mod assembly_register;
 mod assembly_line;
 mod intermediate_assembly;
-mod binary_program;
\ No newline at end of file
+pub mod binary_program;
+pub mod symbols;
\ No newline at end of file
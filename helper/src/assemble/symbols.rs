@@ -0,0 +1,95 @@
+use crate::assemble::assembly_line::Label;
+use std::collections::HashMap;
+
+/// What the assembler knows about a program beyond its bytes.
+pub struct Symbols {
+    labels: HashMap<Label, u16>,
+    /// The address of each instruction and the line of the source it comes from, in address order.
+    lines: Vec<(u16, usize)>,
+}
+
+impl Symbols {
+    pub fn new(labels: HashMap<Label, u16>, lines: Vec<(u16, usize)>) -> Self {
+        Symbols { labels, lines }
+    }
+
+    /// The address of each instruction and the line of the source it comes from (starting from 1), in address order.
+    pub fn lines(&self) -> &[(u16, usize)] {
+        &self.lines
+    }
+
+    /// The address of a label written like in the source, e.g. `:loop` or `.halt`.
+    pub fn address(&self, label: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .find(|(candidate, _)| candidate.to_string() == label)
+            .map(|(_, address)| *address)
+    }
+
+    /// The labels that point to the given address, sorted by name.
+    pub fn labels_at(&self, address: u16) -> Vec<String> {
+        let mut labels: Vec<String> = self
+            .labels
+            .iter()
+            .filter(|(_, candidate)| **candidate == address)
+            .map(|(label, _)| label.to_string())
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    /// The address of the closest label at or before the given address, i.e. the part of the program it belongs to.
+    pub fn enclosing_label(&self, address: u16) -> Option<u16> {
+        self.labels
+            .values()
+            .filter(|candidate| **candidate <= address)
+            .max()
+            .copied()
+    }
+
+    /// An address for the user: with its labels, or with the offset from the enclosing label, like `0006 :loop+2`.
+    pub fn describe(&self, address: u16) -> String {
+        let labels = self.labels_at(address);
+        if !labels.is_empty() {
+            return format!("{:0>4x} {}", address, labels.join(" "));
+        }
+        match self.enclosing_label(address) {
+            Some(label) => format!(
+                "{:0>4x} {}+{:x}",
+                address,
+                self.labels_at(label).join(" "),
+                address - label
+            ),
+            None => format!("{:0>4x}", address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assemble::assemble::assemble_with_symbols;
+
+    #[test]
+    fn test_labels() -> eyre::Result<()> {
+        let (_, symbols) = assemble_with_symbols(include_str!("../../../examples/fib.as"))?;
+
+        assert_eq!(symbols.address(":loop"), Some(0x04));
+        assert_eq!(symbols.address(".halt"), Some(0x0e));
+        assert_eq!(symbols.address(".loop"), None);
+        assert_eq!(symbols.labels_at(0x0e), vec![".halt"]);
+        assert!(symbols.labels_at(0x05).is_empty());
+        assert_eq!(symbols.enclosing_label(0x05), Some(0x04));
+        assert_eq!(symbols.enclosing_label(0x0e), Some(0x0e));
+        assert_eq!(symbols.enclosing_label(0x02), None);
+        assert_eq!(symbols.describe(0x04), "0004 :loop");
+        assert_eq!(symbols.describe(0x06), "0006 :loop+2");
+        assert_eq!(symbols.describe(0x02), "0002");
+
+        assert_eq!(symbols.lines().len(), 10);
+        assert_eq!(symbols.lines()[0], (0x00, 3));
+        assert_eq!(symbols.lines()[2], (0x04, 7));
+        assert_eq!(symbols.lines()[9], (0x0e, 17));
+
+        Ok(())
+    }
+}
@@ -1,32 +1,117 @@
+use crate::circuit::circuit::{HIGH_ROM, LOW_ROM};
+use crate::circuit::contents::{self, Target};
+use crate::constants::control_word::ControlWord;
 use crate::constants::flag::Flags;
 use crate::constants::machine_instruction::{steps, MachineInstruction};
+use crate::emulate::microcode::Microcode;
+use eyre::bail;
 use std::fs;
 
-pub fn burn() -> eyre::Result<()> {
+const ROM_ADDRESSES: std::ops::RangeInclusive<usize> = 0..=0b11_11_11_11_11_11_11_11;
+
+/// Decode a ROM address into the instruction, step and flags it is selected by, and the word stored there.
+fn rom_word(rom_address: usize) -> (MachineInstruction, usize, u8, ControlWord) {
+    let instruction_value = (rom_address & 0b11_11_11_11) as u8;
+    let step = (rom_address >> 8) & 0b11_11;
+    let flags = ((rom_address >> 12) & 0b11_11) as u8;
+
+    let instruction = MachineInstruction::from(instruction_value);
+    let steps = steps(instruction, Flags::from(flags));
+
+    (instruction, step, flags, steps[step])
+}
+
+/// Check every word of the ROMs against the hardware rules declared next to `ControlLine`.
+pub fn verify() -> eyre::Result<()> {
+    let mut violations = Vec::new();
+
+    for rom_address in ROM_ADDRESSES {
+        let (instruction, step, flags, control_word) = rom_word(rom_address);
+        for violation in control_word.violations() {
+            violations.push(format!(
+                "{:0>4x}: {:?} step {} flags {:0>4b}: {}",
+                rom_address, instruction, step, flags, violation
+            ));
+        }
+    }
+
+    if !violations.is_empty() {
+        bail!("{} illegal control words in the microcode:\n{}", violations.len(), violations.join("\n"));
+    }
+
+    Ok(())
+}
+
+/// The content of `rom01.img` and `rom02.img`, in the Logisim image format: the low and the high 32 bits of every
+/// control word.
+pub fn images() -> eyre::Result<(String, String)> {
+    verify()?;
+
     let mut rom1 = String::with_capacity(580_000);
     let mut rom2 = String::with_capacity(580_000);
 
     rom1 += "v3.0 hex bytes plain big-endian\n";
     rom2 += "v3.0 hex bytes plain big-endian\n";
 
-    for rom_address in 0..=0b11_11_11_11_11_11_11_11 {
-        let instruction_value = (rom_address & 0b11_11_11_11) as u8;
-        let step = (rom_address >> 8) & 0b11_11;
-        let flags = ((rom_address >> 12) & 0b11_11) as u8;
-
-        let instruction = MachineInstruction::from(instruction_value);
-        let steps = steps(instruction, Flags::from(flags));
-        let control_word = steps[step];
-
-        let low_bits = control_word.value() & 0xFF_FF_FF_FF;
-        let high_bits = (control_word.value() & 0xFF_FF_FF_FF_00_00_00_00) >> 32;
-
+    let (low_bits, high_bits) = Microcode::from_steps().rom_words();
+    for (low_bits, high_bits) in low_bits.into_iter().zip(high_bits) {
         rom1 += &format!("{:0>8x}\n", low_bits);
         rom2 += &format!("{:0>8x}\n", high_bits);
     }
 
+    Ok((rom1, rom2))
+}
+
+/// `helper burn`: write `rom01.img` and `rom02.img`. With `--circuit <file.circ>`, rewrite the contents of the control
+/// ROMs in the circuit instead, found by `--low-rom` and `--high-rom` (a label or `(x,y)`) if they moved.
+pub fn burn(args: &[String]) -> eyre::Result<()> {
+    if !args.is_empty() {
+        return burn_circuit(args);
+    }
+
+    let (rom1, rom2) = images()?;
+
     fs::write("rom01.img", rom1).unwrap();
     fs::write("rom02.img", rom2).unwrap();
 
     Ok(())
 }
+
+fn burn_circuit(args: &[String]) -> eyre::Result<()> {
+    let mut circuit_file = None;
+    let mut low_rom = Target::Location(LOW_ROM);
+    let mut high_rom = Target::Location(HIGH_ROM);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(value) = args.next() else {
+            bail!("{} requires a value", arg);
+        };
+        match arg.as_str() {
+            "--circuit" => circuit_file = Some(value.to_string()),
+            "--low-rom" => low_rom = Target::parse(value)?,
+            "--high-rom" => high_rom = Target::parse(value)?,
+            other => bail!("Unknown option: {}", other),
+        }
+    }
+    let Some(circuit_file) = circuit_file else {
+        bail!("Usage: burn [--circuit <file.circ> [--low-rom <label or (x,y)>] [--high-rom <label or (x,y)>]]");
+    };
+
+    verify()?;
+    let (low_bits, high_bits) = Microcode::from_steps().rom_words();
+    let words = low_bits.len();
+    contents::inject_file(&circuit_file, &[("ROM", low_rom, low_bits), ("ROM", high_rom, high_bits)])?;
+    println!("Burned {} control words into {}", words, circuit_file);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_microcode_is_legal() -> eyre::Result<()> {
+        verify()
+    }
+}
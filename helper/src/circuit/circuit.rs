@@ -0,0 +1,251 @@
+use crate::circuit::xml;
+use eyre::{bail, eyre, WrapErr};
+use std::fmt;
+use std::ops::Range;
+
+pub const DEFAULT_CIRCUIT: &str = "../schema.circ";
+
+/// Where `schema.circ` has the control ROM with the low 32 bits of the control word, the one with the high bits, and
+/// the RAM.
+pub const LOW_ROM: Location = Location { x: 2420, y: 770 };
+pub const HIGH_ROM: Location = Location { x: 2420, y: 1760 };
+pub const RAM: Location = Location { x: 980, y: 1180 };
+
+/// A point of the Logisim grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+/// A component of the circuit, with the attributes saved in the file: the missing ones have their default value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Component {
+    pub name: String,
+    pub location: Location,
+    pub attributes: Vec<Attribute>,
+    /// Where the attributes are in the file, to add one.
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribute {
+    pub name: String,
+    pub value: String,
+    /// Where the value is in the file, to rewrite it in place.
+    pub span: Range<usize>,
+}
+
+/// The main circuit of a Logisim file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Circuit {
+    pub name: String,
+    pub components: Vec<Component>,
+    pub wires: Vec<(Location, Location)>,
+}
+
+impl Location {
+    pub fn new(x: i32, y: i32) -> Self {
+        Location { x, y }
+    }
+
+    pub fn translate(&self, dx: i32, dy: i32) -> Self {
+        Location::new(self.x + dx, self.y + dy)
+    }
+
+    /// `(x,y)`, as in the file.
+    pub fn parse(input: &str) -> eyre::Result<Self> {
+        let coordinates = input
+            .trim()
+            .strip_prefix('(')
+            .and_then(|input| input.strip_suffix(')'))
+            .and_then(|input| input.split_once(','));
+        let Some((x, y)) = coordinates else {
+            bail!("Invalid location: {}", input);
+        };
+        Ok(Location::new(
+            x.trim()
+                .parse()
+                .wrap_err_with(|| format!("Invalid location: {}", input))?,
+            y.trim()
+                .parse()
+                .wrap_err_with(|| format!("Invalid location: {}", input))?,
+        ))
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({},{})", self.x, self.y)
+    }
+}
+
+impl Direction {
+    pub fn parse(input: &str) -> eyre::Result<Self> {
+        match input {
+            "north" => Ok(Direction::North),
+            "east" => Ok(Direction::East),
+            "south" => Ok(Direction::South),
+            "west" => Ok(Direction::West),
+            _ => bail!("Invalid direction: {}", input),
+        }
+    }
+}
+
+impl Component {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+            .map(|attribute| attribute.value.as_str())
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.attribute("label").filter(|label| !label.is_empty())
+    }
+
+    /// Every component of the circuit faces east unless told otherwise.
+    pub fn facing(&self) -> eyre::Result<Direction> {
+        self.attribute("facing").map_or(Ok(Direction::East), Direction::parse)
+    }
+
+    pub fn boolean(&self, name: &str, default: bool) -> eyre::Result<bool> {
+        match self.attribute(name) {
+            None => Ok(default),
+            Some("true") => Ok(true),
+            Some("false") => Ok(false),
+            Some(value) => bail!("{}: invalid {}: {}", self, name, value),
+        }
+    }
+
+    /// A number attribute, decimal or hexadecimal with `0x` like Logisim saves them.
+    pub fn number(&self, name: &str, default: u64) -> eyre::Result<u64> {
+        let Some(value) = self.attribute(name) else {
+            return Ok(default);
+        };
+        let result = match value.strip_prefix("0x") {
+            Some(digits) => u64::from_str_radix(digits, 16),
+            None => value.parse(),
+        };
+        result.map_err(|_| eyre!("{}: invalid {}: {}", self, name, value))
+    }
+
+    pub fn width(&self, name: &str, default: usize) -> eyre::Result<usize> {
+        let width = self.number(name, default as u64)? as usize;
+        if !(1..=64).contains(&width) {
+            bail!("{}: {} {} out of range", self, name, width);
+        }
+        Ok(width)
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.name, self.location)?;
+        if let Some(label) = self.label() {
+            write!(f, " ({})", label)?;
+        }
+        Ok(())
+    }
+}
+
+impl Circuit {
+    /// Read the main circuit of a `.circ` file.
+    pub fn parse(source: &str) -> eyre::Result<Self> {
+        let project = xml::parse(source)?;
+        if project.name != "project" {
+            bail!("Not a Logisim file: the root element is <{}>", project.name);
+        }
+        let main = project
+            .children("main")
+            .next()
+            .and_then(|main| main.attribute("name"))
+            .unwrap_or("main");
+        let Some(circuit) = project
+            .children("circuit")
+            .find(|circuit| circuit.attribute("name") == Some(main))
+        else {
+            bail!("No circuit named {}", main);
+        };
+
+        let mut components = Vec::new();
+        for comp in circuit.children("comp") {
+            let name = comp.attribute("name").unwrap_or_default().to_string();
+            let location = Location::parse(comp.attribute("loc").unwrap_or_default())
+                .wrap_err_with(|| format!("Component {}", name))?;
+            let attributes = comp
+                .children("a")
+                .map(|attribute| {
+                    // Long values, like the content of memories, are saved as text instead of `val`.
+                    let value = attribute
+                        .attribute("val")
+                        .map_or(attribute.text.clone(), str::to_string);
+                    Attribute {
+                        name: attribute.attribute("name").unwrap_or_default().to_string(),
+                        value,
+                        span: attribute.content.clone(),
+                    }
+                })
+                .collect();
+            components.push(Component {
+                name,
+                location,
+                attributes,
+                span: comp.content.clone(),
+            });
+        }
+
+        let mut wires = Vec::new();
+        for wire in circuit.children("wire") {
+            let from = Location::parse(wire.attribute("from").unwrap_or_default())?;
+            let to = Location::parse(wire.attribute("to").unwrap_or_default())?;
+            wires.push((from, to));
+        }
+
+        Ok(Circuit {
+            name: main.to_string(),
+            components,
+            wires,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> eyre::Result<()> {
+        let circuit = Circuit::parse(include_str!("../../../schema.circ"))?;
+        assert_eq!(circuit.name, "main");
+        assert!(circuit.wires.len() > 700);
+
+        let rom = circuit
+            .components
+            .iter()
+            .find(|component| component.name == "ROM" && component.location == Location::new(2420, 770))
+            .unwrap();
+        assert_eq!(rom.width("dataWidth", 8)?, 32);
+        assert!(rom.attribute("contents").unwrap().starts_with("addr/data: 16 32\n"));
+        assert_eq!(rom.facing()?, Direction::East);
+
+        let register = circuit
+            .components
+            .iter()
+            .find(|component| component.label() == Some("A"))
+            .unwrap();
+        assert_eq!(register.to_string(), "Register at (1360,390) (A)");
+
+        assert!(Location::parse("(1,x)").is_err());
+
+        Ok(())
+    }
+}
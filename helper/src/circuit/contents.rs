@@ -0,0 +1,245 @@
+use crate::assemble::assemble::assemble_program;
+use crate::circuit::circuit::{Circuit, Component, Location, DEFAULT_CIRCUIT, RAM};
+use eyre::bail;
+use std::fs;
+
+/// How many words Logisim writes on a line of `contents`.
+const WORDS_PER_LINE: usize = 8;
+/// From how many equal words in a row Logisim writes them as `count*word`.
+const MIN_RUN: usize = 4;
+
+/// A memory of the circuit: by the label given to it in Logisim, or by its location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Label(String),
+    Location(Location),
+}
+
+impl Target {
+    /// `(x,y)` is a location, anything else a label.
+    pub fn parse(input: &str) -> eyre::Result<Self> {
+        if input.starts_with('(') {
+            Ok(Target::Location(Location::parse(input)?))
+        } else {
+            Ok(Target::Label(input.to_string()))
+        }
+    }
+
+    /// The `ROM` or `RAM` component it points to.
+    fn find<'a>(&self, circuit: &'a Circuit, name: &str) -> eyre::Result<&'a Component> {
+        let mut matches = circuit.components.iter().filter(|component| {
+            component.name == name
+                && match self {
+                    Target::Label(label) => component.label() == Some(label.as_str()),
+                    Target::Location(location) => component.location == *location,
+                }
+        });
+        let Some(component) = matches.next() else {
+            bail!("No {} {} in the circuit", name, self);
+        };
+        if let Some(other) = matches.next() {
+            bail!("Both {} and {} are the {} {}", component, other, name, self);
+        }
+        Ok(component)
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Label(label) => write!(f, "{}", label),
+            Target::Location(location) => write!(f, "at {}", location),
+        }
+    }
+}
+
+/// The `contents` attribute of a memory the way Logisim saves it: the `addr/data:` header, then the words in
+/// hexadecimal, runs as `count*word`, eight to a line, without the zeros at the end.
+pub fn contents(address_width: usize, data_width: usize, words: &[u64]) -> String {
+    let used = words.iter().rposition(|word| *word != 0).map_or(0, |last| last + 1);
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < used {
+        let word = words[index];
+        let run = words[index..used].iter().take_while(|other| **other == word).count();
+        if run >= MIN_RUN {
+            tokens.push(format!("{}*{:x}", run, word));
+            index += run;
+        } else {
+            tokens.push(format!("{:x}", word));
+            index += 1;
+        }
+    }
+
+    let mut contents = format!("addr/data: {} {}\n", address_width, data_width);
+    for line in tokens.chunks(WORDS_PER_LINE) {
+        contents += &line.join(" ");
+        contents += "\n";
+    }
+    contents
+}
+
+/// Rewrite the `contents` of memories in the source of a `.circ` file, leaving the rest of it as it is: `name` is
+/// `ROM` or `RAM`, and a memory without contents gets the attribute.
+///
+/// Logisim-evolution only keeps the contents of a RAM in the file when its type is non-volatile, while the default
+/// type is volatile: a RAM without a type is made non-volatile, one explicitly volatile is refused.
+pub fn inject(source: &str, memories: &[(&str, Target, Vec<u64>)]) -> eyre::Result<String> {
+    let circuit = Circuit::parse(source)?;
+    let mut edits = Vec::new();
+    for (name, target, words) in memories {
+        let component = target.find(&circuit, name)?;
+        let address_width = component.width("addrWidth", 8)?;
+        let data_width = component.width("dataWidth", 8)?;
+        if words.len() as u128 > 1 << address_width {
+            bail!("{} words do not fit in {}", words.len(), component);
+        }
+        if let Some(word) = words.iter().find(|word| data_width < 64 && **word >> data_width != 0) {
+            bail!("{:x} does not fit in the {} bits of {}", word, data_width, component);
+        }
+
+        // Right before the indentation of `</comp>`, as the last attribute.
+        let end = component.span.end;
+        let last = source[..end].rfind('\n').map_or(end, |newline| newline + 1);
+
+        let contents = contents(address_width, data_width, words);
+        match component
+            .attributes
+            .iter()
+            .find(|attribute| attribute.name == "contents")
+        {
+            Some(attribute) => edits.push((attribute.span.clone(), contents)),
+            None if component.span.is_empty() => bail!("{} has no attributes to add the contents to", component),
+            None => edits.push((last..last, format!("      <a name=\"contents\">{}</a>\n", contents))),
+        }
+        if *name == "RAM" {
+            match component.attribute("type") {
+                Some("nonvolatile") => {}
+                Some(other) => bail!("{} is {}: Logisim would not keep its contents", component, other),
+                None => edits.push((last..last, "      <a name=\"type\" val=\"nonvolatile\"/>\n".to_string())),
+            }
+        }
+    }
+
+    edits.sort_by_key(|(span, _)| span.start);
+    let mut result = String::with_capacity(source.len());
+    let mut position = 0;
+    for (span, text) in edits {
+        if span.start < position {
+            bail!("The same memory is written twice");
+        }
+        result += &source[position..span.start];
+        result += &text;
+        position = span.end;
+    }
+    result += &source[position..];
+    Ok(result)
+}
+
+/// Rewrite the memories of a `.circ` file.
+pub fn inject_file(file: &str, memories: &[(&str, Target, Vec<u64>)]) -> eyre::Result<()> {
+    let source = fs::read_to_string(file)?;
+    fs::write(file, inject(&source, memories)?)?;
+    Ok(())
+}
+
+/// `helper assemble <file.as> --circuit <file.circ> [--ram <label or (x,y)>]`: put the program in the RAM of the
+/// circuit.
+pub fn assemble(args: &[String]) -> eyre::Result<()> {
+    let mut file = None;
+    let mut circuit_file = DEFAULT_CIRCUIT.to_string();
+    let mut ram = Target::Location(RAM);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--circuit" => {
+                let Some(value) = args.next() else {
+                    bail!("--circuit requires a file");
+                };
+                circuit_file = value.to_string();
+            }
+            "--ram" => {
+                let Some(value) = args.next() else {
+                    bail!("--ram requires a label or a location");
+                };
+                ram = Target::parse(value)?;
+            }
+            other if other.starts_with("--") => bail!("Unknown option: {}", other),
+            other if file.is_none() => file = Some(other.to_string()),
+            other => bail!("Unexpected argument: {}", other),
+        }
+    }
+    let Some(file) = file else {
+        bail!("Usage: assemble <file.as> --circuit <file.circ> [--ram <label or (x,y)>]");
+    };
+
+    let source: &'static str = String::from_utf8(fs::read(&file)?)?.leak();
+    let program = assemble_program(source)?;
+    let bytes = program.0.iter().map(|byte| *byte as u64).collect();
+    inject_file(&circuit_file, &[("RAM", ram.clone(), bytes)])?;
+    println!(
+        "Wrote {} bytes into the RAM {} of {}",
+        program.0.len(),
+        ram,
+        circuit_file
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::circuit::{HIGH_ROM, LOW_ROM};
+    use crate::emulate::microcode::{parse_image, Microcode};
+
+    #[test]
+    fn test_contents() {
+        assert_eq!(contents(4, 8, &[]), "addr/data: 4 8\n");
+        assert_eq!(
+            contents(4, 8, &[1, 1, 1, 1, 2, 2, 0, 0, 0, 0, 3, 4, 5, 6, 7, 8, 9, 0, 0]),
+            "addr/data: 4 8\n4*1 2 2 4*0 3 4 5 6\n7 8 9\n"
+        );
+    }
+
+    #[test]
+    fn test_inject() -> eyre::Result<()> {
+        let source = include_str!("../../../schema.circ");
+        let (low, high) = Microcode::from_steps().rom_words();
+        let program = vec![0x12, 0x34, 0x34, 0x34, 0x34];
+        let injected = inject(
+            source,
+            &[
+                ("ROM", Target::Location(LOW_ROM), low.clone()),
+                ("ROM", Target::parse("(2420,1760)")?, high.clone()),
+                ("RAM", Target::Location(RAM), program.clone()),
+            ],
+        )?;
+
+        let circuit = Circuit::parse(&injected)?;
+        let image = |name: &str, location: Location| -> eyre::Result<Vec<u64>> {
+            let component = Target::Location(location).find(&circuit, name)?;
+            let mut words: Vec<u64> = parse_image(component.attribute("contents").unwrap())?
+                .into_iter()
+                .map(u64::from)
+                .collect();
+            words.resize(1 << component.width("addrWidth", 8)?, 0);
+            Ok(words)
+        };
+        assert_eq!(image("ROM", LOW_ROM)?, low);
+        assert_eq!(image("ROM", HIGH_ROM)?, high);
+        assert_eq!(image("RAM", RAM)?[..6], [0x12, 0x34, 0x34, 0x34, 0x34, 0]);
+        assert_eq!(Target::Location(RAM).find(&circuit, "RAM")?.attribute("type"), Some("nonvolatile"));
+        // Only the contents changed: the rest of the file is the same, and injecting again changes nothing.
+        assert_eq!(inject(&injected, &[("RAM", Target::Location(RAM), program.clone())])?, injected);
+        assert!(injected.contains(
+            "      <a name=\"contents\">addr/data: 16 8\n12 4*34\n</a>\n      <a name=\"type\" val=\"nonvolatile\"/>\n    </comp>"
+        ));
+        let volatile = injected.replace("val=\"nonvolatile\"", "val=\"volatile\"");
+        assert!(inject(&volatile, &[("RAM", Target::Location(RAM), program)]).is_err());
+
+        assert!(inject(source, &[("RAM", Target::parse("Nowhere")?, vec![])]).is_err());
+        assert!(inject(source, &[("RAM", Target::Location(RAM), vec![0x100])]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use crate::assemble::assemble::assemble_program;
+use crate::circuit::circuit::{Circuit, Location, DEFAULT_CIRCUIT, HIGH_ROM, LOW_ROM, RAM};
+use crate::circuit::simulation::Simulation;
+use crate::constants::control_line::ControlLine;
+use crate::constants::machine_instruction::MachineInstruction;
+use crate::emulate::microcode::Microcode;
+use crate::emulate::microcode_emulator::MicrocodeEmulator;
+use eyre::bail;
+use std::fs;
+use std::sync::Arc;
+
+pub const RESET_BUTTON: Location = Location { x: 4010, y: 390 };
+
+const DEFAULT_MAX_CYCLES: u64 = 1_000_000;
+/// The devices are not in the circuit: from here on the RAM of the emulator is not the one of the circuit.
+const DEVICES: usize = 0xFF00;
+
+type Register = fn(&MicrocodeEmulator) -> u64;
+
+/// The registers the circuit shows on its tunnels, and where the emulator keeps them.
+const SIGNALS: [(&str, Register); 17] = [
+    ("Register_A", |emulator| emulator.state.registers[0] as u64),
+    ("Register_B", |emulator| emulator.state.registers[1] as u64),
+    ("Register_C", |emulator| emulator.state.registers[2] as u64),
+    ("Register_D", |emulator| emulator.state.registers[3] as u64),
+    ("Register_ALU_1", |emulator| emulator.state.alu1 as u64),
+    ("Register_ALU_2", |emulator| emulator.state.alu2 as u64),
+    ("Instruction_Pointer", |emulator| {
+        emulator.state.instruction_pointer as u64
+    }),
+    ("Stack_Pointer_High", |emulator| {
+        (emulator.state.stack_pointer >> 8) as u64
+    }),
+    ("Stack_Pointer_Low", |emulator| {
+        (emulator.state.stack_pointer & 0xFF) as u64
+    }),
+    ("Jump_Register_High", |emulator| {
+        (emulator.state.jump_register >> 8) as u64
+    }),
+    ("Jump_Register_Low", |emulator| {
+        (emulator.state.jump_register & 0xFF) as u64
+    }),
+    ("RET_Register_High", |emulator| {
+        (emulator.state.return_register >> 8) as u64
+    }),
+    ("RET_Register_Low", |emulator| {
+        (emulator.state.return_register & 0xFF) as u64
+    }),
+    ("Memory_Register_High", |emulator| {
+        (emulator.state.memory_register >> 8) as u64
+    }),
+    ("Memory_Register_Low", |emulator| {
+        (emulator.state.memory_register & 0xFF) as u64
+    }),
+    ("Instruction", |emulator| emulator.instruction_register as u64),
+    ("Micro_Counter", |emulator| emulator.step as u64),
+];
+
+/// Runs a program on the gate level simulation of the circuit and on the microcode emulator, one clock cycle at a
+/// time, comparing the bus and every register after each of them: where they part is either a bug of the emulator or
+/// a wire of the circuit that does not do what the microcode expects.
+pub struct CrossCheck {
+    pub simulation: Simulation,
+    pub emulator: MicrocodeEmulator,
+}
+
+/// Where the circuit and the emulator parted.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub cycle: u64,
+    /// The instruction being executed: its address, opcode and step.
+    pub instruction: (u16, u8, u8),
+    pub differences: Vec<String>,
+}
+
+impl CrossCheck {
+    /// Burn the microcode into the control ROMs of the circuit and load the program into its RAM.
+    pub fn new(circuit: &Circuit, microcode: Arc<Microcode>, program: &[u8]) -> eyre::Result<Self> {
+        let mut simulation = Simulation::new(circuit)?;
+        let (low, high) = microcode.rom_words();
+        simulation.load_rom(LOW_ROM, &low)?;
+        simulation.load_rom(HIGH_ROM, &high)?;
+        simulation.load_ram(RAM, program)?;
+        // Until then the flags are unknown, and so is the control word they select: pressing reset forces them.
+        simulation.press(RESET_BUTTON, true)?;
+        simulation.press(RESET_BUTTON, false)?;
+
+        let mut emulator = MicrocodeEmulator::new(microcode, program)?;
+        emulator.clocks = Some(Vec::new());
+        Ok(CrossCheck { simulation, emulator })
+    }
+
+    /// Run a clock cycle on both: what differs after it, if anything.
+    pub fn clock(&mut self) -> eyre::Result<Option<Divergence>> {
+        let instruction = (
+            self.emulator.state.instruction_pointer,
+            self.emulator.instruction_register,
+            self.emulator.step,
+        );
+        // The circuit shows what the cycle does before its clock edge.
+        let bus = self.simulation.label("Bus")?;
+        let halt = self.simulation.label("Halt")?;
+        let control_word = self.emulator.control_word()?;
+        let device = (control_word.has(ControlLine::MO) || control_word.has(ControlLine::MI))
+            && control_word.has(ControlLine::MIS)
+            && self.emulator.state.memory_register as usize >= DEVICES;
+        let memory_register = self.emulator.state.memory_register;
+
+        self.emulator.clock()?;
+        let clock = self.emulator.clocks.as_mut().and_then(|clocks| clocks.pop());
+        let Some(clock) = clock else {
+            bail!("the emulator did not record the clock cycle");
+        };
+        self.simulation.tick()?;
+
+        let mut differences = Vec::new();
+        let mut compare = |name: &str, circuit: Option<u64>, emulator: u64| {
+            if circuit != Some(emulator) {
+                let circuit = circuit.map_or("undefined".to_string(), |value| format!("{:x}", value));
+                differences.push(format!("{}: circuit {}, emulator {:x}", name, circuit, emulator));
+            }
+        };
+        compare("Bus", bus, clock.bus as u64);
+        compare("Halt", halt, clock.control_word.has(ControlLine::HLT) as u64);
+        for (name, value) in SIGNALS {
+            compare(name, self.simulation.label(name)?, value(&self.emulator));
+        }
+        if self.emulator.halted {
+            let circuit = &self.simulation.ram(RAM)?[..DEVICES];
+            let emulator = &self.emulator.state.memory.ram()[..DEVICES];
+            for (address, (circuit, emulator)) in circuit.iter().zip(emulator).enumerate() {
+                if circuit != emulator {
+                    differences.push(format!(
+                        "mem[{:0>4x}]: circuit {:0>2x}, emulator {:0>2x}",
+                        address, circuit, emulator
+                    ));
+                }
+            }
+        }
+
+        if device && !differences.is_empty() {
+            differences.push(format!(
+                "the program accessed the device at {:0>4x}, which is not in the circuit",
+                memory_register
+            ));
+        }
+
+        Ok((!differences.is_empty()).then_some(Divergence {
+            cycle: self.emulator.cycles,
+            instruction,
+            differences,
+        }))
+    }
+}
+
+/// `helper circuit <file.as>`: returns whether the circuit and the emulator agreed until the program halted.
+pub fn circuit(args: &[String]) -> eyre::Result<bool> {
+    let mut file = None;
+    let mut circuit_file = DEFAULT_CIRCUIT.to_string();
+    let mut max_cycles = DEFAULT_MAX_CYCLES;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--circuit" => {
+                let Some(value) = args.next() else {
+                    bail!("--circuit requires a file");
+                };
+                circuit_file = value.to_string();
+            }
+            "--max-cycles" => {
+                let Some(value) = args.next() else {
+                    bail!("--max-cycles requires a value");
+                };
+                max_cycles = value.parse()?;
+            }
+            other if other.starts_with("--") => bail!("Unknown option: {}", other),
+            other if file.is_none() => file = Some(other.to_string()),
+            other => bail!("Unexpected argument: {}", other),
+        }
+    }
+    let Some(file) = file else {
+        bail!("Usage: circuit <file.as> [--circuit <file.circ>] [--max-cycles <n>]");
+    };
+
+    let source: &'static str = String::from_utf8(fs::read(&file)?)?.leak();
+    let program = assemble_program(source)?;
+    let circuit = Circuit::parse(&fs::read_to_string(&circuit_file)?)?;
+    let mut cross_check = CrossCheck::new(&circuit, Arc::new(Microcode::from_steps()), &program.0)?;
+
+    while !cross_check.emulator.halted {
+        if cross_check.emulator.cycles >= max_cycles {
+            bail!("The program did not halt within {} cycles", max_cycles);
+        }
+        if let Some(divergence) = cross_check.clock()? {
+            let (address, opcode, step) = divergence.instruction;
+            println!(
+                "The circuit and the emulator differ after cycle {}, {:0>4x} {:0>2x} {:?} step {}:",
+                divergence.cycle,
+                address,
+                opcode,
+                MachineInstruction::from(opcode),
+                step
+            );
+            for difference in divergence.differences {
+                println!("    {}", difference);
+            }
+            return Ok(false);
+        }
+    }
+
+    println!(
+        "The circuit and the emulator agree for {} cycles, until {}",
+        cross_check.emulator.cycles,
+        if cross_check.emulator.state.memory.host.exit.is_some() {
+            "the program exited"
+        } else {
+            "HLT"
+        }
+    );
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cross_check(source: &'static str) -> eyre::Result<CrossCheck> {
+        let circuit = Circuit::parse(include_str!("../../../schema.circ"))?;
+        let program = assemble_program(source)?;
+        CrossCheck::new(&circuit, Arc::new(Microcode::from_steps()), &program.0)
+    }
+
+    #[test]
+    fn test_fib() -> eyre::Result<()> {
+        let mut cross_check = cross_check(include_str!("../../../examples/fib.as"))?;
+        while !cross_check.emulator.halted {
+            assert!(cross_check.emulator.cycles < 100_000);
+            assert_eq!(cross_check.clock()?, None);
+        }
+        assert_eq!(cross_check.simulation.label("Register_A")?, Some(0xe9));
+        assert_eq!(cross_check.simulation.label("Halt")?, Some(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_divergence() -> eyre::Result<()> {
+        let mut cross_check = cross_check("LI A, 0x12\nHLT")?;
+        cross_check.emulator.state.registers[1] = 0x34;
+        let divergence = cross_check.clock()?.unwrap();
+        assert_eq!(divergence.cycle, 1);
+        assert_eq!(divergence.instruction, (0, 0, 0));
+        assert_eq!(divergence.differences, vec!["Register_B: circuit 0, emulator 34"]);
+
+        Ok(())
+    }
+}
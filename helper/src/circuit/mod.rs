@@ -0,0 +1,9 @@
+#[allow(clippy::module_inception)]
+pub mod circuit;
+pub mod contents;
+pub mod cross_check;
+pub mod netlist;
+pub mod part;
+pub mod simulation;
+pub mod wiring;
+pub mod xml;
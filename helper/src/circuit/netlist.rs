@@ -0,0 +1,251 @@
+use crate::circuit::circuit::{Circuit, Component, Location};
+use crate::circuit::part::{Kind, Part};
+use eyre::bail;
+use std::collections::HashMap;
+
+/// The circuit reduced to bits: every wire, tunnel and splitter is gone, each port is the list of the bits it is
+/// connected to, from the least significant.
+pub struct Netlist {
+    pub components: Vec<Component>,
+    pub parts: Vec<Part>,
+    /// For every part, for every port, its bits.
+    pub connections: Vec<Vec<Vec<usize>>>,
+    /// How many distinct bits there are.
+    pub bits: usize,
+    /// The bits of every tunnel label.
+    pub labels: HashMap<String, Vec<usize>>,
+    /// A name for every bit, for the error messages: a tunnel label when the bit has one.
+    pub names: Vec<String>,
+}
+
+/// Union-find over integers.
+struct Sets(Vec<usize>);
+
+impl Sets {
+    fn add(&mut self) -> usize {
+        self.0.push(self.0.len());
+        self.0.len() - 1
+    }
+
+    fn find(&mut self, mut item: usize) -> usize {
+        while self.0[item] != item {
+            self.0[item] = self.0[self.0[item]];
+            item = self.0[item];
+        }
+        item
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.0[a] = b;
+    }
+}
+
+impl Netlist {
+    pub fn new(circuit: &Circuit) -> eyre::Result<Self> {
+        let parts = circuit
+            .components
+            .iter()
+            .map(Part::new)
+            .collect::<eyre::Result<Vec<Part>>>()?;
+
+        // The nets: points joined by wires, and by tunnels with the same label.
+        let mut points: HashMap<Location, usize> = HashMap::new();
+        let mut nets = Sets(Vec::new());
+        let mut point = |location: Location, nets: &mut Sets| *points.entry(location).or_insert_with(|| nets.add());
+        for (from, to) in &circuit.wires {
+            let (from, to) = (point(*from, &mut nets), point(*to, &mut nets));
+            nets.union(from, to);
+        }
+        let mut tunnels: HashMap<&str, usize> = HashMap::new();
+        let mut port_nets = Vec::new();
+        for part in &parts {
+            let net: Vec<usize> = part.ports.iter().map(|port| point(port.location, &mut nets)).collect();
+            // Like in Logisim, the tunnels without a label connect to nothing.
+            match &part.kind {
+                Kind::Tunnel(label) if !label.is_empty() => {
+                    let first = *tunnels.entry(label).or_insert(net[0]);
+                    nets.union(first, net[0]);
+                }
+                _ => {}
+            }
+            port_nets.push(net);
+        }
+        let port_nets: Vec<Vec<usize>> = port_nets
+            .into_iter()
+            .map(|net| net.into_iter().map(|net| nets.find(net)).collect())
+            .collect();
+
+        // Every net is as wide as the ports on it, and they all have to agree.
+        let mut widths: HashMap<usize, (usize, usize)> = HashMap::new();
+        for (index, part) in parts.iter().enumerate() {
+            for (port, net) in part.ports.iter().zip(&port_nets[index]) {
+                let Some(width) = port.width else {
+                    continue;
+                };
+                match widths.get(net) {
+                    Some((other, component)) if *other != width => bail!(
+                        "Incompatible widths at {}: {} bits from {}, {} bits from {}",
+                        port.location,
+                        width,
+                        circuit.components[index],
+                        other,
+                        circuit.components[*component]
+                    ),
+                    Some(_) => {}
+                    None => {
+                        widths.insert(*net, (width, index));
+                    }
+                }
+            }
+        }
+        let mut first_bits: HashMap<usize, usize> = HashMap::new();
+        let mut bits = Sets(Vec::new());
+        let mut net_bits = |net: usize, bits: &mut Sets| -> Vec<usize> {
+            let width = widths.get(&net).map_or(1, |(width, _)| *width);
+            let first = *first_bits.entry(net).or_insert_with(|| {
+                let first = bits.0.len();
+                for _ in 0..width {
+                    bits.add();
+                }
+                first
+            });
+            (first..first + width).collect()
+        };
+        let mut connections: Vec<Vec<Vec<usize>>> = port_nets
+            .iter()
+            .map(|nets| nets.iter().map(|net| net_bits(*net, &mut bits)).collect())
+            .collect();
+
+        // Splitters join the bits of their ends to the bits of the combined end, both ways.
+        for (index, part) in parts.iter().enumerate() {
+            let Kind::Splitter { bits: ends } = &part.kind else {
+                continue;
+            };
+            let mut taken = vec![0; part.ports.len()];
+            for (bit, end) in ends.iter().enumerate() {
+                let Some(end) = end else {
+                    continue;
+                };
+                let combined = connections[index][0][bit];
+                let other = connections[index][end + 1][taken[end + 1]];
+                taken[end + 1] += 1;
+                bits.union(combined, other);
+            }
+        }
+
+        // Number the bits that are left.
+        let mut numbers: HashMap<usize, usize> = HashMap::new();
+        let mut roots = Vec::with_capacity(bits.0.len());
+        for bit in 0..bits.0.len() {
+            let root = bits.find(bit);
+            let count = numbers.len();
+            roots.push(*numbers.entry(root).or_insert(count));
+        }
+        for ports in &mut connections {
+            for port in ports.iter_mut() {
+                for bit in port.iter_mut() {
+                    *bit = roots[*bit];
+                }
+            }
+        }
+
+        let mut labels = HashMap::new();
+        let mut names = vec![String::new(); numbers.len()];
+        for (index, part) in parts.iter().enumerate() {
+            let port = &connections[index];
+            let name = match &part.kind {
+                Kind::Tunnel(label) if !label.is_empty() => {
+                    labels.insert(label.clone(), port[0].clone());
+                    label.clone()
+                }
+                _ => continue,
+            };
+            for (position, bit) in port[0].iter().enumerate() {
+                if names[*bit].is_empty() {
+                    names[*bit] = if port[0].len() == 1 {
+                        name.clone()
+                    } else {
+                        format!("{}[{}]", name, position)
+                    };
+                }
+            }
+        }
+        for (index, ports) in connections.iter().enumerate() {
+            for (port, bits) in ports.iter().enumerate() {
+                for (position, bit) in bits.iter().enumerate() {
+                    if names[*bit].is_empty() {
+                        names[*bit] = format!(
+                            "{}[{}] of {}",
+                            parts[index].ports[port].location, position, circuit.components[index]
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(Netlist {
+            components: circuit.components.clone(),
+            parts,
+            connections,
+            bits: numbers.len(),
+            labels,
+            names,
+        })
+    }
+
+    /// The bits of a tunnel label.
+    pub fn label(&self, label: &str) -> eyre::Result<&[usize]> {
+        self.labels
+            .get(label)
+            .map(Vec::as_slice)
+            .ok_or_else(|| eyre::eyre!("No tunnel labelled {}", label))
+    }
+
+    /// The index of the component at the location.
+    pub fn component_at(&self, name: &str, location: Location) -> eyre::Result<usize> {
+        self.components
+            .iter()
+            .position(|component| component.name == name && component.location == location)
+            .ok_or_else(|| eyre::eyre!("No {} at {}", name, location))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema() -> eyre::Result<()> {
+        let netlist = Netlist::new(&Circuit::parse(include_str!("../../../schema.circ"))?)?;
+        assert_eq!(netlist.label("Instruction_Pointer")?.len(), 16);
+        assert_eq!(netlist.label("Bus")?.len(), 8);
+
+        // The splitters take the instruction pointer apart for the bus, and put it back together for the RAM.
+        let counter = netlist.component_at("Counter", Location::new(1130, 730))?;
+        assert_eq!(netlist.connections[counter][0], netlist.label("Instruction_Pointer")?);
+        let ram = netlist.component_at("RAM", Location::new(980, 1180))?;
+        assert_eq!(netlist.connections[ram][5], netlist.label("Bus")?);
+        assert_eq!(netlist.connections[ram][1], netlist.label("Memory_In")?);
+
+        // The control ROM drives the lines through its splitter.
+        let rom = netlist.component_at("ROM", Location::new(2420, 770))?;
+        assert_eq!(netlist.connections[rom][1][8], netlist.label("Halt")?[0]);
+        assert_eq!(netlist.names[netlist.label("Halt")?[0]], "Halt");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_widths() -> eyre::Result<()> {
+        let source = "<project><circuit name=\"main\">\
+            <comp lib=\"0\" loc=\"(0,0)\" name=\"Tunnel\"><a name=\"label\" val=\"x\"/><a name=\"width\" val=\"8\"/></comp>\
+            <comp lib=\"0\" loc=\"(100,0)\" name=\"Tunnel\"><a name=\"label\" val=\"x\"/></comp>\
+            </circuit></project>";
+        let error = Netlist::new(&Circuit::parse(source)?).err().unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("Incompatible widths at (100,0): 1 bits from Tunnel"));
+        Ok(())
+    }
+}
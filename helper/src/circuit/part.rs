@@ -0,0 +1,712 @@
+use crate::circuit::circuit::{Component, Direction, Location};
+use eyre::bail;
+
+/// A pin of a component: where wires connect to it and how many bits it carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Port {
+    pub location: Location,
+    /// `None` when the port takes the width of whatever it is connected to, like a probe.
+    pub width: Option<usize>,
+    pub output: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GateFunction {
+    And,
+    Or,
+    Xor,
+}
+
+/// What a component does, with the attributes that matter to the simulation. The ports of each kind come in the order
+/// given next to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// Output, then the inputs.
+    Gate {
+        function: GateFunction,
+        negated_inputs: Vec<bool>,
+        negated_output: bool,
+    },
+    /// Output, input.
+    Not,
+    /// Output, input, control.
+    ControlledBuffer,
+    /// Output, select, then the inputs.
+    Multiplexer,
+    /// Input, select, then the outputs, and the enable last if there is one.
+    Demultiplexer { enable: bool },
+    /// Select, then the outputs, and the enable last if there is one.
+    Decoder { enable: bool },
+    /// Output, input, enable, clock, clear.
+    Register,
+    /// Output, input, load, count, clock, clear.
+    Counter { max: u64 },
+    /// Output, A, B, carry in, carry out.
+    Adder,
+    /// Output, A, B, borrow in, borrow out.
+    Subtractor,
+    /// Address, data.
+    Rom,
+    /// Address, write enable, output enable, clock, data in, data out.
+    Ram,
+    /// Output.
+    Constant(u64),
+    /// Output.
+    Clock,
+    /// Output: 1 while pressed.
+    Button,
+    /// The value a floating net is pulled to, `None` for the error value.
+    PullResistor(Option<bool>),
+    /// Joins the nets of the tunnels with the same label.
+    Tunnel(String),
+    /// Shows a net, does nothing.
+    Probe,
+    /// The combined end, then the others: `bits[i]` is the end bit `i` of the combined end goes to.
+    Splitter { bits: Vec<Option<usize>> },
+}
+
+/// A component with its ports laid out like Logisim does: only the components, appearances and orientations the
+/// schematic uses are supported, anything else is an error rather than a guess.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub kind: Kind,
+    pub ports: Vec<Port>,
+}
+
+impl Part {
+    pub fn new(component: &Component) -> eyre::Result<Self> {
+        let location = component.location;
+        let facing = component.facing()?;
+        let port = |dx: i32, dy: i32, width: usize, output: bool| Port {
+            location: location.translate(dx, dy),
+            width: Some(width),
+            output,
+        };
+        let unsupported = |what: &str| -> eyre::Result<Self> { bail!("{}: unsupported {}", component, what) };
+
+        let part = match component.name.as_str() {
+            "AND Gate" | "OR Gate" | "XOR Gate" | "NAND Gate" | "NOR Gate" | "XNOR Gate" => {
+                let (function, negated_output) = match component.name.as_str() {
+                    "AND Gate" => (GateFunction::And, false),
+                    "OR Gate" => (GateFunction::Or, false),
+                    "XOR Gate" => (GateFunction::Xor, false),
+                    "NAND Gate" => (GateFunction::And, true),
+                    "NOR Gate" => (GateFunction::Or, true),
+                    _ => (GateFunction::Xor, true),
+                };
+                let width = component.width("width", 1)?;
+                let size = component.number("size", 50)? as i32;
+                let inputs = component.number("inputs", 2)? as usize;
+                if !(2..=32).contains(&inputs) {
+                    return unsupported("number of inputs");
+                }
+                let negated_inputs = (0..inputs)
+                    .map(|index| component.boolean(&format!("negate{}", index), false))
+                    .collect::<eyre::Result<Vec<bool>>>()?;
+                let bonus = if function == GateFunction::Xor { 10 } else { 0 } + if negated_output { 10 } else { 0 };
+
+                let mut ports = vec![port(0, 0, width, true)];
+                for (index, negated) in negated_inputs.iter().enumerate() {
+                    let (dx, dy) = gate_input_offset(size, inputs, index);
+                    let dx = dx + bonus + if *negated { 10 } else { 0 };
+                    let (dx, dy) = match facing {
+                        Direction::East => (-dx, dy),
+                        Direction::West => (dx, dy),
+                        Direction::South => (dy, -dx),
+                        Direction::North => (dy, dx),
+                    };
+                    ports.push(port(dx, dy, width, false));
+                }
+                Part {
+                    kind: Kind::Gate {
+                        function,
+                        negated_inputs,
+                        negated_output,
+                    },
+                    ports,
+                }
+            }
+            "NOT Gate" => {
+                if component.attribute("size").is_some_and(|size| size != "30") {
+                    return unsupported("size");
+                }
+                let width = component.width("width", 1)?;
+                let (dx, dy) = backwards(facing, 30);
+                Part {
+                    kind: Kind::Not,
+                    ports: vec![port(0, 0, width, true), port(dx, dy, width, false)],
+                }
+            }
+            "Controlled Buffer" => {
+                let width = component.width("width", 1)?;
+                let (dx, dy) = backwards(facing, 20);
+                let (mx, my) = backwards(facing, 10);
+                // The control sits on the right of the direction the buffer faces, or on its left.
+                let (rx, ry) = match facing {
+                    Direction::East => (0, 10),
+                    Direction::West => (0, -10),
+                    Direction::South => (-10, 0),
+                    Direction::North => (10, 0),
+                };
+                let (cx, cy) = match component.attribute("control").unwrap_or("right") {
+                    "right" => (mx + rx, my + ry),
+                    "left" => (mx - rx, my - ry),
+                    _ => return unsupported("control position"),
+                };
+                Part {
+                    kind: Kind::ControlledBuffer,
+                    ports: vec![
+                        port(0, 0, width, true),
+                        port(dx, dy, width, false),
+                        port(cx, cy, 1, false),
+                    ],
+                }
+            }
+            "Multiplexer" => {
+                if component.boolean("enable", false)? {
+                    return unsupported("enable input");
+                }
+                let width = component.width("width", 1)?;
+                let select = component.width("select", 1)?;
+                let top = top_select(component)?;
+                let mut ports = vec![port(0, 0, width, true)];
+                let inputs: Vec<(i32, i32)> = match (select, facing) {
+                    (1, Direction::East) => vec![(-30, -10), (-30, 10)],
+                    (1, Direction::West) => vec![(30, -10), (30, 10)],
+                    (1, Direction::South) => vec![(-10, -30), (10, -30)],
+                    (1, Direction::North) => vec![(-10, 30), (10, 30)],
+                    (select, Direction::East) => {
+                        let inputs = 1 << select;
+                        (0..inputs)
+                            .map(|index| (-40, -(inputs / 2) * 10 + index * 10))
+                            .collect()
+                    }
+                    _ => return unsupported("orientation"),
+                };
+                let (sx, sy) = match (facing, top) {
+                    (Direction::East, false) => (-20, 20),
+                    (Direction::East, true) => (-20, -20),
+                    (Direction::West, false) => (20, 20),
+                    (Direction::West, true) => (20, -20),
+                    (Direction::South, false) => (-20, -20),
+                    (Direction::South, true) => (20, -20),
+                    (Direction::North, false) => (-20, 20),
+                    (Direction::North, true) => (20, 20),
+                };
+                if select > 1 && top {
+                    return unsupported("select position");
+                }
+                ports.push(port(sx, sy, select, false));
+                ports.extend(inputs.into_iter().map(|(dx, dy)| port(dx, dy, width, false)));
+                Part {
+                    kind: Kind::Multiplexer,
+                    ports,
+                }
+            }
+            "Demultiplexer" => {
+                let width = component.width("width", 1)?;
+                let select = component.width("select", 1)?;
+                let enable = component.boolean("enable", false)?;
+                if facing != Direction::East || select != 1 {
+                    return unsupported("orientation");
+                }
+                let sy = if top_select(component)? { -20 } else { 20 };
+                let mut ports = vec![port(0, 0, width, false), port(20, sy, 1, false)];
+                ports.push(port(30, -10, width, true));
+                ports.push(port(30, 10, width, true));
+                if enable {
+                    ports.push(port(10, sy, 1, false));
+                }
+                Part {
+                    kind: Kind::Demultiplexer { enable },
+                    ports,
+                }
+            }
+            "Decoder" => {
+                let select = component.width("select", 1)?;
+                // Unlike the demultiplexer, the decoder has an enable input unless told otherwise.
+                let enable = component.boolean("enable", true)?;
+                let outputs = 1 << select;
+                let (dx, ex) = match facing {
+                    Direction::East => (20, -10),
+                    Direction::West => (-20, 10),
+                    _ => return unsupported("orientation"),
+                };
+                if select < 2 {
+                    return unsupported("select width");
+                }
+                let dy = if top_select(component)? { 0 } else { -10 * outputs };
+                let mut ports = vec![port(0, 0, select, false)];
+                ports.extend((0..outputs).map(|index| port(dx, dy + index * 10, 1, true)));
+                if enable {
+                    ports.push(port(ex, 0, 1, false));
+                }
+                Part {
+                    kind: Kind::Decoder { enable },
+                    ports,
+                }
+            }
+            "Register" | "Counter" | "Adder" | "Subtractor" | "ROM" | "RAM" => {
+                if component
+                    .attribute("appearance")
+                    .is_some_and(|appearance| appearance != "classic")
+                    && component.name != "Adder"
+                    && component.name != "Subtractor"
+                {
+                    return unsupported("appearance");
+                }
+                if facing != Direction::East {
+                    return unsupported("orientation");
+                }
+                match component.name.as_str() {
+                    "Register" => {
+                        let width = component.width("width", 8)?;
+                        if component
+                            .attribute("trigger")
+                            .is_some_and(|trigger| trigger != "rising")
+                        {
+                            return unsupported("trigger");
+                        }
+                        Part {
+                            kind: Kind::Register,
+                            ports: vec![
+                                port(0, 0, width, true),
+                                port(-30, 0, width, false),
+                                port(-30, 10, 1, false),
+                                port(-20, 20, 1, false),
+                                port(-10, 20, 1, false),
+                            ],
+                        }
+                    }
+                    "Counter" => {
+                        let width = component.width("width", 8)?;
+                        if component
+                            .attribute("trigger")
+                            .is_some_and(|trigger| trigger != "rising")
+                            || component.attribute("ongoal").is_some_and(|goal| goal != "wrap")
+                        {
+                            return unsupported("behaviour");
+                        }
+                        let max = component.number("max", (1 << width) - 1)?;
+                        Part {
+                            kind: Kind::Counter { max },
+                            ports: vec![
+                                port(0, 0, width, true),
+                                port(-30, 0, width, false),
+                                port(-30, -10, 1, false),
+                                port(-30, 10, 1, false),
+                                port(-20, 20, 1, false),
+                                port(-10, 20, 1, false),
+                            ],
+                        }
+                    }
+                    "Adder" | "Subtractor" => {
+                        let width = component.width("width", 8)?;
+                        Part {
+                            kind: if component.name == "Adder" {
+                                Kind::Adder
+                            } else {
+                                Kind::Subtractor
+                            },
+                            ports: vec![
+                                port(0, 0, width, true),
+                                port(-40, -10, width, false),
+                                port(-40, 10, width, false),
+                                port(-20, -20, 1, false),
+                                port(-20, 20, 1, true),
+                            ],
+                        }
+                    }
+                    "ROM" => {
+                        let address = component.width("addrWidth", 8)?;
+                        let data = component.width("dataWidth", 8)?;
+                        Part {
+                            kind: Kind::Rom,
+                            ports: vec![port(0, 10, address, false), port(240, 60, data, true)],
+                        }
+                    }
+                    _ => {
+                        let address = component.width("addrWidth", 8)?;
+                        let data = component.width("dataWidth", 8)?;
+                        if data != 8 || component.attribute("databus").is_some_and(|bus| bus != "separate") {
+                            return unsupported("data bus");
+                        }
+                        Part {
+                            kind: Kind::Ram,
+                            ports: vec![
+                                port(0, 10, address, false),
+                                port(0, 50, 1, false),
+                                port(0, 60, 1, false),
+                                port(0, 70, 1, false),
+                                port(0, 90, data, false),
+                                port(240, 90, data, true),
+                            ],
+                        }
+                    }
+                }
+            }
+            "Constant" => {
+                let width = component.width("width", 1)?;
+                let value = component.number("value", 1)? & mask(width);
+                Part {
+                    kind: Kind::Constant(value),
+                    ports: vec![port(0, 0, width, true)],
+                }
+            }
+            "Clock" => Part {
+                kind: Kind::Clock,
+                ports: vec![port(0, 0, 1, true)],
+            },
+            "Button" => Part {
+                kind: Kind::Button,
+                ports: vec![port(0, 0, 1, true)],
+            },
+            "Pull Resistor" => {
+                let pull = match component.attribute("pull").unwrap_or("0") {
+                    "0" => Some(false),
+                    "1" => Some(true),
+                    _ => None,
+                };
+                Part {
+                    kind: Kind::PullResistor(pull),
+                    ports: vec![adapting(location)],
+                }
+            }
+            "Tunnel" => Part {
+                kind: Kind::Tunnel(component.label().unwrap_or_default().to_string()),
+                ports: vec![port(0, 0, component.width("width", 1)?, false)],
+            },
+            "Probe" => Part {
+                kind: Kind::Probe,
+                ports: vec![adapting(location)],
+            },
+            "Splitter" => splitter(component, facing)?,
+            "Text" => Part {
+                kind: Kind::Probe,
+                ports: Vec::new(),
+            },
+            _ => return unsupported("component"),
+        };
+        Ok(part)
+    }
+}
+
+/// How far the input of a gate is from its output, for a gate facing east: Logisim spreads the inputs evenly, leaving
+/// the middle free when there is an even number of them.
+fn gate_input_offset(size: i32, inputs: usize, index: usize) -> (i32, i32) {
+    let (inputs, index) = (inputs as i32, index as i32);
+    let (start, distance, lower_even) = if inputs <= 3 {
+        if size < 40 {
+            (-5, 10, 10)
+        } else if size < 60 || inputs <= 2 {
+            (-10, 20, 20)
+        } else {
+            (-15, 30, 30)
+        }
+    } else if inputs == 4 && size >= 60 {
+        (-5, 20, 0)
+    } else {
+        (-5, 10, 10)
+    };
+    let dy = if inputs % 2 == 1 {
+        start * (inputs - 1) + distance * index
+    } else {
+        start * inputs + distance * index + if index >= inputs / 2 { lower_even } else { 0 }
+    };
+    (size, dy)
+}
+
+/// The offset of a point `distance` behind a component facing `facing`.
+fn backwards(facing: Direction, distance: i32) -> (i32, i32) {
+    match facing {
+        Direction::East => (-distance, 0),
+        Direction::West => (distance, 0),
+        Direction::South => (0, -distance),
+        Direction::North => (0, distance),
+    }
+}
+
+fn top_select(component: &Component) -> eyre::Result<bool> {
+    match component.attribute("selloc").unwrap_or("bl") {
+        "bl" => Ok(false),
+        "tr" => Ok(true),
+        other => bail!("{}: invalid selloc: {}", component, other),
+    }
+}
+
+fn adapting(location: Location) -> Port {
+    Port {
+        location,
+        width: None,
+        output: false,
+    }
+}
+
+pub fn mask(width: usize) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+/// The ends of a splitter spread from the combined one like Logisim's, and the bits go to the ends in order unless the
+/// `bitN` attributes say otherwise.
+fn splitter(component: &Component, facing: Direction) -> eyre::Result<Part> {
+    let fanout = component.number("fanout", 2)? as i32;
+    let incoming = component.width("incoming", 2)?;
+    let spacing = component.number("spacing", 1)? as i32;
+    let justify = match component.attribute("appear").unwrap_or("left") {
+        "center" | "legacy" => 0,
+        "right" => 1,
+        _ => -1,
+    };
+    if fanout < 1 {
+        bail!("{}: invalid fanout", component);
+    }
+
+    let (dx0, dy0, ddx, ddy) = match facing {
+        Direction::North | Direction::South => {
+            let m = if facing == Direction::North { 1 } else { -1 };
+            let dx0 = if justify == 0 {
+                10 * ((fanout + 1) / 2 - 1)
+            } else if m * justify < 0 {
+                -10
+            } else {
+                10 * fanout
+            };
+            (dx0, -20 * m, -10 * spacing, 0)
+        }
+        Direction::East | Direction::West => {
+            let m = if facing == Direction::West { -1 } else { 1 };
+            let dy0 = if justify == 0 {
+                -10 * (fanout / 2)
+            } else if m * justify > 0 {
+                10
+            } else {
+                -10 * fanout
+            };
+            (20 * m, dy0, 0, 10 * spacing)
+        }
+    };
+
+    let mut bits: Vec<Option<usize>> = if fanout as usize >= incoming {
+        (0..incoming).map(Some).collect()
+    } else {
+        let per_end = incoming / fanout as usize;
+        let mut extra = incoming % fanout as usize;
+        let (mut end, mut left) = (0, 0);
+        let mut bits = Vec::new();
+        for _ in 0..incoming {
+            if left == 0 {
+                end += 1;
+                left = per_end;
+                if extra > 0 {
+                    left += 1;
+                    extra -= 1;
+                }
+            }
+            bits.push(Some(end - 1));
+            left -= 1;
+        }
+        bits
+    };
+    for (index, bit) in bits.iter_mut().enumerate() {
+        match component.attribute(&format!("bit{}", index)) {
+            None => {}
+            Some("none") => *bit = None,
+            Some(end) => match end.parse::<usize>() {
+                Ok(end) if end < fanout as usize => *bit = Some(end),
+                _ => bail!("{}: invalid bit{}: {}", component, index, end),
+            },
+        }
+    }
+
+    let location = component.location;
+    let mut ports = vec![Port {
+        location,
+        width: Some(incoming),
+        output: false,
+    }];
+    for end in 0..fanout {
+        let width = bits.iter().filter(|bit| **bit == Some(end as usize)).count();
+        ports.push(Port {
+            location: location.translate(dx0 + end * ddx, dy0 + end * ddy),
+            width: (width > 0).then_some(width),
+            output: false,
+        });
+    }
+    Ok(Part {
+        kind: Kind::Splitter { bits },
+        ports,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::circuit::Attribute;
+
+    fn component(name: &str, x: i32, y: i32, attributes: &[(&str, &str)]) -> Component {
+        Component {
+            name: name.to_string(),
+            location: Location::new(x, y),
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| Attribute {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                    span: 0..0,
+                })
+                .collect(),
+            span: 0..0,
+        }
+    }
+
+    fn locations(part: &Part) -> Vec<(i32, i32)> {
+        part.ports
+            .iter()
+            .map(|port| (port.location.x, port.location.y))
+            .collect()
+    }
+
+    #[test]
+    fn test_gates() -> eyre::Result<()> {
+        let and = Part::new(&component(
+            "AND Gate",
+            1020,
+            1710,
+            &[
+                ("facing", "south"),
+                ("inputs", "3"),
+                ("negate0", "true"),
+                ("size", "30"),
+            ],
+        ))?;
+        assert_eq!(
+            locations(&and),
+            vec![(1020, 1710), (1010, 1670), (1020, 1680), (1030, 1680)]
+        );
+
+        let nor = Part::new(&component("NOR Gate", 1560, 1350, &[("inputs", "8")]))?;
+        assert_eq!(nor.ports.len(), 9);
+        assert_eq!((nor.ports[1].location.x, nor.ports[1].location.y), (1500, 1310));
+        assert_eq!((nor.ports[8].location.x, nor.ports[8].location.y), (1500, 1390));
+
+        let xor = Part::new(&component("XOR Gate", 1380, 1660, &[("size", "30"), ("width", "8")]))?;
+        assert_eq!(locations(&xor), vec![(1380, 1660), (1340, 1650), (1340, 1670)]);
+        assert_eq!(xor.ports[0].width, Some(8));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plexers() -> eyre::Result<()> {
+        let mux = Part::new(&component(
+            "Multiplexer",
+            960,
+            1130,
+            &[("facing", "south"), ("width", "16")],
+        ))?;
+        assert_eq!(
+            locations(&mux),
+            vec![(960, 1130), (940, 1110), (950, 1100), (970, 1100)]
+        );
+
+        let mux = Part::new(&component(
+            "Multiplexer",
+            1570,
+            1510,
+            &[("select", "2"), ("width", "8")],
+        ))?;
+        assert_eq!(
+            locations(&mux),
+            vec![
+                (1570, 1510),
+                (1550, 1530),
+                (1530, 1490),
+                (1530, 1500),
+                (1530, 1510),
+                (1530, 1520)
+            ]
+        );
+
+        let demux = Part::new(&component("Demultiplexer", 1010, 520, &[("enable", "true")]))?;
+        assert_eq!(
+            locations(&demux),
+            vec![(1010, 520), (1030, 540), (1040, 510), (1040, 530), (1020, 540)]
+        );
+
+        let decoder = Part::new(&component("Decoder", 1590, 890, &[("facing", "west"), ("select", "2")]))?;
+        assert_eq!(
+            locations(&decoder),
+            vec![
+                (1590, 890),
+                (1570, 850),
+                (1570, 860),
+                (1570, 870),
+                (1570, 880),
+                (1600, 890)
+            ]
+        );
+
+        assert!(Part::new(&component("Demultiplexer", 0, 0, &[("facing", "north")])).is_err());
+        assert!(Part::new(&component("Subcircuit", 0, 0, &[])).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_splitters() -> eyre::Result<()> {
+        let splitter = Part::new(&component(
+            "Splitter",
+            2380,
+            760,
+            &[
+                ("appear", "right"),
+                ("facing", "west"),
+                ("incoming", "8"),
+                ("bit1", "0"),
+                ("bit2", "0"),
+                ("bit3", "0"),
+                ("bit4", "1"),
+                ("bit5", "1"),
+                ("bit6", "1"),
+                ("bit7", "1"),
+            ],
+        ))?;
+        assert_eq!(locations(&splitter), vec![(2380, 760), (2360, 740), (2360, 750)]);
+        assert_eq!(
+            splitter.kind,
+            Kind::Splitter {
+                bits: vec![Some(0), Some(0), Some(0), Some(0), Some(1), Some(1), Some(1), Some(1)]
+            }
+        );
+
+        let splitter = Part::new(&component(
+            "Splitter",
+            2670,
+            820,
+            &[("fanout", "32"), ("incoming", "32")],
+        ))?;
+        assert_eq!(splitter.ports.len(), 33);
+        assert_eq!(
+            (splitter.ports[1].location.x, splitter.ports[1].location.y),
+            (2690, 500)
+        );
+        assert_eq!(splitter.ports[1].width, Some(1));
+
+        let splitter = Part::new(&component(
+            "Splitter",
+            0,
+            0,
+            &[("fanout", "3"), ("incoming", "8"), ("bit7", "none")],
+        ))?;
+        assert_eq!(
+            splitter.ports.iter().map(|port| port.width).collect::<Vec<_>>(),
+            vec![Some(8), Some(3), Some(3), Some(1)]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,646 @@
+use crate::circuit::circuit::{Circuit, Location};
+use crate::circuit::netlist::Netlist;
+use crate::circuit::part::{mask, GateFunction, Kind};
+use crate::emulate::microcode::parse_image;
+use eyre::{bail, WrapErr};
+use std::collections::VecDeque;
+
+/// A circuit that keeps changing after this many component evaluations per component oscillates.
+const SETTLE_LIMIT: usize = 1_000;
+
+/// The value of a bit, like Logisim shows it on the wires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Nothing drives it: blue.
+    Floating,
+    Low,
+    High,
+    /// Driven to different values, or computed from undefined ones: red.
+    Error,
+}
+
+/// What the stateful components remember between two evaluations.
+enum State {
+    None,
+    /// Whether the button is pressed.
+    Button(bool),
+    Register {
+        value: Vec<Level>,
+        clock: Level,
+    },
+    Counter {
+        value: Option<u64>,
+        clock: Level,
+    },
+    Rom(Vec<u64>),
+    Ram {
+        memory: Vec<u8>,
+        clock: Level,
+    },
+}
+
+/// Simulates the netlist like Logisim does: a change re-evaluates the components that read it until nothing changes,
+/// and the registers latch on the rising edge of their clock. The `Clock` components are driven by `tick`, the
+/// buttons by `press`.
+pub struct Simulation {
+    pub netlist: Netlist,
+    values: Vec<Level>,
+    /// What every component drives, output port after output port.
+    driven: Vec<Vec<Level>>,
+    /// The bits every component drives, in the same order.
+    outputs: Vec<Vec<usize>>,
+    /// For every bit, the components and the index in their `driven` of what drives it.
+    drivers: Vec<Vec<(usize, usize)>>,
+    /// For every bit, the components that read it.
+    readers: Vec<Vec<usize>>,
+    pulls: Vec<Option<Level>>,
+    states: Vec<State>,
+    clock: Level,
+    queue: VecDeque<usize>,
+    queued: Vec<bool>,
+}
+
+impl Level {
+    fn from_bool(value: bool) -> Self {
+        if value {
+            Level::High
+        } else {
+            Level::Low
+        }
+    }
+
+    fn not(self) -> Self {
+        match self {
+            Level::Low => Level::High,
+            Level::High => Level::Low,
+            other => other,
+        }
+    }
+}
+
+impl Simulation {
+    /// Power the circuit on: the registers and counters start at 0, the memories with their saved contents.
+    pub fn new(circuit: &Circuit) -> eyre::Result<Self> {
+        let netlist = Netlist::new(circuit)?;
+        let count = netlist.parts.len();
+        let mut simulation = Simulation {
+            values: vec![Level::Floating; netlist.bits],
+            driven: vec![Vec::new(); count],
+            outputs: vec![Vec::new(); count],
+            drivers: vec![Vec::new(); netlist.bits],
+            readers: vec![Vec::new(); netlist.bits],
+            pulls: vec![None; netlist.bits],
+            states: Vec::with_capacity(count),
+            clock: Level::Low,
+            queue: VecDeque::new(),
+            queued: vec![false; count],
+            netlist,
+        };
+
+        for index in 0..count {
+            let part = &simulation.netlist.parts[index];
+            let connections = &simulation.netlist.connections[index];
+            for (port, bits) in part.ports.iter().zip(connections) {
+                if port.output {
+                    for bit in bits {
+                        simulation.drivers[*bit].push((index, simulation.outputs[index].len()));
+                        simulation.outputs[index].push(*bit);
+                    }
+                } else {
+                    for bit in bits {
+                        simulation.readers[*bit].push(index);
+                    }
+                }
+            }
+            simulation.driven[index] = vec![Level::Floating; simulation.outputs[index].len()];
+
+            let component = &simulation.netlist.components[index];
+            let contents = || -> eyre::Result<Vec<u64>> {
+                let words = match component.attribute("contents") {
+                    Some(contents) => parse_image(contents).wrap_err_with(|| format!("{}", component))?,
+                    None => Vec::new(),
+                };
+                let width = connections[1].len();
+                let mut memory: Vec<u64> = words.into_iter().map(|word| word as u64 & mask(width)).collect();
+                memory.resize(1 << connections[0].len(), 0);
+                Ok(memory)
+            };
+            let state = match &part.kind {
+                Kind::PullResistor(pull) => {
+                    let level = pull.map_or(Level::Error, Level::from_bool);
+                    for bit in &connections[0] {
+                        simulation.pulls[*bit] = Some(level);
+                    }
+                    State::None
+                }
+                Kind::Register => State::Register {
+                    value: vec![Level::Low; connections[0].len()],
+                    clock: Level::Low,
+                },
+                Kind::Counter { .. } => State::Counter {
+                    value: Some(0),
+                    clock: Level::Low,
+                },
+                Kind::Button => State::Button(false),
+                Kind::Rom => State::Rom(contents()?),
+                Kind::Ram => State::Ram {
+                    memory: contents()?.into_iter().map(|word| word as u8).collect(),
+                    clock: Level::Low,
+                },
+                _ => State::None,
+            };
+            simulation.states.push(state);
+        }
+
+        for index in 0..count {
+            simulation.schedule(index);
+        }
+        for bit in 0..simulation.netlist.bits {
+            simulation.resolve(bit);
+        }
+        simulation.settle()?;
+        Ok(simulation)
+    }
+
+    /// Replace the content of the ROM at the location, and let the circuit settle again.
+    pub fn load_rom(&mut self, location: Location, words: &[u64]) -> eyre::Result<()> {
+        let index = self.netlist.component_at("ROM", location)?;
+        let width = self.netlist.connections[index][1].len();
+        let State::Rom(memory) = &mut self.states[index] else {
+            unreachable!("a ROM has a ROM state");
+        };
+        if words.len() > memory.len() {
+            bail!("{} words do not fit the ROM at {}", words.len(), location);
+        }
+        memory.fill(0);
+        for (word, value) in memory.iter_mut().zip(words) {
+            *word = value & mask(width);
+        }
+        self.schedule(index);
+        self.settle()
+    }
+
+    /// Replace the content of the RAM at the location.
+    pub fn load_ram(&mut self, location: Location, bytes: &[u8]) -> eyre::Result<()> {
+        let index = self.netlist.component_at("RAM", location)?;
+        let State::Ram { memory, .. } = &mut self.states[index] else {
+            unreachable!("a RAM has a RAM state");
+        };
+        if bytes.len() > memory.len() {
+            bail!("{} bytes do not fit the RAM at {}", bytes.len(), location);
+        }
+        memory.fill(0);
+        memory[..bytes.len()].copy_from_slice(bytes);
+        self.schedule(index);
+        self.settle()
+    }
+
+    /// Press or release the button at the location, and let the circuit settle.
+    pub fn press(&mut self, location: Location, pressed: bool) -> eyre::Result<()> {
+        let index = self.netlist.component_at("Button", location)?;
+        self.states[index] = State::Button(pressed);
+        self.schedule(index);
+        self.settle()
+    }
+
+    pub fn ram(&self, location: Location) -> eyre::Result<&[u8]> {
+        let index = self.netlist.component_at("RAM", location)?;
+        match &self.states[index] {
+            State::Ram { memory, .. } => Ok(memory),
+            _ => unreachable!("a RAM has a RAM state"),
+        }
+    }
+
+    /// A full clock cycle: the clock rises, the registers latch, the clock falls.
+    pub fn tick(&mut self) -> eyre::Result<()> {
+        self.set_clock(Level::High)?;
+        self.set_clock(Level::Low)
+    }
+
+    /// The value of the bits of a tunnel label, `None` when some of them are not 0 or 1.
+    pub fn label(&self, label: &str) -> eyre::Result<Option<u64>> {
+        Ok(self.value(self.netlist.label(label)?))
+    }
+
+    fn value(&self, bits: &[usize]) -> Option<u64> {
+        let mut value = 0;
+        for (index, bit) in bits.iter().enumerate() {
+            match self.values[*bit] {
+                Level::Low => {}
+                Level::High => value |= 1 << index,
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+
+    /// The value of the bits, or what to make of them when they are not all 0 or 1: an error if one of them is, unknown
+    /// otherwise.
+    fn defined(&self, bits: &[usize]) -> Result<u64, Level> {
+        self.value(bits).ok_or_else(|| {
+            if bits.iter().any(|bit| self.values[*bit] == Level::Error) {
+                Level::Error
+            } else {
+                Level::Floating
+            }
+        })
+    }
+
+    fn levels(&self, bits: &[usize]) -> Vec<Level> {
+        bits.iter().map(|bit| self.values[*bit]).collect()
+    }
+
+    fn schedule(&mut self, index: usize) {
+        if !self.queued[index] {
+            self.queued[index] = true;
+            self.queue.push_back(index);
+        }
+    }
+
+    fn set_clock(&mut self, level: Level) -> eyre::Result<()> {
+        self.clock = level;
+        for index in 0..self.netlist.parts.len() {
+            if self.netlist.parts[index].kind == Kind::Clock {
+                self.schedule(index);
+            }
+        }
+        self.settle()?;
+
+        // Every register sees the values from before the edge: latch them all, then let the new values propagate.
+        for index in 0..self.netlist.parts.len() {
+            if self.latch(index)? {
+                self.schedule(index);
+            }
+        }
+        self.settle()
+    }
+
+    /// Evaluate the components until the values stop changing.
+    fn settle(&mut self) -> eyre::Result<()> {
+        let mut evaluations = 0;
+        let limit = SETTLE_LIMIT * self.netlist.parts.len();
+        while let Some(index) = self.queue.pop_front() {
+            self.queued[index] = false;
+            evaluations += 1;
+            if evaluations > limit {
+                bail!(
+                    "The circuit does not settle: {} keeps changing",
+                    self.netlist.components[index]
+                );
+            }
+            let driven = self.evaluate(index);
+            for (offset, level) in driven.into_iter().enumerate() {
+                if level != self.driven[index][offset] {
+                    self.driven[index][offset] = level;
+                    self.resolve(self.outputs[index][offset]);
+                }
+            }
+        }
+
+        for bit in 0..self.netlist.bits {
+            if self.values[bit] != Level::Error {
+                continue;
+            }
+            let drivers: Vec<String> = self.drivers[bit]
+                .iter()
+                .filter(|(index, offset)| matches!(self.driven[*index][*offset], Level::Low | Level::High))
+                .map(|(index, offset)| {
+                    format!(
+                        "{} drives {:?}",
+                        self.netlist.components[*index], self.driven[*index][*offset]
+                    )
+                })
+                .collect();
+            let levels: Vec<Level> = self.drivers[bit]
+                .iter()
+                .map(|(index, offset)| self.driven[*index][*offset])
+                .collect();
+            if levels.contains(&Level::Low) && levels.contains(&Level::High) {
+                bail!("Short circuit on {}: {}", self.netlist.names[bit], drivers.join(", "));
+            }
+        }
+        Ok(())
+    }
+
+    /// Combine the drivers of the bit, and wake up the readers if it changed.
+    fn resolve(&mut self, bit: usize) {
+        let mut level = Level::Floating;
+        for (index, offset) in &self.drivers[bit] {
+            level = match (level, self.driven[*index][*offset]) {
+                (level, Level::Floating) => level,
+                (Level::Floating, driven) => driven,
+                (level, driven) if level == driven => level,
+                _ => Level::Error,
+            };
+        }
+        if level == Level::Floating {
+            level = self.pulls[bit].unwrap_or(Level::Floating);
+        }
+        if self.values[bit] != level {
+            self.values[bit] = level;
+            for reader in 0..self.readers[bit].len() {
+                self.schedule(self.readers[bit][reader]);
+            }
+        }
+    }
+
+    /// What the component drives, from the values of its inputs.
+    fn evaluate(&mut self, index: usize) -> Vec<Level> {
+        let connections = &self.netlist.connections[index];
+        let port = |port: usize| self.levels(&connections[port]);
+        let value = |port: usize| self.defined(&connections[port]);
+        let word = |value: Result<u64, Level>, width: usize| match value {
+            Ok(value) => (0..width).map(|bit| Level::from_bool(value >> bit & 1 == 1)).collect(),
+            Err(level) => vec![level; width],
+        };
+        let width = self.driven[index].len();
+
+        match &self.netlist.parts[index].kind {
+            Kind::Gate {
+                function,
+                negated_inputs,
+                negated_output,
+            } => {
+                let inputs: Vec<Vec<Level>> = (1..=negated_inputs.len()).map(&port).collect();
+                (0..width)
+                    .map(|bit| {
+                        // Undefined inputs are ignored, as set in the options of the circuit.
+                        let levels: Vec<Level> = inputs
+                            .iter()
+                            .zip(negated_inputs)
+                            .map(|(input, negated)| if *negated { input[bit].not() } else { input[bit] })
+                            .filter(|level| *level != Level::Floating)
+                            .collect();
+                        let level = if levels.is_empty() {
+                            Level::Floating
+                        } else if levels.contains(&Level::Error) {
+                            Level::Error
+                        } else {
+                            let ones = levels.iter().filter(|level| **level == Level::High).count();
+                            Level::from_bool(match function {
+                                GateFunction::And => ones == levels.len(),
+                                GateFunction::Or => ones > 0,
+                                GateFunction::Xor => ones % 2 == 1,
+                            })
+                        };
+                        if *negated_output {
+                            level.not()
+                        } else {
+                            level
+                        }
+                    })
+                    .collect()
+            }
+            Kind::Not => port(1).into_iter().map(Level::not).collect(),
+            Kind::ControlledBuffer => match self.values[connections[2][0]] {
+                Level::High => port(1),
+                Level::Low => vec![Level::Floating; width],
+                _ => vec![Level::Error; width],
+            },
+            Kind::Multiplexer => match value(1) {
+                Ok(select) => port(2 + select as usize),
+                Err(level) => vec![level; width],
+            },
+            Kind::Demultiplexer { enable } => {
+                let input = port(0);
+                let outputs = connections.len() - 2 - *enable as usize;
+                let enabled = !*enable || self.values[connections[connections.len() - 1][0]] != Level::Low;
+                match value(1) {
+                    _ if !enabled => vec![Level::Low; width],
+                    Ok(select) => (0..outputs)
+                        .flat_map(|output| {
+                            if output == select as usize {
+                                input.clone()
+                            } else {
+                                vec![Level::Low; input.len()]
+                            }
+                        })
+                        .collect(),
+                    Err(level) => vec![level; width],
+                }
+            }
+            Kind::Decoder { enable } => {
+                let enabled = !*enable || self.values[connections[connections.len() - 1][0]] != Level::Low;
+                match value(0) {
+                    _ if !enabled => vec![Level::Low; width],
+                    Ok(select) => (0..width)
+                        .map(|output| Level::from_bool(output == select as usize))
+                        .collect(),
+                    Err(level) => vec![level; width],
+                }
+            }
+            Kind::Register => {
+                let clear = self.values[connections[4][0]] == Level::High;
+                let State::Register { value, .. } = &mut self.states[index] else {
+                    unreachable!("a register has a register state");
+                };
+                if clear {
+                    value.fill(Level::Low);
+                }
+                value.clone()
+            }
+            Kind::Counter { .. } => {
+                let clear = self.values[connections[5][0]] == Level::High;
+                let State::Counter { value, .. } = &mut self.states[index] else {
+                    unreachable!("a counter has a counter state");
+                };
+                if clear {
+                    *value = Some(0);
+                }
+                word(value.ok_or(Level::Error), width)
+            }
+            Kind::Adder | Kind::Subtractor => {
+                let bits = width - 1;
+                // An undefined carry in is no carry.
+                let carry = match self.values[connections[3][0]] {
+                    Level::Floating | Level::Low => Ok(0),
+                    Level::High => Ok(1),
+                    Level::Error => Err(Level::Error),
+                };
+                let result = match (value(1), value(2), carry) {
+                    (Ok(a), Ok(b), Ok(carry)) if self.netlist.parts[index].kind == Kind::Adder => Ok(a + b + carry),
+                    // The borrow out is the bit above the result, like the carry.
+                    (Ok(a), Ok(b), Ok(borrow)) => Ok(a.wrapping_sub(b).wrapping_sub(borrow) & mask(bits + 1)),
+                    (a, b, carry) => Err([a, b, carry]
+                        .into_iter()
+                        .filter_map(Result::err)
+                        .max_by_key(|level| *level == Level::Error)
+                        .unwrap_or(Level::Error)),
+                };
+                word(result, width)
+            }
+            Kind::Rom => {
+                let State::Rom(memory) = &self.states[index] else {
+                    unreachable!("a ROM has a ROM state");
+                };
+                word(value(0).map(|address| memory[address as usize]), width)
+            }
+            Kind::Ram => {
+                if self.values[connections[2][0]] != Level::High {
+                    return vec![Level::Floating; width];
+                }
+                let State::Ram { memory, .. } = &self.states[index] else {
+                    unreachable!("a RAM has a RAM state");
+                };
+                word(value(0).map(|address| memory[address as usize] as u64), width)
+            }
+            Kind::Constant(constant) => word(Ok(*constant), width),
+            Kind::Clock => vec![self.clock],
+            Kind::Button => match self.states[index] {
+                State::Button(true) => vec![Level::High],
+                _ => vec![Level::Low],
+            },
+            Kind::PullResistor(_) | Kind::Tunnel(_) | Kind::Probe | Kind::Splitter { .. } => Vec::new(),
+        }
+    }
+
+    /// Latch the inputs of a clocked component if its clock just rose: whether it did.
+    fn latch(&mut self, index: usize) -> eyre::Result<bool> {
+        let connections = &self.netlist.connections[index];
+        let level = |port: usize| self.values[connections[port][0]];
+        let (clock, rising) = match &self.states[index] {
+            State::Register { clock, .. } => (level(3), *clock == Level::Low && level(3) == Level::High),
+            State::Counter { clock, .. } => (level(4), *clock == Level::Low && level(4) == Level::High),
+            State::Ram { clock, .. } => (level(3), *clock == Level::Low && level(3) == Level::High),
+            _ => return Ok(false),
+        };
+
+        let input = self.levels(&connections[1]);
+        let enabled = level(2) != Level::Low;
+        let load = level(2) == Level::High;
+        let write = level(1) == Level::High;
+        let count = level(3) != Level::Low;
+        let clear = match self.netlist.parts[index].kind {
+            Kind::Register => level(4) == Level::High,
+            Kind::Counter { .. } => level(5) == Level::High,
+            _ => false,
+        };
+        let address = self.value(&connections[0]);
+        let data = self.value(&connections[4]);
+        let max = match self.netlist.parts[index].kind {
+            Kind::Counter { max } => max,
+            _ => 0,
+        };
+        let in_value = self.value(&connections[1]);
+        let component = &self.netlist.components[index];
+
+        match &mut self.states[index] {
+            State::Register { value, clock: last } => {
+                *last = clock;
+                if rising && enabled && !clear {
+                    *value = input;
+                }
+            }
+            State::Counter { value, clock: last } => {
+                *last = clock;
+                if rising && !clear {
+                    *value = match (load, count) {
+                        (false, false) => *value,
+                        (false, true) => value.map(|value| if value >= max { 0 } else { value + 1 }),
+                        (true, true) => value.map(|value| if value == 0 { max } else { value - 1 }),
+                        (true, false) => in_value,
+                    };
+                }
+            }
+            State::Ram { memory, clock: last } => {
+                *last = clock;
+                if rising && write {
+                    match (address, data) {
+                        (Some(address), Some(data)) => memory[address as usize] = data as u8,
+                        _ => bail!("{} writes an undefined value or address", component),
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(rising)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circuit(components: &str, wires: &[[i32; 4]]) -> eyre::Result<Circuit> {
+        let wires: String = wires
+            .iter()
+            .map(|[x1, y1, x2, y2]| format!("<wire from=\"({},{})\" to=\"({},{})\"/>", x1, y1, x2, y2))
+            .collect();
+        Circuit::parse(&format!(
+            "<project><circuit name=\"main\">{}{}</circuit></project>",
+            components, wires
+        ))
+    }
+
+    fn tunnel(x: i32, y: i32, label: &str, width: usize) -> String {
+        format!(
+            "<comp loc=\"({},{})\" name=\"Tunnel\"><a name=\"label\" val=\"{}\"/><a name=\"width\" val=\"{}\"/></comp>",
+            x, y, label, width
+        )
+    }
+
+    #[test]
+    fn test_counter() -> eyre::Result<()> {
+        // A 4 bit counter that counts up on every clock, and an AND gate of its two low bits.
+        let components = [
+            "<comp loc=\"(100,100)\" name=\"Counter\"><a name=\"width\" val=\"4\"/></comp>".to_string(),
+            "<comp loc=\"(60,140)\" name=\"Clock\"/>".to_string(),
+            tunnel(100, 100, "count", 4),
+            "<comp loc=\"(200,100)\" name=\"Splitter\"><a name=\"facing\" val=\"west\"/><a name=\"fanout\" val=\"4\"/><a name=\"incoming\" val=\"4\"/></comp>".to_string(),
+            "<comp loc=\"(300,100)\" name=\"AND Gate\"/>".to_string(),
+            tunnel(300, 100, "and", 1),
+        ];
+        let wires = [
+            [60, 140, 80, 140],
+            [80, 140, 80, 120],
+            [100, 100, 200, 100],
+            // The ends of the splitter are at (180,110) for bit 0 to (180,140) for bit 3.
+            [180, 110, 230, 110],
+            [230, 110, 230, 80],
+            [230, 80, 250, 80],
+            [180, 120, 250, 120],
+        ];
+        let mut simulation = Simulation::new(&circuit(&components.concat(), &wires)?)?;
+        assert_eq!(simulation.label("count")?, Some(0));
+        assert_eq!(simulation.label("and")?, Some(0));
+        for _ in 0..3 {
+            simulation.tick()?;
+        }
+        assert_eq!(simulation.label("count")?, Some(3));
+        assert_eq!(simulation.label("and")?, Some(1));
+        for _ in 0..14 {
+            simulation.tick()?;
+        }
+        assert_eq!(simulation.label("count")?, Some(1));
+        assert_eq!(simulation.label("and")?, Some(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_circuit() -> eyre::Result<()> {
+        let components = [
+            "<comp loc=\"(0,0)\" name=\"Constant\"/>",
+            "<comp loc=\"(0,20)\" name=\"Constant\"><a name=\"value\" val=\"0x0\"/></comp>",
+        ]
+        .concat();
+        let error = Simulation::new(&circuit(&components, &[[0, 0, 0, 20]])?)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Short circuit on (0,0)[0] of Constant at (0,0): Constant at (0,0) drives High, Constant at (0,20) drives Low"
+        );
+
+        // A gate that flips its own input never settles.
+        let components = "<comp loc=\"(100,100)\" name=\"XOR Gate\"/><comp loc=\"(40,80)\" name=\"Constant\"/>";
+        let wires = [
+            [100, 100, 100, 140],
+            [100, 140, 40, 140],
+            [40, 140, 40, 120],
+        ];
+        assert!(Simulation::new(&circuit(components, &wires)?).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use crate::circuit::circuit::{Circuit, DEFAULT_CIRCUIT, HIGH_ROM, LOW_ROM};
+use crate::circuit::netlist::Netlist;
+use crate::circuit::part::Kind;
+use crate::constants::control_line::CONTROL_LINES;
+use eyre::bail;
+use std::collections::BTreeSet;
+use std::fs;
+
+/// The tunnels every bit of the control word drives, from the data pins of the two control ROMs: the bits of the
+/// splitters are already joined in the netlist. A bit without a tunnel of its own, like RST, is followed through the
+/// gate it feeds.
+pub fn control_tunnels(netlist: &Netlist) -> eyre::Result<Vec<BTreeSet<String>>> {
+    let mut tunnels = Vec::new();
+    for location in [LOW_ROM, HIGH_ROM] {
+        let rom = netlist.component_at("ROM", location)?;
+        for bit in &netlist.connections[rom][1] {
+            let mut labels = labels_of(netlist, *bit);
+            if labels.is_empty() {
+                for (index, part) in netlist.parts.iter().enumerate() {
+                    let ports = &netlist.connections[index];
+                    if matches!(part.kind, Kind::Gate { .. }) && ports[1..].iter().any(|port| port.contains(bit)) {
+                        labels.extend(ports[0].iter().flat_map(|output| labels_of(netlist, *output)));
+                    }
+                }
+            }
+            tunnels.push(labels);
+        }
+    }
+    Ok(tunnels)
+}
+
+/// The labels of the one bit wide tunnels on a bit.
+fn labels_of(netlist: &Netlist, bit: usize) -> BTreeSet<String> {
+    netlist
+        .labels
+        .iter()
+        .filter(|(_, bits)| bits.as_slice() == [bit])
+        .map(|(label, _)| label.clone())
+        .collect()
+}
+
+/// Where the bits of `ControlLine::value()` and the wiring of the circuit disagree: a line whose bit does not drive its
+/// tunnel, or a bit no line uses that drives one anyway.
+pub fn check(netlist: &Netlist) -> eyre::Result<Vec<String>> {
+    let tunnels = control_tunnels(netlist)?;
+    let mut problems = Vec::new();
+    let describe = |labels: &BTreeSet<String>| {
+        if labels.is_empty() {
+            "no tunnel".to_string()
+        } else {
+            labels.iter().cloned().collect::<Vec<_>>().join(", ")
+        }
+    };
+    for line in CONTROL_LINES {
+        let bit = line.value().trailing_zeros() as usize;
+        match tunnels.get(bit) {
+            None => problems.push(format!(
+                "{:?} (bit {}) is past the {} bits of the ROMs",
+                line,
+                bit,
+                tunnels.len()
+            )),
+            Some(labels) if !labels.contains(line.tunnel()) => problems.push(format!(
+                "{:?} (bit {}) should drive {}, the circuit wires it to {}",
+                line,
+                bit,
+                line.tunnel(),
+                describe(labels)
+            )),
+            Some(_) => {}
+        }
+    }
+    for (bit, labels) in tunnels.iter().enumerate() {
+        let used = CONTROL_LINES.iter().any(|line| line.value() == 1 << bit);
+        if !used && !labels.is_empty() {
+            problems.push(format!(
+                "Bit {} is not a control line, the circuit wires it to {}",
+                bit,
+                describe(labels)
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+/// `helper wiring [--circuit <file.circ>]`: returns whether every control line is wired where the source says.
+pub fn wiring(args: &[String]) -> eyre::Result<bool> {
+    let circuit_file = match args {
+        [] => DEFAULT_CIRCUIT,
+        [option, file] if option == "--circuit" => file.as_str(),
+        _ => bail!("Usage: wiring [--circuit <file.circ>]"),
+    };
+    let netlist = Netlist::new(&Circuit::parse(&fs::read_to_string(circuit_file)?)?)?;
+    let problems = check(&netlist)?;
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!(
+            "The {} control lines are wired to the tunnels of {}",
+            CONTROL_LINES.len(),
+            circuit_file
+        );
+    }
+    Ok(problems.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::control_line::ControlLine;
+
+    #[test]
+    fn test_schema() -> eyre::Result<()> {
+        let source = include_str!("../../../schema.circ");
+        let netlist = Netlist::new(&Circuit::parse(source)?)?;
+        assert_eq!(check(&netlist)?, Vec::<String>::new());
+
+        let tunnels = control_tunnels(&netlist)?;
+        assert!(tunnels[0].contains("Reset"));
+        assert!(tunnels[22].is_empty() && tunnels[23].is_empty());
+        assert!(tunnels[37].contains(ControlLine::RETS.tunnel()));
+
+        // Swap the labels of two lines, as if their bits had been renumbered in the source only.
+        let swapped = source
+            .replace("val=\"IP_Enable\"", "val=\"Swapped\"")
+            .replace("val=\"IP_Out\"", "val=\"IP_Enable\"")
+            .replace("val=\"Swapped\"", "val=\"IP_Out\"");
+        let netlist = Netlist::new(&Circuit::parse(&swapped)?)?;
+        assert_eq!(
+            check(&netlist)?,
+            vec![
+                "IPE (bit 24) should drive IP_Enable, the circuit wires it to IP_Out",
+                "IPO (bit 25) should drive IP_Out, the circuit wires it to IP_Enable",
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+use eyre::{bail, eyre};
+use std::ops::Range;
+
+/// An element of an XML document, with where its content is in the source, so that it can be replaced without
+/// rewriting the rest of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// The text directly inside the element, entities decoded.
+    pub text: String,
+    /// The bytes between the start and the end tag: an empty range right after the tag for `<a/>`.
+    pub content: Range<usize>,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+/// Parse the root element of a document: enough XML for the files Logisim writes, so the declaration, comments,
+/// attributes and the five predefined entities, without DTDs or CDATA sections.
+pub fn parse(source: &str) -> eyre::Result<Element> {
+    let mut parser = Parser { source, position: 0 };
+    parser.skip_misc()?;
+    let root = parser.element()?;
+    parser.skip_misc()?;
+    if parser.position != source.len() {
+        return Err(parser.error("content after the root element"));
+    }
+    Ok(root)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.source[self.position..]
+    }
+
+    fn error(&self, message: &str) -> eyre::Report {
+        let line = self.source[..self.position].matches('\n').count() + 1;
+        eyre!("XML line {}: {}", line, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skip what is allowed around the root element: whitespace, the declaration and comments.
+    fn skip_misc(&mut self) -> eyre::Result<()> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_past(&mut self, end: &str) -> eyre::Result<()> {
+        match self.rest().find(end) {
+            Some(index) => {
+                self.position += index + end.len();
+                Ok(())
+            }
+            None => Err(self.error(&format!("missing {}", end))),
+        }
+    }
+
+    fn name(&mut self) -> eyre::Result<String> {
+        let length = self
+            .rest()
+            .find(|char: char| char.is_whitespace() || matches!(char, '/' | '>' | '='))
+            .unwrap_or(self.rest().len());
+        if length == 0 {
+            return Err(self.error("expected a name"));
+        }
+        let name = self.rest()[..length].to_string();
+        self.position += length;
+        Ok(name)
+    }
+
+    fn expect(&mut self, token: &str) -> eyre::Result<()> {
+        if !self.rest().starts_with(token) {
+            return Err(self.error(&format!("expected {}", token)));
+        }
+        self.position += token.len();
+        Ok(())
+    }
+
+    fn element(&mut self) -> eyre::Result<Element> {
+        self.expect("<")?;
+        let name = self.name()?;
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.position += 2;
+                return Ok(Element {
+                    name,
+                    attributes,
+                    children: Vec::new(),
+                    text: String::new(),
+                    content: self.position..self.position,
+                });
+            }
+            if self.rest().starts_with('>') {
+                self.position += 1;
+                break;
+            }
+            let attribute = self.name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(self.error("expected a quoted attribute value")),
+            };
+            self.position += 1;
+            let Some(length) = self.rest().find(quote) else {
+                return Err(self.error("unterminated attribute value"));
+            };
+            let value = decode(&self.rest()[..length]).map_err(|error| self.error(&error.to_string()))?;
+            self.position += length + 1;
+            attributes.push((attribute, value));
+        }
+
+        let start = self.position;
+        let mut children = Vec::new();
+        let mut text = String::new();
+        loop {
+            let Some(length) = self.rest().find('<') else {
+                return Err(self.error(&format!("missing </{}>", name)));
+            };
+            text += &decode(&self.rest()[..length]).map_err(|error| self.error(&error.to_string()))?;
+            self.position += length;
+            if self.rest().starts_with("</") {
+                let content = start..self.position;
+                self.position += 2;
+                let end = self.name()?;
+                if end != name {
+                    return Err(self.error(&format!("</{}> closes <{}>", end, name)));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(Element {
+                    name,
+                    attributes,
+                    children,
+                    text,
+                    content,
+                });
+            }
+            if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("<!") || self.rest().starts_with("<?") {
+                return Err(self.error("unsupported markup"));
+            } else {
+                children.push(self.element()?);
+            }
+        }
+    }
+}
+
+fn decode(text: &str) -> eyre::Result<String> {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find('&') {
+        decoded += &rest[..index];
+        rest = &rest[index + 1..];
+        let Some(end) = rest.find(';') else {
+            bail!("unterminated entity");
+        };
+        let entity = &rest[..end];
+        let char = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x") {
+                    Some(digits) => u32::from_str_radix(digits, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|digits| digits.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| eyre!("unknown entity &{};", entity))?
+            }
+        };
+        decoded.push(char);
+        rest = &rest[end + 1..];
+    }
+    decoded += rest;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> eyre::Result<()> {
+        let source = "<?xml version=\"1.0\"?>\n<!-- c -->\n<project a='1'>\n  text &amp; &#x41;<b x=\"&lt;\"/>\n  <c>inner</c>\n</project>\n";
+        let root = parse(source)?;
+        assert_eq!(root.name, "project");
+        assert_eq!(root.attribute("a"), Some("1"));
+        assert_eq!(root.text.trim(), "text & A");
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[0].attribute("x"), Some("<"));
+        let inner = root.children("c").next().unwrap();
+        assert_eq!(&source[inner.content.clone()], "inner");
+
+        assert!(parse("<a><b></a>").is_err());
+        assert!(parse("<a>").is_err());
+        assert!(parse("<a/><b/>").is_err());
+        assert!(parse("<a>&bogus;</a>").is_err());
+
+        Ok(())
+    }
+}
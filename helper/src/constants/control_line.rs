@@ -1,76 +1,77 @@
 #[allow(unused, clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ControlLine {
-    /// Reset, same as the reset button.
+    /// Reset (`Reset`), same as the reset button.
     RST,
-    /// Micro Reset, reset the step counter.
+    /// Micro Reset (`Micro_Reset`), reset the step counter.
     MRST,
-    /// Register Output Enable, put the content of the selected register (see ROH and ROL) on the bus.
+    /// Register Output Enable (`Register_Out_Enable`), put the content of the selected register (see ROH and ROL) on the bus.
     ROE,
-    /// Register Output Low, select the low byte of the register to output.
+    /// Register Output Low (`Register_Out_L`), select the low byte of the register to output.
     ROL,
-    /// Register Output High, select the high byte of the register to output.
+    /// Register Output High (`Register_Out_H`), select the high byte of the register to output.
     ROH,
-    /// Register Input Enable, put the content of the bus on the selected register (see RIL and RIH).
+    /// Register Input Enable (`Register_In_Enable`), put the content of the bus on the selected register (see RIL and RIH).
     RIE,
-    /// Register Input Low, select the low byte of the register to input.
+    /// Register Input Low (`Register_In_L`), select the low byte of the register to input.
     RIL,
-    /// Register Input High, select the high byte of the register to input.
+    /// Register Input High (`Register_In_H`), select the high byte of the register to input.
     RIH,
-    /// Halt, stop the clock.
+    /// Halt (`Halt`), stop the clock.
     HLT,
-    /// Memory In, put the content of the bus into the RAM.
+    /// Memory In (`Memory_In`), put the content of the bus into the RAM.
     MI,
-    /// Write Memory Enable, write the content of the bus into the selected RAM address register (see WMS and MIS).
+    /// Write Memory Enable (`Write_Memory_Enable`), write the content of the bus into the selected RAM address register (see WMS and MIS).
     WME,
-    /// Write Memory Select, set to 0 to select the RAM address register low, set to 1 to select the RAM address register high.
+    /// Write Memory Select (`Write_Memory_Select`), set to 0 to select the RAM address register low, set to 1 to select the RAM address register high.
     WMS,
-    /// Memory Input Select, set to 0 to select the select the RAM address from the Instruction Pointer register, set to 1 to select the RAM address from the RAM address register.
+    /// Memory Input Select (`Memory_Input_Select`), set to 0 to select the select the RAM address from the Instruction Pointer register, set to 1 to select the RAM address from the RAM address register.
     MIS,
-    /// Instruction Pointer Advance, advance the Instruction Pointer register by 1.
+    /// Instruction Pointer Advance (`IP_Advance`), advance the Instruction Pointer register by 1.
     IPA,
-    /// Memory Output, put the content of the selected RAM address register (see WMS and MIS) on the bus.
+    /// Memory Output (`Memory_Out`), put the content of the selected RAM address register (see WMS and MIS) on the bus.
     MO,
-    /// Instruction Register Enable, put the content of the bus into the Instruction Register.
+    /// Instruction Register Enable (`Instruction_Register_Enable`), put the content of the bus into the Instruction Register.
     IRE,
-    /// ALU 1 Input, put the content of the bus into the ALU 1 register.
+    /// ALU 1 Input (`Alu_1_In`), put the content of the bus into the ALU 1 register.
     A1I,
-    /// ALU 2 Input, put the content of the bus into the ALU 2 register.
+    /// ALU 2 Input (`Alu_2_In`), put the content of the bus into the ALU 2 register.
     A2I,
-    /// Carry Input, set the 1 to perform an operation with carry.
+    /// Carry Input (`Carry_In`), set the 1 to perform an operation with carry.
     CI,
-    /// ALU Operation Low, select the low bit of the ALU operation.
+    /// ALU Operation Low (`Alu_Op_L`), select the low bit of the ALU operation.
     AOPL,
-    /// ALU Operation High, select the high bit of the ALU operation.
+    /// ALU Operation High (`Alu_Op_H`), select the high bit of the ALU operation.
     AOPH,
-    /// ALU Out, put the content of the ALU into the bus.
+    /// ALU Out (`Alu_Out`), put the content of the ALU into the bus.
     AO,
-    /// Instruction Pointer Enable, ???.
+    /// Instruction Pointer Enable (`IP_Enable`), set to 1 to read or write the selected Instruction Pointer register (depending on IPO and IPS): without IPO, it loads the content of the bus.
     IPE,
-    /// Instruction Pointer Output, put the content of the Instruction Pointer register on the bus.
+    /// Instruction Pointer Output (`IP_Out`), put the content of the Instruction Pointer register on the bus.
     IPO,
-    /// Instruction Pointer Select, set to 0 to select the Instruction Pointer Low register, set to 1 to select the Instruction Pointer High register.
+    /// Instruction Pointer Select (`IP_Select`), set to 0 to select the Instruction Pointer Low register, set to 1 to select the Instruction Pointer High register.
     IPS,
-    /// One Out, output 0x01 on the bus.
+    /// One Out (`One_Out`), output 0x01 on the bus.
     ONEO,
-    /// FF Out, output 0xFF on the bus.
+    /// FF Out (`FF_Out`), output 0xFF on the bus.
     FFO,
-    /// Stack Pointer Enable, set to 1 to read or write the selectedStack Pointer register (depending on SPI and SPS).
+    /// Stack Pointer Enable (`SP_Enable`), set to 1 to read or write the selectedStack Pointer register (depending on SPI and SPS).
     SPE,
-    /// Stack Pointer In, put the content of the bus into the selected Stack Pointer register (see SPS).
+    /// Stack Pointer In (`SP_In`), put the content of the bus into the selected Stack Pointer register (see SPS).
     SPI,
-    /// Stack Pointer Select, set to 0 to select the Stack Pointer Low register, set to 1 to select the Stack Pointer High register.
+    /// Stack Pointer Select (`SP_Select`), set to 0 to select the Stack Pointer Low register, set to 1 to select the Stack Pointer High register.
     SPS,
-    /// Jump Pointer In, put the content of the bus into the selected Jump Pointer register (see JMPS).
+    /// Jump Pointer In (`JMP_In`), put the content of the bus into the selected Jump Pointer register (see JMPS).
     JMPI,
-    /// Jump Pointer Enable, set to 1 to read or write the selected Jump Pointer register (depending on JMPI and JMPS).
+    /// Jump Pointer Enable (`JMP_Enable`), set to 1 to read or write the selected Jump Pointer register (depending on JMPI and JMPS).
     JMPE,
-    /// Jump Pointer Select, set to 0 to select the Jump Pointer Low register, set to 1 to select the Jump Pointer High register.
+    /// Jump Pointer Select (`JMP_Select`), set to 0 to select the Jump Pointer Low register, set to 1 to select the Jump Pointer High register.
     JMPS,
-    /// Return register In, put the content of the bus into the selected Return register (see RETS).
+    /// Return register In (`RET_In`), put the content of the bus into the selected Return register (see RETS).
     RETI,
-    /// Return register Enable, set to 1 to read or write the selected Return register (depending on RETI and RETS).
+    /// Return register Enable (`RET_Enable`), set to 1 to read or write the selected Return register (depending on RETI and RETS).
     RETE,
-    /// Return register Select, set to 0 to select the Return Low register, set to 1 to select the Return High register.
+    /// Return register Select (`RET_Select`), set to 0 to select the Return Low register, set to 1 to select the Return High register.
     RETS,
 }
 
@@ -115,4 +116,172 @@ impl ControlLine {
             ControlLine::RETS => 1 << 37,
         }
     }
+
+    /// The label of the tunnel the line drives in `schema.circ`, the one in the doc comment of the line: RST goes
+    /// through the OR gate of the reset button.
+    pub fn tunnel(&self) -> &'static str {
+        match self {
+            ControlLine::RST => "Reset",
+            ControlLine::MRST => "Micro_Reset",
+            ControlLine::ROE => "Register_Out_Enable",
+            ControlLine::ROL => "Register_Out_L",
+            ControlLine::ROH => "Register_Out_H",
+            ControlLine::RIE => "Register_In_Enable",
+            ControlLine::RIL => "Register_In_L",
+            ControlLine::RIH => "Register_In_H",
+            ControlLine::HLT => "Halt",
+            ControlLine::MI => "Memory_In",
+            ControlLine::WME => "Write_Memory_Enable",
+            ControlLine::WMS => "Write_Memory_Select",
+            ControlLine::MIS => "Memory_Input_Select",
+            ControlLine::IPA => "IP_Advance",
+            ControlLine::MO => "Memory_Out",
+            ControlLine::IRE => "Instruction_Register_Enable",
+            ControlLine::A1I => "Alu_1_In",
+            ControlLine::A2I => "Alu_2_In",
+            ControlLine::CI => "Carry_In",
+            ControlLine::AOPL => "Alu_Op_L",
+            ControlLine::AOPH => "Alu_Op_H",
+            ControlLine::AO => "Alu_Out",
+            ControlLine::IPE => "IP_Enable",
+            ControlLine::IPO => "IP_Out",
+            ControlLine::IPS => "IP_Select",
+            ControlLine::ONEO => "One_Out",
+            ControlLine::FFO => "FF_Out",
+            ControlLine::SPE => "SP_Enable",
+            ControlLine::SPI => "SP_In",
+            ControlLine::SPS => "SP_Select",
+            ControlLine::JMPI => "JMP_In",
+            ControlLine::JMPE => "JMP_Enable",
+            ControlLine::JMPS => "JMP_Select",
+            ControlLine::RETI => "RET_In",
+            ControlLine::RETE => "RET_Enable",
+            ControlLine::RETS => "RET_Select",
+        }
+    }
+}
+
+/// Every control line, in the order of their bits.
+pub const CONTROL_LINES: [ControlLine; 36] = [
+    ControlLine::RST,
+    ControlLine::MRST,
+    ControlLine::ROE,
+    ControlLine::ROL,
+    ControlLine::ROH,
+    ControlLine::RIE,
+    ControlLine::RIL,
+    ControlLine::RIH,
+    ControlLine::HLT,
+    ControlLine::MI,
+    ControlLine::WME,
+    ControlLine::WMS,
+    ControlLine::MIS,
+    ControlLine::IPA,
+    ControlLine::MO,
+    ControlLine::IRE,
+    ControlLine::A1I,
+    ControlLine::A2I,
+    ControlLine::CI,
+    ControlLine::AOPL,
+    ControlLine::AOPH,
+    ControlLine::AO,
+    ControlLine::IPE,
+    ControlLine::IPO,
+    ControlLine::IPS,
+    ControlLine::ONEO,
+    ControlLine::FFO,
+    ControlLine::SPE,
+    ControlLine::SPI,
+    ControlLine::SPS,
+    ControlLine::JMPI,
+    ControlLine::JMPE,
+    ControlLine::JMPS,
+    ControlLine::RETI,
+    ControlLine::RETE,
+    ControlLine::RETS,
+];
+
+/// A component that puts a value on the bus.
+pub struct BusDriver {
+    /// The component drives the bus when all of these lines are active...
+    pub lines: &'static [ControlLine],
+    /// ...unless this one is active too: it turns the component into a receiver.
+    pub unless: Option<ControlLine>,
+}
+
+/// Everything that can drive the bus: at most one of them can be active in a control word.
+pub const BUS_DRIVERS: [BusDriver; 9] = [
+    BusDriver { lines: &[ControlLine::ROE], unless: None },
+    BusDriver { lines: &[ControlLine::MO], unless: None },
+    BusDriver { lines: &[ControlLine::AO], unless: None },
+    BusDriver { lines: &[ControlLine::ONEO], unless: None },
+    BusDriver { lines: &[ControlLine::FFO], unless: None },
+    BusDriver { lines: &[ControlLine::IPE, ControlLine::IPO], unless: None },
+    BusDriver { lines: &[ControlLine::SPE], unless: Some(ControlLine::SPI) },
+    BusDriver { lines: &[ControlLine::JMPE], unless: Some(ControlLine::JMPI) },
+    BusDriver { lines: &[ControlLine::RETE], unless: Some(ControlLine::RETI) },
+];
+
+/// Lines that only select what another line does: on their own they do nothing, and are most likely a mistake.
+pub const REQUIRED_ENABLES: [(ControlLine, &[ControlLine]); 14] = [
+    (ControlLine::ROL, &[ControlLine::ROE]),
+    (ControlLine::ROH, &[ControlLine::ROE]),
+    (ControlLine::RIL, &[ControlLine::RIE]),
+    (ControlLine::RIH, &[ControlLine::RIE]),
+    (ControlLine::WMS, &[ControlLine::WME]),
+    (ControlLine::MIS, &[ControlLine::MI, ControlLine::MO]),
+    (ControlLine::IPO, &[ControlLine::IPE]),
+    (ControlLine::IPS, &[ControlLine::IPE]),
+    (ControlLine::SPI, &[ControlLine::SPE]),
+    (ControlLine::SPS, &[ControlLine::SPE]),
+    (ControlLine::JMPI, &[ControlLine::JMPE]),
+    (ControlLine::JMPS, &[ControlLine::JMPE]),
+    (ControlLine::RETI, &[ControlLine::RETE]),
+    (ControlLine::RETS, &[ControlLine::RETE]),
+];
+
+/// Lines that fight over the same component when they are active together.
+pub const EXCLUSIVE_LINES: [(ControlLine, ControlLine); 3] = [
+    // The RAM has a single data port.
+    (ControlLine::MI, ControlLine::MO),
+    // IPA drives the count input of the IP counter and IPE, without IPO, its load input: when both are set the
+    // counter counts down instead of loading the bus. IPE with IPO only reads IP, but no step needs to advance IP
+    // while putting it on the bus, so the two are kept apart altogether.
+    (ControlLine::IPA, ControlLine::IPE),
+    // The reset lines win over everything else, a word with both has no clear meaning.
+    (ControlLine::RST, ControlLine::MRST),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tunnels_match_documentation() {
+        // The doc comment of every variant, by the name of the variant.
+        let source = include_str!("control_line.rs");
+        let mut documentation = Vec::new();
+        let mut comment = "";
+        for line in source.lines() {
+            let line = line.trim();
+            if let Some(text) = line.strip_prefix("/// ") {
+                comment = text;
+            } else if let Some(name) = line.strip_suffix(',').filter(|name| name.chars().all(char::is_alphanumeric)) {
+                documentation.push((name, comment));
+            }
+        }
+
+        for line in CONTROL_LINES {
+            let name = format!("{:?}", line);
+            let Some((_, comment)) = documentation.iter().find(|(candidate, _)| *candidate == name) else {
+                panic!("{} has no doc comment", name);
+            };
+            assert!(
+                comment.contains(&format!("(`{}`)", line.tunnel())),
+                "the doc comment of {} does not name its tunnel {}",
+                name,
+                line.tunnel()
+            );
+        }
+    }
 }
@@ -1,6 +1,6 @@
-use crate::constants::control_line::ControlLine;
+use crate::constants::control_line::{ControlLine, BUS_DRIVERS, CONTROL_LINES, EXCLUSIVE_LINES, REQUIRED_ENABLES};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct ControlWord(u64);
 
 impl ControlWord {
@@ -12,9 +12,52 @@ impl ControlWord {
         Self(value)
     }
 
+    /// The word stored in the control ROMs, the bit of every line set.
+    pub fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
     pub fn value(&self) -> u64 {
         self.0
     }
+
+    pub fn has(&self, line: ControlLine) -> bool {
+        (self.0 & line.value()) != 0
+    }
+
+    /// The active lines, in the order of their bits.
+    pub fn lines(&self) -> Vec<ControlLine> {
+        CONTROL_LINES.into_iter().filter(|line| self.has(*line)).collect()
+    }
+
+    /// The hardware rules declared next to `ControlLine` that this word breaks.
+    pub fn violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        let drivers: Vec<&[ControlLine]> = BUS_DRIVERS
+            .iter()
+            .filter(|driver| driver.lines.iter().all(|line| self.has(*line)))
+            .filter(|driver| !driver.unless.is_some_and(|line| self.has(line)))
+            .map(|driver| driver.lines)
+            .collect();
+        if drivers.len() > 1 {
+            violations.push(format!("bus conflict between {:?}", drivers));
+        }
+
+        for (line, enables) in REQUIRED_ENABLES.iter() {
+            if self.has(*line) && !enables.iter().any(|enable| self.has(*enable)) {
+                violations.push(format!("{:?} without {:?}", line, enables));
+            }
+        }
+
+        for (first, second) in EXCLUSIVE_LINES.iter() {
+            if self.has(*first) && self.has(*second) {
+                violations.push(format!("{:?} together with {:?}", first, second));
+            }
+        }
+
+        violations
+    }
 }
 
 impl core::fmt::Debug for ControlWord {
@@ -42,3 +85,23 @@ impl<const N: usize> From<[ControlLine; N]> for ControlWord {
         Self::from_lines(&lines)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_violations() {
+        let legal: ControlWord = [ControlLine::ROE, ControlLine::ROL, ControlLine::SPE, ControlLine::SPI].into();
+        assert!(legal.violations().is_empty());
+
+        let conflict: ControlWord = [ControlLine::AO, ControlLine::IPE, ControlLine::IPO].into();
+        assert_eq!(conflict.violations(), vec!["bus conflict between [[AO], [IPE, IPO]]"]);
+
+        let missing_enable: ControlWord = [ControlLine::MO, ControlLine::WMS].into();
+        assert_eq!(missing_enable.violations(), vec!["WMS without [WME]"]);
+
+        let exclusive: ControlWord = [ControlLine::MIS, ControlLine::MI, ControlLine::MO].into();
+        assert_eq!(exclusive.violations(), vec!["MI together with MO"]);
+    }
+}
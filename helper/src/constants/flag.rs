@@ -1,4 +1,5 @@
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Flag {
     /// Flag Zero, 1 if the output of the ALU is 0x00, 0 otherwise.
     FZ,
@@ -33,6 +34,7 @@ impl Flag {
 //     }
 // }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Flags(u8);
 
 impl Flags {
@@ -43,6 +45,10 @@ impl Flags {
             .fold(0, |acc, val| acc | val);
         Self(value)
     }
+
+    pub fn value(&self) -> u8 {
+        self.0
+    }
 }
 
 impl From<Vec<Flag>> for Flags {
@@ -57,6 +63,12 @@ impl<const N: usize> From<[Flag; N]> for Flags {
     }
 }
 
+impl FromIterator<Flag> for Flags {
+    fn from_iter<T: IntoIterator<Item = Flag>>(flags: T) -> Self {
+        Self(flags.into_iter().fold(0, |acc, flag| acc | flag.value()))
+    }
+}
+
 impl From<u8> for Flags {
     fn from(value: u8) -> Self {
         if value > 0b1111 {
@@ -6,7 +6,7 @@ use crate::starts_with::StartsWith;
 use std::hash::Hash;
 
 #[allow(unused, clippy::upper_case_acronyms)]
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MachineInstruction {
     /// Move, move the content of a general register to another general register.
     MV {
@@ -83,6 +83,9 @@ pub enum MachineInstruction {
     NOP,
 }
 
+/// The encoding the assembler uses for NOP: every unused opcode decodes to NOP too.
+pub const NOP_OPCODE: u8 = 0b11_11_11_10;
+
 impl From<u8> for MachineInstruction {
     fn from(value: u8) -> Self {
         match value {
@@ -181,6 +184,86 @@ impl From<u8> for MachineInstruction {
     }
 }
 
+impl MachineInstruction {
+    /// How many bytes the instruction takes in memory, operands included.
+    pub fn size(&self) -> u16 {
+        match self {
+            MachineInstruction::LI { .. }
+            | MachineInstruction::ADDI { .. }
+            | MachineInstruction::JCR
+            | MachineInstruction::JZR
+            | MachineInstruction::JNR
+            | MachineInstruction::JLTR
+            | MachineInstruction::SPOF => 2,
+            MachineInstruction::PJMP => 3,
+            _ => 1,
+        }
+    }
+
+    /// Whether the byte is not the encoding of any instruction, and only runs as a NOP because of how the opcodes
+    /// are decoded.
+    pub fn is_unused_opcode(opcode: u8) -> bool {
+        opcode != NOP_OPCODE && MachineInstruction::from(opcode) == MachineInstruction::NOP
+    }
+
+    /// The flag a conditional jump is taken on.
+    pub fn condition(&self) -> Option<Flag> {
+        match self {
+            MachineInstruction::JCR => Some(Flag::CO),
+            MachineInstruction::JZR => Some(Flag::FZ),
+            MachineInstruction::JNR => Some(Flag::NEG),
+            MachineInstruction::JLTR => Some(Flag::A2G1),
+            _ => None,
+        }
+    }
+}
+
+/// Steps shared by the relative jumps: the offset byte is added to IP low, then IP high is adjusted by +1 (positive
+/// offset with carry), -1 (negative offset without carry) or left alone.
+///
+/// The offset counts from the offset byte itself, which is how `BinaryProgram` assembles it: IP still points at that
+/// byte when it is copied into A1, so the addition takes no carry in.
+///
+/// The condition is only sampled at the first step: from then on the flags follow the scratch values in the ALU.
+fn relative_jump(taken: bool, flags: Flags) -> Vec<ControlWord> {
+    vec![
+        if taken {
+            [ControlLine::A1I, ControlLine::IPE, ControlLine::IPO].into()
+        } else {
+            [ControlLine::IPA, ControlLine::MRST].into()
+        },
+        [ControlLine::MO, ControlLine::A2I].into(),
+        [ControlLine::AO, ControlLine::IPE].into(),
+        // A1 is the new IP low: it is smaller than the offset only if the addition carried.
+        [ControlLine::IPE, ControlLine::IPO, ControlLine::A1I].into(),
+        if flags.has(Flag::A2G1) {
+            [ControlLine::A1I].into()
+        } else {
+            [ControlLine::FFO, ControlLine::A1I].into()
+        },
+        // With carry A1 is 0x00, without it is 0xFF: the flags still tell the two apart.
+        if flags.has(Flag::A2G1) {
+            [ControlLine::AO, ControlLine::A1I].into()
+        } else {
+            [ControlLine::A1I].into()
+        },
+        // With carry A1 = A2 = offset, without carry A1 = 0x00 and A2 = offset: the sign of the offset decides.
+        if flags.has(Flag::A2G1) {
+            if flags.has(Flag::NEG) {
+                [ControlLine::FFO, ControlLine::A1I].into()
+            } else {
+                [ControlLine::MRST].into()
+            }
+        } else if flags.has(Flag::CO) || flags.has(Flag::FZ) {
+            [ControlLine::MRST].into()
+        } else {
+            [ControlLine::ONEO, ControlLine::A1I].into()
+        },
+        [ControlLine::IPE, ControlLine::IPO, ControlLine::IPS, ControlLine::A2I].into(),
+        [ControlLine::AO, ControlLine::IPE, ControlLine::IPS].into(),
+    ]
+}
+
 pub fn steps(instruction: MachineInstruction, flags: Flags) -> [ControlWord; 16] {
     let instruction_steps = match instruction {
         MachineInstruction::NOP => vec![],
@@ -261,7 +344,7 @@ pub fn steps(instruction: MachineInstruction, flags: Flags) -> [ControlWord; 16]
         MachineInstruction::NEG { dst } => vec![
             [ControlLine::A1I].into(),
             dst.register_out() | [ControlLine::A2I].into(),
-            dst.register_in() | [ControlLine::AO, ControlLine::AOPL, ControlLine::CI].into(),
+            dst.register_in() | [ControlLine::AO, ControlLine::AOPL].into(),
         ],
         MachineInstruction::PJMP => vec![
             [ControlLine::MO, ControlLine::JMPE, ControlLine::JMPI, ControlLine::JMPS].into(),
@@ -283,94 +366,10 @@ pub fn steps(instruction: MachineInstruction, flags: Flags) -> [ControlWord; 16]
             [ControlLine::RETE, ControlLine::IPE].into(),
             [ControlLine::RETE, ControlLine::RETS, ControlLine::IPE, ControlLine::IPS].into(),
         ],
-        MachineInstruction::JCR => {
-            vec![
-                if flags.has(Flag::CO) {
-                    [ControlLine::A1I, ControlLine::IPE, ControlLine::IPO].into()
-                } else {
-                    [ControlLine::IPA, ControlLine::MRST].into()
-                },
-                [ControlLine::MO, ControlLine::A2I].into(),
-                [ControlLine::AO, ControlLine::CI, ControlLine::IPE].into(),
-                if flags.has(Flag::CO) {
-                    if flags.has(Flag::A2G1) {
-                        [ControlLine::FFO, ControlLine::A2I].into()
-                    } else {
-                        [ControlLine::ONEO, ControlLine::A2I].into()
-                    }
-                } else {
-                    [ControlLine::MRST].into()
-                },
-                [ControlLine::IPE, ControlLine::IPO, ControlLine::IPS, ControlLine::A1I].into(),
-                [ControlLine::AO, ControlLine::IPE, ControlLine::IPS].into(),
-            ]
-        }
-        MachineInstruction::JZR => {
-            vec![
-                if flags.has(Flag::FZ) {
-                    [ControlLine::A1I, ControlLine::IPE, ControlLine::IPO].into()
-                } else {
-                    [ControlLine::IPA, ControlLine::MRST].into()
-                },
-                [ControlLine::MO, ControlLine::A2I].into(),
-                [ControlLine::AO, ControlLine::CI, ControlLine::IPE].into(),
-                if flags.has(Flag::CO) {
-                    if flags.has(Flag::A2G1) {
-                        [ControlLine::FFO, ControlLine::A2I].into()
-                    } else {
-                        [ControlLine::ONEO, ControlLine::A2I].into()
-                    }
-                } else {
-                    [ControlLine::MRST].into()
-                },
-                [ControlLine::IPE, ControlLine::IPO, ControlLine::IPS, ControlLine::A1I].into(),
-                [ControlLine::AO, ControlLine::IPE, ControlLine::IPS].into(),
-            ]
-        }
-        MachineInstruction::JNR => {
-            vec![
-                if flags.has(Flag::NEG) {
-                    [ControlLine::A1I, ControlLine::IPE, ControlLine::IPO].into()
-                } else {
-                    [ControlLine::IPA, ControlLine::MRST].into()
-                },
-                [ControlLine::MO, ControlLine::A2I].into(),
-                [ControlLine::AO, ControlLine::CI, ControlLine::IPE].into(),
-                if flags.has(Flag::CO) {
-                    if flags.has(Flag::A2G1) {
-                        [ControlLine::FFO, ControlLine::A2I].into()
-                    } else {
-                        [ControlLine::ONEO, ControlLine::A2I].into()
-                    }
-                } else {
-                    [ControlLine::MRST].into()
-                },
-                [ControlLine::IPE, ControlLine::IPO, ControlLine::IPS, ControlLine::A1I].into(),
-                [ControlLine::AO, ControlLine::IPE, ControlLine::IPS].into(),
-            ]
-        }
-        MachineInstruction::JLTR => {
-            vec![
-                if flags.has(Flag::A2G1) {
-                    [ControlLine::A1I, ControlLine::IPE, ControlLine::IPO].into()
-                } else {
-                    [ControlLine::IPA, ControlLine::MRST].into()
-                },
-                [ControlLine::MO, ControlLine::A2I].into(),
-                [ControlLine::AO, ControlLine::CI, ControlLine::IPE].into(),
-                if flags.has(Flag::CO) {
-                    if flags.has(Flag::A2G1) {
-                        [ControlLine::FFO, ControlLine::A2I].into()
-                    } else {
-                        [ControlLine::ONEO, ControlLine::A2I].into()
-                    }
-                } else {
-                    [ControlLine::MRST].into()
-                },
-                [ControlLine::IPE, ControlLine::IPO, ControlLine::IPS, ControlLine::A1I].into(),
-                [ControlLine::AO, ControlLine::IPE, ControlLine::IPS].into(),
-            ]
-        }
+        MachineInstruction::JCR => relative_jump(flags.has(Flag::CO), flags),
+        MachineInstruction::JZR => relative_jump(flags.has(Flag::FZ), flags),
+        MachineInstruction::JNR => relative_jump(flags.has(Flag::NEG), flags),
+        MachineInstruction::JLTR => relative_jump(flags.has(Flag::A2G1), flags),
         MachineInstruction::SPSL { src } => vec![
             src.register_out() | [ControlLine::SPE, ControlLine::SPI].into(),
         ],
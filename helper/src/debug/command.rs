@@ -0,0 +1,160 @@
+use crate::emulate::watchpoint::{Comparison, Condition, Operand, WatchKind};
+use eyre::bail;
+
+pub const HELP: &str = "\
+break [<address|label>]     set a breakpoint, or list them without arguments (b)
+delete <address|label>      remove a breakpoint (d)
+continue                    run until a breakpoint or HLT (c)
+step [<count>]              execute whole instructions (s)
+microstep [<count>]         execute single clock cycles (ms)
+back [<count>]              undo the last steps, instructions or clock cycles alike (bs)
+reverse-continue            run backwards until a breakpoint (rc)
+who <register|address|label>
+                            which step last changed a register or a byte of memory (addresses start with 0x)
+watch [<address|label> [<length>]]
+                            stop after the program writes memory, or list the watchpoints without arguments
+rwatch <address|label> [<length>]
+                            stop after the program reads memory
+awatch <address|label> [<length>]
+                            stop after the program reads or writes memory
+watch <register|flag> <comparison> <value>
+                            stop when a condition becomes true, like watch SP < FE00 or watch FZ == 1
+unwatch <number>            remove a watchpoint
+registers                   show registers, flags and the microcode step (r)
+memory <address|label> [<length>]
+                            dump memory, 64 bytes by default (x)
+restart                     reload the program and start over
+save <file>                 save a snapshot of the machine
+load <file>                 resume from a snapshot
+help                        show this message (h)
+quit                        leave the debugger (q)
+Addresses are hexadecimal (0x8000 or 8000), labels are written like in the source (:loop, .halt).";
+
+const DEFAULT_DUMP_LENGTH: u16 = 0x40;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Break(Option<String>),
+    Delete(String),
+    Continue,
+    Step(u64),
+    Microstep(u64),
+    Back(u64),
+    ReverseContinue,
+    Who(String),
+    Watch(Option<Watch>),
+    Unwatch(usize),
+    Registers,
+    Memory(String, u16),
+    Restart,
+    Save(String),
+    Load(String),
+    Help,
+    Quit,
+}
+
+/// A watchpoint as typed by the user: the locations are resolved by the debugger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watch {
+    Memory(WatchKind, String, u16),
+    Condition(Condition),
+}
+
+impl Command {
+    pub fn parse(line: &str) -> eyre::Result<Self> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let command = match words.as_slice() {
+            ["break" | "b"] => Command::Break(None),
+            ["break" | "b", location] => Command::Break(Some(location.to_string())),
+            ["delete" | "d", location] => Command::Delete(location.to_string()),
+            ["continue" | "c"] => Command::Continue,
+            ["step" | "s"] => Command::Step(1),
+            ["step" | "s", count] => Command::Step(count.parse()?),
+            ["microstep" | "ms"] => Command::Microstep(1),
+            ["microstep" | "ms", count] => Command::Microstep(count.parse()?),
+            ["back" | "bs"] => Command::Back(1),
+            ["back" | "bs", count] => Command::Back(count.parse()?),
+            ["reverse-continue" | "rc"] => Command::ReverseContinue,
+            ["who", location] => Command::Who(location.to_string()),
+            ["watch"] => Command::Watch(None),
+            [kind @ ("watch" | "rwatch" | "awatch"), location] => {
+                Command::Watch(Some(Watch::Memory(watch_kind(kind), location.to_string(), 1)))
+            }
+            [kind @ ("watch" | "rwatch" | "awatch"), location, length] => Command::Watch(Some(Watch::Memory(
+                watch_kind(kind),
+                location.to_string(),
+                parse_hex(length)?,
+            ))),
+            ["watch", operand, comparison, value] => Command::Watch(Some(Watch::Condition(Condition {
+                operand: Operand::parse(operand)?,
+                comparison: Comparison::parse(comparison)?,
+                value: parse_hex(value)?,
+            }))),
+            ["unwatch", number] => Command::Unwatch(number.parse()?),
+            ["registers" | "r"] => Command::Registers,
+            ["memory" | "x", location] => Command::Memory(location.to_string(), DEFAULT_DUMP_LENGTH),
+            ["memory" | "x", location, length] => Command::Memory(location.to_string(), parse_hex(length)?),
+            ["restart"] => Command::Restart,
+            ["save", file] => Command::Save(file.to_string()),
+            ["load", file] => Command::Load(file.to_string()),
+            ["help" | "h"] => Command::Help,
+            ["quit" | "q"] => Command::Quit,
+            _ => bail!("Unknown command: {} (try help)", line.trim()),
+        };
+
+        Ok(command)
+    }
+}
+
+fn watch_kind(command: &str) -> WatchKind {
+    match command {
+        "rwatch" => WatchKind::Read,
+        "awatch" => WatchKind::Access,
+        _ => WatchKind::Write,
+    }
+}
+
+/// Parse a hexadecimal number, with or without the `0x` prefix.
+pub fn parse_hex(input: &str) -> eyre::Result<u16> {
+    let digits = input.strip_prefix("0x").unwrap_or(input);
+    match u16::from_str_radix(digits, 16) {
+        Ok(value) => Ok(value),
+        Err(_) => bail!("Invalid hexadecimal number: {}", input),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> eyre::Result<()> {
+        assert_eq!(Command::parse("b .halt")?, Command::Break(Some(".halt".to_string())));
+        assert_eq!(Command::parse("  step 10 ")?, Command::Step(10));
+        assert_eq!(
+            Command::parse("x 0x8000 10")?,
+            Command::Memory("0x8000".to_string(), 0x10)
+        );
+        assert_eq!(Command::parse("save crash.snapshot")?, Command::Save("crash.snapshot".to_string()));
+        assert_eq!(Command::parse("bs 3")?, Command::Back(3));
+        assert_eq!(Command::parse("who 0x8000")?, Command::Who("0x8000".to_string()));
+        assert_eq!(
+            Command::parse("rwatch 0x8000 4")?,
+            Command::Watch(Some(Watch::Memory(WatchKind::Read, "0x8000".to_string(), 4)))
+        );
+        assert_eq!(
+            Command::parse("watch SP < 0xFE00")?,
+            Command::Watch(Some(Watch::Condition(Condition {
+                operand: Operand::StackPointer,
+                comparison: Comparison::Less,
+                value: 0xFE00,
+            })))
+        );
+        assert!(Command::parse("watch SP = 0xFE00").is_err());
+        assert!(Command::parse("step many").is_err());
+        assert!(Command::parse("jump").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use crate::assemble::assemble::assemble_with_symbols;
+use crate::assemble::binary_program::BinaryProgram;
+use crate::assemble::symbols::Symbols;
+use crate::debug::command::Command;
+use crate::debug::debugger::Debugger;
+use crate::debug::gdb::{serve, GdbStub};
+use crate::emulate::devices::Keyboard;
+use eyre::bail;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::net::TcpListener;
+
+const PROMPT: &str = "(debug) ";
+const DEFAULT_GDB_PORT: u16 = 1234;
+
+pub fn debug(args: &[String]) -> eyre::Result<()> {
+    let (file, input) = match args {
+        [file] => (file, None),
+        [file, option, input] if option == "--input" => (file, Some(input)),
+        _ => bail!("Usage: debug <file.as> [--input <file>]"),
+    };
+
+    let (program, symbols) = assemble_file(file)?;
+    let mut debugger = Debugger::new(program.0, symbols)?;
+    if let Some(input) = input {
+        debugger.set_input(fs::read(input)?);
+    }
+
+    println!("{}", debugger.execute(Command::Restart)?);
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut last_command: Option<Command> = None;
+    loop {
+        print!("{}", PROMPT);
+        std::io::stdout().flush()?;
+
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        let line = line?;
+
+        // An empty line repeats the last command, handy to keep stepping.
+        let command = if line.trim().is_empty() {
+            match &last_command {
+                Some(command) => Ok(command.clone()),
+                None => continue,
+            }
+        } else {
+            Command::parse(&line)
+        };
+
+        match command {
+            Ok(Command::Quit) => return Ok(()),
+            Ok(command) => {
+                last_command = Some(command.clone());
+                match debugger.execute(command) {
+                    Ok(output) => println!("{}", output),
+                    Err(error) => println!("Error: {}", error),
+                }
+            }
+            Err(error) => println!("Error: {}", error),
+        }
+    }
+}
+
+pub fn gdb(args: &[String]) -> eyre::Result<()> {
+    let (file, port) = match args {
+        [file] => (file, DEFAULT_GDB_PORT),
+        [file, option, port] if option == "--port" => (file, port.parse()?),
+        _ => bail!("Usage: gdb <file.as> [--port <port>]"),
+    };
+
+    let (program, _) = assemble_file(file)?;
+    let mut stub = GdbStub::new(&program.0)?;
+    // gdb has its own terminal: the program's console and keyboard are in this one.
+    stub.emulator.state.memory.console.echo = true;
+    stub.emulator.state.memory.keyboard = Keyboard::stdin();
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Waiting for gdb on {}", listener.local_addr()?);
+    serve(listener, &mut stub)?;
+    println!("Detached");
+
+    Ok(())
+}
+
+fn assemble_file(file: &str) -> eyre::Result<(BinaryProgram, Symbols)> {
+    let file_contents = fs::read(file)?;
+    let file_contents = String::from_utf8(file_contents)?;
+    let file_contents: &'static str = file_contents.leak();
+
+    assemble_with_symbols(file_contents)
+}
@@ -0,0 +1,622 @@
+use crate::assemble::symbols::Symbols;
+use crate::constants::machine_instruction::MachineInstruction;
+use crate::debug::command::{parse_hex, Command, Watch, HELP};
+use crate::debug::history::{History, Registers};
+use crate::emulate::devices::Keyboard;
+use crate::emulate::emulator::Emulator;
+use crate::emulate::microcode::Microcode;
+use crate::emulate::microcode_emulator::MicrocodeEmulator;
+use crate::emulate::snapshot::Snapshot;
+use crate::emulate::watchpoint::Watchpoint;
+use eyre::bail;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::sync::Arc;
+
+/// `continue` gives up after this many instructions, so that a program stuck in a loop without breakpoints does not
+/// hang the debugger.
+const MAX_CONTINUE_INSTRUCTIONS: u64 = 10_000_000;
+
+const DUMP_LINE_LENGTH: u16 = 0x10;
+
+/// How many steps can be run backwards: the oldest ones are forgotten first.
+const MAX_HISTORY: usize = 1_000_000;
+
+/// Symbolic debugger on top of the microcode emulator, so that it can step through single clock cycles too.
+pub struct Debugger {
+    pub emulator: MicrocodeEmulator,
+    symbols: Symbols,
+    program: Vec<u8>,
+    microcode: Arc<Microcode>,
+    breakpoints: BTreeSet<u16>,
+    /// By number, as shown to the user.
+    watchpoints: BTreeMap<usize, Watchpoint>,
+    next_watchpoint: usize,
+    history: History,
+    /// What the keyboard types, from the start of the program: stdin belongs to the debugger itself.
+    input: Vec<u8>,
+}
+
+impl Debugger {
+    pub fn new(program: Vec<u8>, symbols: Symbols) -> eyre::Result<Self> {
+        let microcode = Arc::new(Microcode::from_steps());
+        Ok(Debugger {
+            emulator: new_emulator(&microcode, &program)?,
+            symbols,
+            program,
+            microcode,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            next_watchpoint: 1,
+            history: History::new(MAX_HISTORY),
+            input: Vec::new(),
+        })
+    }
+
+    /// Script the keyboard, for this run and the restarted ones.
+    pub fn set_input(&mut self, input: Vec<u8>) {
+        self.emulator.state.memory.keyboard = Keyboard::scripted(&input);
+        self.input = input;
+    }
+
+    /// Execute a command, and return what should be shown to the user: what the program wrote on the console while
+    /// it ran, then the output of the command itself.
+    pub fn execute(&mut self, command: Command) -> eyre::Result<String> {
+        let output = self.execute_command(command)?;
+        let console = self.emulator.state.memory.console.take();
+        if console.is_empty() {
+            return Ok(output);
+        }
+
+        let mut console = String::from_utf8_lossy(&console).into_owned();
+        if !console.ends_with('\n') {
+            console.push('\n');
+        }
+        Ok(console + &output)
+    }
+
+    fn execute_command(&mut self, command: Command) -> eyre::Result<String> {
+        match command {
+            Command::Break(None) => Ok(self.breakpoints()),
+            Command::Break(Some(location)) => {
+                let address = self.address(&location)?;
+                self.breakpoints.insert(address);
+                Ok(format!("Breakpoint at {}", self.describe_address(address)))
+            }
+            Command::Delete(location) => {
+                let address = self.address(&location)?;
+                if !self.breakpoints.remove(&address) {
+                    bail!("No breakpoint at {}", self.describe_address(address));
+                }
+                Ok(format!("Deleted breakpoint at {}", self.describe_address(address)))
+            }
+            Command::Continue => self.continue_execution(),
+            Command::Step(count) => {
+                for _ in 0..count {
+                    if self.emulator.halted {
+                        break;
+                    }
+                    if let Some(hit) = self.advance(false)? {
+                        return Ok(format!("{}\n{}", hit, self.location()));
+                    }
+                }
+                Ok(self.location())
+            }
+            Command::Microstep(count) => self.microstep(count),
+            Command::Back(count) => {
+                for _ in 0..count {
+                    if !self.history.undo(&mut self.emulator) {
+                        return Ok(format!("Reached the start of the history\n{}", self.location()));
+                    }
+                }
+                Ok(self.location())
+            }
+            Command::ReverseContinue => {
+                while self.history.undo(&mut self.emulator) {
+                    if self.emulator.step == 0 && self.breakpoints.contains(&self.emulator.state.instruction_pointer) {
+                        return Ok(format!("Breakpoint hit\n{}", self.location()));
+                    }
+                }
+                Ok(format!("Reached the start of the history\n{}", self.location()))
+            }
+            Command::Who(location) => self.who(&location),
+            Command::Watch(None) => Ok(self.watchpoints()),
+            Command::Watch(Some(watch)) => {
+                let watchpoint = match watch {
+                    Watch::Memory(kind, location, length) => Watchpoint::Memory {
+                        start: self.address(&location)?,
+                        length,
+                        kind,
+                    },
+                    Watch::Condition(condition) => Watchpoint::Condition(condition),
+                };
+                let number = self.next_watchpoint;
+                self.next_watchpoint += 1;
+                self.watchpoints.insert(number, watchpoint);
+                Ok(format!("Watchpoint {}: {}", number, watchpoint))
+            }
+            Command::Unwatch(number) => {
+                if self.watchpoints.remove(&number).is_none() {
+                    bail!("No watchpoint {}", number);
+                }
+                Ok(format!("Deleted watchpoint {}", number))
+            }
+            Command::Registers => Ok(format!(
+                "{}IR: {:0>2x}  step: {}",
+                self.emulator.state, self.emulator.instruction_register, self.emulator.step
+            )),
+            Command::Memory(location, length) => {
+                let address = self.address(&location)?;
+                Ok(self.dump(address, length))
+            }
+            Command::Restart => {
+                self.emulator = new_emulator(&self.microcode, &self.program)?;
+                self.emulator.state.memory.keyboard = Keyboard::scripted(&self.input);
+                self.history.clear();
+                Ok(self.location())
+            }
+            Command::Save(file) => {
+                fs::write(&file, self.emulator.snapshot().to_string())?;
+                Ok(format!("Saved to {}", file))
+            }
+            Command::Load(file) => {
+                self.emulator.restore(&Snapshot::parse(&fs::read_to_string(file)?)?)?;
+                self.history.clear();
+                Ok(self.location())
+            }
+            Command::Help => Ok(HELP.to_string()),
+            Command::Quit => Ok(String::new()),
+        }
+    }
+
+    fn continue_execution(&mut self) -> eyre::Result<String> {
+        for _ in 0..MAX_CONTINUE_INSTRUCTIONS {
+            if self.emulator.halted {
+                return Ok(self.location());
+            }
+
+            // Always move forward, even when sitting on a breakpoint.
+            if let Some(hit) = self.advance(false)? {
+                return Ok(format!("{}\n{}", hit, self.location()));
+            }
+            if self.breakpoints.contains(&self.emulator.state.instruction_pointer) {
+                return Ok(format!("Breakpoint hit\n{}", self.location()));
+            }
+        }
+
+        Ok(format!(
+            "Still running after {} instructions\n{}",
+            MAX_CONTINUE_INSTRUCTIONS,
+            self.location()
+        ))
+    }
+
+    fn microstep(&mut self, count: u64) -> eyre::Result<String> {
+        let mut output = String::new();
+        for _ in 0..count {
+            if self.emulator.halted {
+                break;
+            }
+            let control_word = self.emulator.control_word()?;
+            writeln!(
+                output,
+                "IR: {:0>2x}  step: {:>2}  {:?}",
+                self.emulator.instruction_register,
+                self.emulator.step,
+                control_word.lines()
+            )?;
+            if let Some(hit) = self.advance(true)? {
+                writeln!(output, "{}", hit)?;
+                break;
+            }
+        }
+        output += &self.location();
+        Ok(output)
+    }
+
+    /// Execute an instruction, or a single clock cycle, and tell which watchpoint it hit if any.
+    fn advance(&mut self, clock: bool) -> eyre::Result<Option<String>> {
+        let held: Vec<bool> = self
+            .watchpoints
+            .values()
+            .map(|watchpoint| match watchpoint {
+                Watchpoint::Condition(condition) => condition.holds(&self.emulator.state),
+                Watchpoint::Memory { .. } => false,
+            })
+            .collect();
+
+        if clock {
+            self.history.record(&mut self.emulator, |emulator| emulator.clock())?;
+        } else {
+            self.history.record(&mut self.emulator, |emulator| emulator.step_instruction())?;
+        }
+        let accesses = self.emulator.accesses.replace(Vec::new()).unwrap_or_default();
+
+        for ((number, watchpoint), held) in self.watchpoints.iter().zip(held) {
+            if !watchpoint.triggered(held, &self.emulator.state, &accesses) {
+                continue;
+            }
+            let Some(entry) = self.history.entries.back() else {
+                continue;
+            };
+            let step = self.describe_step(&entry.before);
+            let cause = match watchpoint.hit(&accesses) {
+                Some(access) => format!("{} {}", step, access),
+                None => format!("{} after {}", watchpoint, step),
+            };
+            return Ok(Some(format!("Watchpoint {} hit: {}", number, cause)));
+        }
+        Ok(None)
+    }
+
+    /// Which step last changed a register or a byte of RAM. Addresses need their `0x` prefix, or `a` and `0xa` would
+    /// be the same thing.
+    fn who(&mut self, location: &str) -> eyre::Result<String> {
+        let current = Registers::capture(&self.emulator);
+        let found = match location.to_uppercase().as_str() {
+            _ if location.starts_with("0x") || location.starts_with(':') || location.starts_with('.') => {
+                let address = self.address(location)?;
+                self.history
+                    .last_write(address)
+                    .map(|(age, entry, old, new)| (age, entry.before, format!("{:0>2x} -> {:0>2x}", old, new)))
+            }
+            "A" | "B" | "C" | "D" => {
+                let index = (location.to_uppercase().as_bytes()[0] - b'A') as usize;
+                self.history
+                    .last_change(&current, |registers| registers.registers[index] as u16)
+                    .map(|(age, entry, old, new)| (age, entry.before, format!("{:0>2x} -> {:0>2x}", old, new)))
+            }
+            "A1" | "A2" => {
+                let second = location.ends_with('2');
+                self.history
+                    .last_change(&current, |registers| if second { registers.alu2 } else { registers.alu1 })
+                    .map(|(age, entry, old, new)| (age, entry.before, format!("{:0>2x} -> {:0>2x}", old, new)))
+            }
+            "IP" | "SP" | "JMP" | "RET" | "MEM" => {
+                let register = match location.to_uppercase().as_str() {
+                    "IP" => |registers: &Registers| registers.instruction_pointer,
+                    "SP" => |registers: &Registers| registers.stack_pointer,
+                    "JMP" => |registers: &Registers| registers.jump_register,
+                    "RET" => |registers: &Registers| registers.return_register,
+                    _ => |registers: &Registers| registers.memory_register,
+                };
+                self.history
+                    .last_change(&current, register)
+                    .map(|(age, entry, old, new)| (age, entry.before, format!("{:0>4x} -> {:0>4x}", old, new)))
+            }
+            _ => bail!("Unknown register: {} (addresses start with 0x)", location),
+        };
+
+        match found {
+            Some((age, before, change)) => Ok(format!(
+                "{} {} steps ago ({}) by {}",
+                location,
+                age,
+                change,
+                self.describe_step(&before)
+            )),
+            None => Ok(format!(
+                "{} did not change in the last {} steps",
+                location,
+                self.history.entries.len()
+            )),
+        }
+    }
+
+    /// The instruction a recorded step was executing.
+    fn describe_step(&self, before: &Registers) -> String {
+        let address = before.instruction_pointer;
+        if before.step == 0 {
+            let instruction = MachineInstruction::from(self.emulator.state.memory.peek(address));
+            format!("{}  {:?}", self.describe_address(address), instruction)
+        } else {
+            let instruction = MachineInstruction::from(before.instruction_register);
+            format!("{}  in {:?}, step {}", self.describe_address(address), instruction, before.step)
+        }
+    }
+
+    /// Where the machine is, and what it is going to execute next.
+    fn location(&mut self) -> String {
+        let address = self.emulator.state.instruction_pointer;
+        let mut location = if self.emulator.step == 0 {
+            let instruction = MachineInstruction::from(self.emulator.state.memory.peek(address));
+            format!("{}  {:?}", self.describe_address(address), instruction)
+        } else {
+            let instruction = MachineInstruction::from(self.emulator.instruction_register);
+            format!(
+                "{}  in {:?}, step {}",
+                self.describe_address(address),
+                instruction,
+                self.emulator.step
+            )
+        };
+        if self.emulator.halted() {
+            location += "  (halted)";
+        }
+        location
+    }
+
+    fn describe_address(&self, address: u16) -> String {
+        let labels = self.symbols.labels_at(address);
+        if labels.is_empty() {
+            format!("{:0>4x}", address)
+        } else {
+            format!("{:0>4x} {}", address, labels.join(" "))
+        }
+    }
+
+    fn breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "No breakpoints".to_string();
+        }
+        self.breakpoints
+            .iter()
+            .map(|address| self.describe_address(*address))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn watchpoints(&self) -> String {
+        if self.watchpoints.is_empty() {
+            return "No watchpoints".to_string();
+        }
+        self.watchpoints
+            .iter()
+            .map(|(number, watchpoint)| format!("{}: {}", number, watchpoint))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn dump(&mut self, address: u16, length: u16) -> String {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < length {
+            let line_address = address.wrapping_add(offset);
+            let line_length = DUMP_LINE_LENGTH.min(length - offset);
+            let bytes: Vec<String> = (0..line_length)
+                .map(|index| {
+                    format!(
+                        "{:0>2x}",
+                        self.emulator.state.memory.peek(line_address.wrapping_add(index))
+                    )
+                })
+                .collect();
+            lines.push(format!("{:0>4x}: {}", line_address, bytes.join(" ")));
+            offset += line_length;
+        }
+        lines.join("\n")
+    }
+
+    /// Resolve a location typed by the user: a label like in the source, or a hexadecimal address.
+    fn address(&self, location: &str) -> eyre::Result<u16> {
+        if location.starts_with(':') || location.starts_with('.') {
+            match self.symbols.address(location) {
+                Some(address) => Ok(address),
+                None => bail!("Unknown label: {}", location),
+            }
+        } else {
+            parse_hex(location)
+        }
+    }
+}
+
+/// The debugger always records the data accesses, for the watchpoints.
+fn new_emulator(microcode: &Arc<Microcode>, program: &[u8]) -> eyre::Result<MicrocodeEmulator> {
+    let mut emulator = MicrocodeEmulator::new(microcode.clone(), program)?;
+    emulator.accesses = Some(Vec::new());
+    Ok(emulator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_with_symbols;
+
+    fn debugger() -> eyre::Result<Debugger> {
+        let (program, symbols) = assemble_with_symbols(include_str!("../../../examples/fib.as"))?;
+        Debugger::new(program.0, symbols)
+    }
+
+    #[test]
+    fn test_breakpoints() -> eyre::Result<()> {
+        let mut debugger = debugger()?;
+
+        debugger.execute(Command::Break(Some(":loop".to_string())))?;
+        let output = debugger.execute(Command::Continue)?;
+        assert_eq!(output, "Breakpoint hit\n0004 :loop  ADD { acc: A, val: B }");
+
+        // The second time around the loop, A and B have been swapped once.
+        debugger.execute(Command::Continue)?;
+        assert_eq!(debugger.emulator.state.registers[..2], [0x00, 0x01]);
+
+        debugger.execute(Command::Delete(":loop".to_string()))?;
+        debugger.execute(Command::Break(Some("e".to_string())))?;
+        let output = debugger.execute(Command::Continue)?;
+        assert_eq!(output, "Breakpoint hit\n000e .halt  HLT");
+
+        let output = debugger.execute(Command::Step(1))?;
+        assert!(output.ends_with("(halted)"));
+        assert_eq!(debugger.emulator.state.registers, [0xe9, 0x79, 0x00, 0x79]);
+
+        assert!(debugger.execute(Command::Break(Some(":nowhere".to_string()))).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_microstep() -> eyre::Result<()> {
+        let mut debugger = debugger()?;
+
+        let output = debugger.execute(Command::Microstep(2))?;
+        assert_eq!(
+            output,
+            "IR: 00  step:  0  [MO, IRE]\nIR: 20  step:  1  [IPA]\n0001  in LI { dst: A }, step 2"
+        );
+
+        // Stepping from the middle of an instruction completes it.
+        let output = debugger.execute(Command::Step(1))?;
+        assert_eq!(output, "0002  LI { dst: B }");
+        assert_eq!(debugger.emulator.state.registers[0], 0x01);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory() -> eyre::Result<()> {
+        let mut debugger = debugger()?;
+
+        let output = debugger.execute(Command::Memory(":loop".to_string(), 0x12))?;
+        assert_eq!(
+            output,
+            "0004: 41 0c 01 07 c4 05 c0 00 04 c1 ff 00 00 00 00 00\n0014: 00 00"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshots() -> eyre::Result<()> {
+        let mut debugger = debugger()?;
+        let file = std::env::temp_dir().join(format!("helper-debugger-{}.snapshot", std::process::id()));
+        let file = file.to_string_lossy().to_string();
+
+        debugger.execute(Command::Microstep(12))?;
+        debugger.execute(Command::Save(file.clone()))?;
+        let registers = debugger.execute(Command::Registers)?;
+
+        debugger.execute(Command::Continue)?;
+        let output = debugger.execute(Command::Load(file.clone()))?;
+        fs::remove_file(&file)?;
+        assert_eq!(output, "0005  in ADD { acc: A, val: B }, step 2");
+        assert_eq!(debugger.execute(Command::Registers)?, registers);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse() -> eyre::Result<()> {
+        let mut debugger = debugger()?;
+
+        debugger.execute(Command::Break(Some(":loop".to_string())))?;
+        debugger.execute(Command::Continue)?;
+        let registers = debugger.execute(Command::Registers)?;
+        debugger.execute(Command::Continue)?;
+
+        let output = debugger.execute(Command::Back(1))?;
+        assert_eq!(output, "000d  JMP");
+        let output = debugger.execute(Command::Who("D".to_string()))?;
+        assert_eq!(output, "D 5 steps ago (00 -> 01) by 0005  MV { dst: D, src: A }");
+
+        let output = debugger.execute(Command::ReverseContinue)?;
+        assert_eq!(output, "Breakpoint hit\n0004 :loop  ADD { acc: A, val: B }");
+        assert_eq!(debugger.execute(Command::Registers)?, registers);
+
+        // Microsteps are undone one at a time, and going forward again replays the same steps.
+        debugger.execute(Command::Microstep(3))?;
+        let output = debugger.execute(Command::Back(2))?;
+        assert_eq!(output, "0004 :loop  in ADD { acc: A, val: B }, step 1");
+        debugger.execute(Command::Delete(":loop".to_string()))?;
+        debugger.execute(Command::Continue)?;
+        assert_eq!(debugger.emulator.state.registers, [0xe9, 0x79, 0x00, 0x79]);
+
+        let output = debugger.execute(Command::Back(1_000))?;
+        assert_eq!(output, "Reached the start of the history\n0000  LI { dst: A }");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_who_wrote() -> eyre::Result<()> {
+        let source = "LI A, 0x00\nLI B, 0x80\nMSRL A\nMSRH B\nLI A, 0x42\nMEMW A\nINC A\nMEMW A\nHLT";
+        let (program, symbols) = assemble_with_symbols(source)?;
+        let mut debugger = Debugger::new(program.0, symbols)?;
+
+        debugger.execute(Command::Continue)?;
+        let output = debugger.execute(Command::Who("0x8000".to_string()))?;
+        assert_eq!(output, "0x8000 2 steps ago (42 -> 43) by 000a  MEMW { src: A }");
+        let output = debugger.execute(Command::Who("0x8001".to_string()))?;
+        assert_eq!(output, "0x8001 did not change in the last 9 steps");
+        // 0xa is a byte of memory, a the register.
+        let output = debugger.execute(Command::Who("0xa".to_string()))?;
+        assert_eq!(output, "0xa did not change in the last 9 steps");
+        let output = debugger.execute(Command::Who("a".to_string()))?;
+        assert_eq!(output, "a 3 steps ago (42 -> 43) by 0009  INC { dst: A }");
+        assert!(debugger.execute(Command::Who("8000".to_string())).is_err());
+
+        debugger.execute(Command::Back(3))?;
+        assert_eq!(debugger.emulator.state.memory.peek(0x8000), 0x42);
+        debugger.execute(Command::Back(1))?;
+        assert_eq!(debugger.emulator.state.memory.peek(0x8000), 0x00);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_watchpoints() -> eyre::Result<()> {
+        let source = "LI A, 0x00\nLI B, 0x80\nMSRL A\nMSRH B\nLI A, 0x42\nMEMW A\nINC A\nMEMW A\nMEMR C\nHLT";
+        let (program, symbols) = assemble_with_symbols(source)?;
+        let mut debugger = Debugger::new(program.0, symbols)?;
+
+        debugger.execute(Command::parse("watch 0x7fff 2")?)?;
+        debugger.execute(Command::parse("rwatch 0x8000")?)?;
+        let output = debugger.execute(Command::parse("watch A == 43")?)?;
+        assert_eq!(output, "Watchpoint 3: A == 0x43");
+        assert_eq!(
+            debugger.execute(Command::parse("watch")?)?,
+            "1: writes to 7fff-8000\n2: reads from 8000\n3: A == 0x43"
+        );
+
+        let output = debugger.execute(Command::Continue)?;
+        assert_eq!(output, "Watchpoint 1 hit: 0008  MEMW { src: A } wrote 42 to 8000\n0009  INC { dst: A }");
+        let output = debugger.execute(Command::Step(5))?;
+        assert_eq!(output, "Watchpoint 3 hit: A == 0x43 after 0009  INC { dst: A }\n000a  MEMW { src: A }");
+
+        debugger.execute(Command::Unwatch(1))?;
+        let output = debugger.execute(Command::Microstep(10))?;
+        assert!(output.ends_with(
+            "Watchpoint 2 hit: 000c  in MEMR { dst: C }, step 2 read 43 from 8000\n000c  in MEMR { dst: C }, step 3"
+        ));
+        assert!(debugger.execute(Command::Unwatch(1)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_console() -> eyre::Result<()> {
+        let (program, symbols) = assemble_with_symbols(include_str!("../../../examples/digits.as"))?;
+        let mut debugger = Debugger::new(program.0, symbols)?;
+
+        debugger.execute(Command::Break(Some(":loop".to_string())))?;
+        debugger.execute(Command::Continue)?;
+        let output = debugger.execute(Command::Continue)?;
+        assert_eq!(output, "0\nBreakpoint hit\n000a :loop  MEMW { src: A }");
+
+        debugger.execute(Command::Delete(":loop".to_string()))?;
+        let output = debugger.execute(Command::Continue)?;
+        assert!(output.starts_with("123456789\n0017"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyboard() -> eyre::Result<()> {
+        let (program, symbols) = assemble_with_symbols(include_str!("../../../examples/echo.as"))?;
+        let mut debugger = Debugger::new(program.0, symbols)?;
+        debugger.set_input(b"abc".to_vec());
+
+        debugger.execute(Command::Break(Some(":poll".to_string())))?;
+        debugger.execute(Command::Continue)?;
+        let output = debugger.execute(Command::Continue)?;
+        assert!(output.starts_with("a\nBreakpoint hit"));
+
+        // Restarting types the same input again.
+        debugger.execute(Command::Delete(":poll".to_string()))?;
+        debugger.execute(Command::Restart)?;
+        let output = debugger.execute(Command::Continue)?;
+        assert!(output.starts_with("abc\n"));
+        assert!(output.ends_with("(halted)"));
+
+        Ok(())
+    }
+}
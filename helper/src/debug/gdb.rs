@@ -0,0 +1,622 @@
+use crate::emulate::emulator::Emulator;
+use crate::emulate::microcode::Microcode;
+use crate::emulate::microcode_emulator::MicrocodeEmulator;
+use crate::emulate::watchpoint::{WatchKind, Watchpoint};
+use crate::word_bytes::WordBytes;
+use eyre::bail;
+use std::collections::BTreeSet;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+
+/// The registers as gdb sees them, in the order of the target description: `g` packets list them in this order too.
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.mypc.core">
+    <flags id="flags_type" size="1">
+      <field name="FZ" start="0" end="0"/>
+      <field name="CO" start="1" end="1"/>
+      <field name="A2G1" start="2" end="2"/>
+      <field name="NEG" start="3" end="3"/>
+    </flags>
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="b" bitsize="8" type="uint8"/>
+    <reg name="c" bitsize="8" type="uint8"/>
+    <reg name="d" bitsize="8" type="uint8"/>
+    <reg name="ip" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="jmp" bitsize="16" type="code_ptr"/>
+    <reg name="ret" bitsize="16" type="code_ptr"/>
+    <reg name="mem" bitsize="16" type="data_ptr"/>
+    <reg name="a1" bitsize="8" type="uint8"/>
+    <reg name="a2" bitsize="8" type="uint8"/>
+    <reg name="flags" bitsize="8" type="flags_type"/>
+  </feature>
+</target>
+"#;
+
+const REGISTERS: usize = 12;
+/// The flags are computed from A1 and A2: writing them is ignored.
+const FLAGS_REGISTER: usize = 11;
+
+/// While running, look for an interrupt from gdb every this many instructions.
+const INTERRUPT_CHECK_INSTRUCTIONS: u64 = 0x10_00;
+
+/// The reply to a packet whose arguments do not parse: the client gets an error, the session goes on.
+const MALFORMED: &str = "E01";
+
+/// The largest packet the stub accepts and sends, as announced in `qSupported` (where it is written in hexadecimal).
+const PACKET_SIZE: usize = 0x4000;
+/// Every byte of memory takes two hexadecimal digits in the reply to `m`: longer reads get the first bytes only, and
+/// gdb asks for the rest.
+const MAX_MEMORY_READ: u16 = (PACKET_SIZE / 2) as u16;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// What the stub does after answering a packet.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Response {
+    Reply(String),
+    /// Reply, then close the connection.
+    Close(String),
+}
+
+/// GDB remote serial protocol stub: everything a client needs to inspect memory and registers, set breakpoints and
+/// step through a program running on the microcode emulator.
+pub struct GdbStub {
+    pub emulator: MicrocodeEmulator,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl GdbStub {
+    pub fn new(program: &[u8]) -> eyre::Result<Self> {
+        let mut emulator = MicrocodeEmulator::new(Arc::new(Microcode::from_steps()), program)?;
+        emulator.accesses = Some(Vec::new());
+        Ok(GdbStub {
+            emulator,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+        })
+    }
+
+    /// Answer a packet (without the framing). `interrupted` is polled while the program runs.
+    pub fn handle(&mut self, packet: &str, interrupted: &mut dyn FnMut() -> bool) -> eyre::Result<Response> {
+        // The packets can contain anything: only the ones starting with an ASCII command are understood.
+        let arguments = packet.get(1..).unwrap_or_default();
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => self.stopped(),
+            Some(b'g') => self.read_registers(),
+            Some(b'G') => or_malformed(self.write_registers(arguments)),
+            Some(b'p') => or_malformed(self.read_register(arguments)),
+            Some(b'P') => or_malformed(self.write_register(arguments)),
+            Some(b'm') => or_malformed(self.read_memory(arguments)),
+            Some(b'M') => or_malformed(self.write_memory(arguments)),
+            Some(b's') if self.emulator.halted => self.stopped(),
+            Some(b's') => match self.step()? {
+                Some(reply) => reply,
+                None => self.stopped(),
+            },
+            Some(b'c') => self.continue_execution(interrupted)?,
+            Some(b'Z') => or_malformed(self.breakpoint(arguments, true)),
+            Some(b'z') => or_malformed(self.breakpoint(arguments, false)),
+            Some(b'H') | Some(b'T') => "OK".to_string(),
+            Some(b'D') => return Ok(Response::Close("OK".to_string())),
+            Some(b'k') => return Ok(Response::Close(String::new())),
+            Some(b'q') => self.query(packet),
+            _ => String::new(),
+        };
+        Ok(Response::Reply(reply))
+    }
+
+    fn query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE)
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            read_chunk(TARGET_XML, range).unwrap_or_else(|| "E00".to_string())
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if packet == "qfThreadInfo" {
+            "m1".to_string()
+        } else if packet == "qsThreadInfo" {
+            "l".to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    fn registers(&self) -> [u16; REGISTERS] {
+        let state = &self.emulator.state;
+        [
+            state.registers[0] as u16,
+            state.registers[1] as u16,
+            state.registers[2] as u16,
+            state.registers[3] as u16,
+            state.instruction_pointer,
+            state.stack_pointer,
+            state.jump_register,
+            state.return_register,
+            state.memory_register,
+            state.alu1 as u16,
+            state.alu2 as u16,
+            state.flags().value() as u16,
+        ]
+    }
+
+    fn set_register(&mut self, register: usize, value: u16) {
+        let state = &mut self.emulator.state;
+        match register {
+            0..=3 => state.registers[register] = value as u8,
+            4 => state.instruction_pointer = value,
+            5 => state.stack_pointer = value,
+            6 => state.jump_register = value,
+            7 => state.return_register = value,
+            8 => state.memory_register = value,
+            9 => state.alu1 = value as u8,
+            10 => state.alu2 = value as u8,
+            _ => {}
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTERS)
+            .zip(self.registers())
+            .map(|(register, value)| encode_register(register, value))
+            .collect()
+    }
+
+    fn write_registers(&mut self, values: &str) -> eyre::Result<String> {
+        let mut values = values;
+        for register in 0..REGISTERS {
+            let length = register_size(register) * 2;
+            let Some((value, rest)) = values.split_at_checked(length) else {
+                bail!("Truncated register values");
+            };
+            if register != FLAGS_REGISTER {
+                self.set_register(register, decode_register(value)?);
+            }
+            values = rest;
+        }
+        Ok("OK".to_string())
+    }
+
+    fn read_register(&self, register: &str) -> eyre::Result<String> {
+        let register = usize::from_str_radix(register, 16)?;
+        if register >= REGISTERS {
+            return Ok("E00".to_string());
+        }
+        Ok(encode_register(register, self.registers()[register]))
+    }
+
+    fn write_register(&mut self, assignment: &str) -> eyre::Result<String> {
+        let Some((register, value)) = assignment.split_once('=') else {
+            bail!("Malformed register assignment: {}", assignment);
+        };
+        let register = usize::from_str_radix(register, 16)?;
+        if register >= REGISTERS {
+            return Ok("E00".to_string());
+        }
+        self.set_register(register, decode_register(value)?);
+        Ok("OK".to_string())
+    }
+
+    fn read_memory(&mut self, arguments: &str) -> eyre::Result<String> {
+        let (address, length) = parse_address_length(arguments)?;
+        Ok((0..length.min(MAX_MEMORY_READ))
+            .map(|offset| format!("{:0>2x}", self.emulator.state.memory.peek(address.wrapping_add(offset))))
+            .collect())
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> eyre::Result<String> {
+        let Some((location, data)) = arguments.split_once(':') else {
+            bail!("Malformed memory write: {}", arguments);
+        };
+        let (address, length) = parse_address_length(location)?;
+        let bytes = decode_hex(data)?;
+        if bytes.len() != length as usize {
+            return Ok("E00".to_string());
+        }
+        for (offset, byte) in bytes.into_iter().enumerate() {
+            self.emulator
+                .state
+                .memory
+                .write(address.wrapping_add(offset as u16), byte);
+        }
+        Ok("OK".to_string())
+    }
+
+    fn breakpoint(&mut self, arguments: &str, insert: bool) -> eyre::Result<String> {
+        let fields: Vec<&str> = arguments.split(',').collect();
+        let [kind, address, length, ..] = fields.as_slice() else {
+            bail!("Malformed breakpoint: {}", arguments);
+        };
+        let address = u16::from_str_radix(address, 16)?;
+        // Software and hardware breakpoints are the same thing for an emulator, the length of a watchpoint is in bytes.
+        let kind = match *kind {
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.insert(address);
+                } else {
+                    self.breakpoints.remove(&address);
+                }
+                return Ok("OK".to_string());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return Ok(String::new()),
+        };
+        let watchpoint = Watchpoint::Memory {
+            start: address,
+            length: u16::from_str_radix(length, 16)?,
+            kind,
+        };
+        if insert {
+            self.watchpoints.push(watchpoint);
+        } else {
+            self.watchpoints.retain(|candidate| *candidate != watchpoint);
+        }
+        Ok("OK".to_string())
+    }
+
+    fn continue_execution(&mut self, interrupted: &mut dyn FnMut() -> bool) -> eyre::Result<String> {
+        let mut executed = 0u64;
+        while !self.emulator.halted {
+            if let Some(reply) = self.step()? {
+                return Ok(reply);
+            }
+            if self.breakpoints.contains(&self.emulator.state.instruction_pointer) {
+                break;
+            }
+
+            executed += 1;
+            if executed.is_multiple_of(INTERRUPT_CHECK_INSTRUCTIONS) && interrupted() {
+                return Ok(stop_reply(SIGINT));
+            }
+        }
+        Ok(self.stopped())
+    }
+
+    /// The reply when the program stops on its own: once halted, it exited with the status it gave to the host (0 if
+    /// it stopped with HLT), otherwise it hit a breakpoint or finished a step.
+    fn stopped(&self) -> String {
+        if self.emulator.halted {
+            format!("W{:0>2x}", self.emulator.state.memory.host.exit.unwrap_or(0))
+        } else {
+            stop_reply(SIGTRAP)
+        }
+    }
+
+    /// Execute an instruction, and return the stop reply of the watchpoint it hit if any.
+    fn step(&mut self) -> eyre::Result<Option<String>> {
+        self.emulator.step_instruction()?;
+        let accesses = self.emulator.accesses.replace(Vec::new()).unwrap_or_default();
+        for watchpoint in &self.watchpoints {
+            let (Watchpoint::Memory { kind, .. }, Some(access)) = (watchpoint, watchpoint.hit(&accesses)) else {
+                continue;
+            };
+            let reason = match kind {
+                WatchKind::Write => "watch",
+                WatchKind::Read => "rwatch",
+                WatchKind::Access => "awatch",
+            };
+            return Ok(Some(format!("T{:0>2x}{}:{:x};", SIGTRAP, reason, access.address)));
+        }
+        Ok(None)
+    }
+}
+
+/// Wait for a client on the listener, and serve it until it detaches.
+pub fn serve(listener: TcpListener, stub: &mut GdbStub) -> eyre::Result<()> {
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    while let Some(packet) = read_packet(&mut reader, &mut writer)? {
+        let mut interrupted = || poll_interrupt(reader.get_ref());
+        let response = stub.handle(&packet, &mut interrupted)?;
+        match response {
+            Response::Reply(reply) => write_packet(&mut writer, &reply)?,
+            Response::Close(reply) => {
+                write_packet(&mut writer, &reply)?;
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the next packet, acknowledging it. Returns `None` when the client disconnects.
+fn read_packet(reader: &mut impl Read, writer: &mut impl Write) -> eyre::Result<Option<String>> {
+    loop {
+        let Some(byte) = read_byte(reader)? else {
+            return Ok(None);
+        };
+        // Acknowledgements of our packets, and interrupts that arrive when nothing is running.
+        if byte != b'$' {
+            continue;
+        }
+
+        let mut packet = Vec::new();
+        loop {
+            match read_byte(reader)? {
+                Some(b'#') => break,
+                Some(byte) => packet.push(byte),
+                None => return Ok(None),
+            }
+        }
+        let mut checksum = [0u8; 2];
+        reader.read_exact(&mut checksum)?;
+
+        let expected = std::str::from_utf8(&checksum)
+            .ok()
+            .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+        if expected != Some(checksum_of(&packet)) {
+            writer.write_all(b"-")?;
+            continue;
+        }
+        writer.write_all(b"+")?;
+
+        return Ok(Some(String::from_utf8_lossy(&unescape(&packet)).into_owned()));
+    }
+}
+
+fn write_packet(writer: &mut impl Write, reply: &str) -> eyre::Result<()> {
+    let data = escape(reply.as_bytes());
+    writer.write_all(b"$")?;
+    writer.write_all(&data)?;
+    write!(writer, "#{:0>2x}", checksum_of(&data))?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_byte(reader: &mut impl Read) -> eyre::Result<Option<u8>> {
+    let mut byte = [0u8];
+    match reader.read(&mut byte) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(byte[0])),
+        Err(error) if error.kind() == ErrorKind::ConnectionReset => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Whether gdb sent a break (a bare 0x03 byte) while the program was running.
+fn poll_interrupt(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut byte = [0u8];
+    let interrupted = matches!((&*stream).read(&mut byte), Ok(1) if byte[0] == 0x03);
+    let _ = stream.set_nonblocking(false);
+    interrupted
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for byte in data {
+        if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+            escaped.extend([b'}', byte ^ 0x20]);
+        } else {
+            escaped.push(*byte);
+        }
+    }
+    escaped
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(byte) = bytes.next() {
+        match (byte, bytes.clone().next()) {
+            (b'}', Some(next)) => {
+                unescaped.push(next ^ 0x20);
+                bytes.next();
+            }
+            _ => unescaped.push(*byte),
+        }
+    }
+    unescaped
+}
+
+/// The reply to a packet, or `E01` if its arguments were malformed.
+fn or_malformed(reply: eyre::Result<String>) -> String {
+    reply.unwrap_or_else(|_| MALFORMED.to_string())
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:0>2x}", signal)
+}
+
+fn register_size(register: usize) -> usize {
+    match register {
+        4..=8 => 2,
+        _ => 1,
+    }
+}
+
+/// Registers go over the wire in target byte order, little endian.
+fn encode_register(register: usize, value: u16) -> String {
+    match register_size(register) {
+        2 => format!("{:0>2x}{:0>2x}", value.low(), value.high()),
+        _ => format!("{:0>2x}", value as u8),
+    }
+}
+
+fn decode_register(value: &str) -> eyre::Result<u16> {
+    let bytes = decode_hex(value)?;
+    match bytes.as_slice() {
+        [low] => Ok(*low as u16),
+        [low, high] => Ok(u16::from_le_bytes([*low, *high])),
+        _ => bail!("Invalid register value: {}", value),
+    }
+}
+
+fn decode_hex(data: &str) -> eyre::Result<Vec<u8>> {
+    if !data.len().is_multiple_of(2) {
+        bail!("Odd number of hexadecimal digits: {}", data);
+    }
+    data.as_bytes()
+        .chunks(2)
+        .map(|digits| Ok(u8::from_str_radix(std::str::from_utf8(digits)?, 16)?))
+        .collect()
+}
+
+fn parse_address_length(arguments: &str) -> eyre::Result<(u16, u16)> {
+    let Some((address, length)) = arguments.split_once(',') else {
+        bail!("Malformed address and length: {}", arguments);
+    };
+    Ok((u16::from_str_radix(address, 16)?, u16::from_str_radix(length, 16)?))
+}
+
+/// Answer a `qXfer` read of `offset,length` from the document.
+fn read_chunk(document: &str, range: &str) -> Option<String> {
+    let (offset, length) = range.split_once(',')?;
+    let offset = usize::from_str_radix(offset, 16).ok()?.min(document.len());
+    let length = usize::from_str_radix(length, 16).ok()?;
+    let end = (offset + length).min(document.len());
+    let marker = if end == document.len() { 'l' } else { 'm' };
+    Some(format!("{}{}", marker, &document[offset..end]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_program;
+    use std::thread;
+
+    /// Minimal RSP client: send a packet, check the acknowledgement and return the reply.
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn exchange(&mut self, packet: &str) -> eyre::Result<String> {
+            write_packet(&mut self.stream, packet)?;
+
+            let mut acknowledgement = [0u8];
+            self.stream.read_exact(&mut acknowledgement)?;
+            assert_eq!(acknowledgement, *b"+");
+
+            let mut writer = self.stream.try_clone()?;
+            let Some(reply) = read_packet(&mut self.stream, &mut writer)? else {
+                bail!("the stub closed the connection");
+            };
+            Ok(reply)
+        }
+    }
+
+    #[test]
+    fn test_session() -> eyre::Result<()> {
+        let program = assemble_program(include_str!("../../../examples/fib.as"))?;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let server = thread::spawn(move || -> eyre::Result<GdbStub> {
+            let mut stub = GdbStub::new(&program.0)?;
+            serve(listener, &mut stub)?;
+            Ok(stub)
+        });
+
+        let mut client = Client {
+            stream: TcpStream::connect(address)?,
+        };
+
+        assert!(client
+            .exchange("qSupported:xmlRegisters=i386")?
+            .contains("qXfer:features:read+"));
+        let description = client.exchange("qXfer:features:read:target.xml:0,fff")?;
+        assert_eq!(description, format!("l{}", TARGET_XML));
+        assert_eq!(client.exchange("?")?, "S05");
+
+        // Break at :loop, the third time there A and B have been swapped twice.
+        assert_eq!(client.exchange("Z0,4,1")?, "OK");
+        for _ in 0..3 {
+            assert_eq!(client.exchange("c")?, "S05");
+        }
+        assert_eq!(client.exchange("p4")?, "0400");
+        assert_eq!(client.exchange("g")?, "0101000104000000040000000000000104");
+        assert_eq!(client.exchange("z0,4,1")?, "OK");
+
+        assert_eq!(client.exchange("P0=2a")?, "OK");
+        assert_eq!(client.exchange("p0")?, "2a");
+        assert_eq!(client.exchange("M8000,2:beef")?, "OK");
+        assert_eq!(client.exchange("m7fff,4")?, "00beef00");
+        assert_eq!(client.exchange("m0,4")?, "20012100");
+
+        assert_eq!(client.exchange("s")?, "S05");
+        assert_eq!(client.exchange("p4")?, "0500");
+
+        // Garbage gets an error, and the session goes on.
+        assert_eq!(client.exchange("pzz")?, "E01");
+        assert_eq!(client.exchange("p")?, "E01");
+        assert_eq!(client.exchange("mzz,4")?, "E01");
+        assert_eq!(client.exchange("m8000")?, "E01");
+        assert_eq!(client.exchange("M8000,1:g")?, "E01");
+        assert_eq!(client.exchange("Z0,zz,1")?, "E01");
+        assert_eq!(client.exchange("p4")?, "0500");
+        assert_eq!(client.exchange("M8000,2:a\u{FFFD}")?, "E01");
+        assert_eq!(client.exchange("G\u{FFFD}")?, "E01");
+        assert_eq!(client.exchange("\u{FFFD}")?, "");
+
+        // Long reads are cut to what fits in a packet.
+        assert_eq!(client.exchange("m0,ffff")?.len(), PACKET_SIZE);
+
+        assert_eq!(client.exchange("vMustReplyEmpty")?, "");
+        assert_eq!(client.exchange("D")?, "OK");
+
+        let stub = server.join().expect("the server thread panicked")?;
+        assert_eq!(stub.emulator.state.registers[0], 0x2b);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_watchpoints() -> eyre::Result<()> {
+        let program = assemble_program("LI A, 0x00\nLI B, 0x80\nMSRL A\nMSRH B\nMEMW A\nMEMR C\nHLT")?;
+        let mut stub = GdbStub::new(&program.0)?;
+        let handle = |stub: &mut GdbStub, packet: &str| match stub.handle(packet, &mut || false) {
+            Ok(Response::Reply(reply)) => reply,
+            other => panic!("unexpected response: {:?}", other),
+        };
+
+        assert_eq!(handle(&mut stub, "Z3,7ffe,4"), "OK");
+        assert_eq!(handle(&mut stub, "c"), "T05rwatch:8000;");
+        assert_eq!(handle(&mut stub, "p4"), "0800");
+        assert_eq!(handle(&mut stub, "z3,7ffe,4"), "OK");
+        assert_eq!(handle(&mut stub, "c"), "W00");
+        assert!(stub.emulator.halted);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exit() -> eyre::Result<()> {
+        let program = assemble_program("LI A, 0x2a\nLI B, 0xFF\nLI C, 0x10\nMSRL C\nMSRH B\nMEMW A\nHLT")?;
+        let mut stub = GdbStub::new(&program.0)?;
+        let handle = |stub: &mut GdbStub, packet: &str| match stub.handle(packet, &mut || false) {
+            Ok(Response::Reply(reply)) => reply,
+            other => panic!("unexpected response: {:?}", other),
+        };
+
+        assert_eq!(handle(&mut stub, "s"), "S05");
+        assert_eq!(handle(&mut stub, "c"), "W2a");
+        assert_eq!(handle(&mut stub, "s"), "W2a");
+        assert_eq!(handle(&mut stub, "c"), "W2a");
+        assert_eq!(handle(&mut stub, "?"), "W2a");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(unescape(&escape(b"a#b$c}d*e")), b"a#b$c}d*e");
+        assert_eq!(escape(b"}"), b"}]");
+    }
+}
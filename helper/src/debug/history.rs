@@ -0,0 +1,228 @@
+use crate::emulate::devices::Host;
+use crate::emulate::memory::Journal;
+use crate::emulate::microcode_emulator::MicrocodeEmulator;
+use std::collections::VecDeque;
+
+/// Everything but the RAM and the devices: small enough to be copied at every step.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub registers: [u8; 4],
+    pub alu1: u8,
+    pub alu2: u8,
+    pub instruction_pointer: u16,
+    pub stack_pointer: u16,
+    pub jump_register: u16,
+    pub return_register: u16,
+    pub memory_register: u16,
+    pub instruction_register: u8,
+    pub step: u8,
+    pub halted: bool,
+    pub cycles: u64,
+}
+
+impl Registers {
+    pub fn capture(emulator: &MicrocodeEmulator) -> Self {
+        let state = &emulator.state;
+        Registers {
+            registers: state.registers,
+            alu1: state.alu1,
+            alu2: state.alu2,
+            instruction_pointer: state.instruction_pointer,
+            stack_pointer: state.stack_pointer,
+            jump_register: state.jump_register,
+            return_register: state.return_register,
+            memory_register: state.memory_register,
+            instruction_register: emulator.instruction_register,
+            step: emulator.step,
+            halted: emulator.halted,
+            cycles: emulator.cycles,
+        }
+    }
+
+    fn apply(&self, emulator: &mut MicrocodeEmulator) {
+        let state = &mut emulator.state;
+        state.registers = self.registers;
+        state.alu1 = self.alu1;
+        state.alu2 = self.alu2;
+        state.instruction_pointer = self.instruction_pointer;
+        state.stack_pointer = self.stack_pointer;
+        state.jump_register = self.jump_register;
+        state.return_register = self.return_register;
+        state.memory_register = self.memory_register;
+        emulator.instruction_register = self.instruction_register;
+        emulator.step = self.step;
+        emulator.halted = self.halted;
+        emulator.cycles = self.cycles;
+    }
+}
+
+/// What a single step of the debugger (an instruction or a clock cycle) changed.
+pub struct Entry {
+    /// The registers before the step.
+    pub before: Registers,
+    /// The RAM writes and the keyboard input consumed during the step.
+    pub journal: Journal,
+    /// The host calls before the step, exit status included.
+    pub host: Host,
+    /// How many bytes the console had printed before the step.
+    pub console_length: usize,
+}
+
+/// The most recent steps of the debugger, to run them backwards.
+pub struct History {
+    /// From the oldest to the most recent.
+    pub entries: VecDeque<Entry>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            entries: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Execute a step of the emulator, remembering how to undo it.
+    pub fn record(
+        &mut self,
+        emulator: &mut MicrocodeEmulator,
+        step: impl FnOnce(&mut MicrocodeEmulator) -> eyre::Result<()>,
+    ) -> eyre::Result<()> {
+        let before = Registers::capture(emulator);
+        let host = emulator.state.memory.host.clone();
+        let console_length = emulator.state.memory.console.output.len();
+        emulator.state.memory.journal = Some(Journal::default());
+        let result = step(emulator);
+        let journal = emulator.state.memory.journal.take().unwrap_or_default();
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            before,
+            journal,
+            host,
+            console_length,
+        });
+
+        result
+    }
+
+    /// Undo the most recent step, returns false if there is none left.
+    pub fn undo(&mut self, emulator: &mut MicrocodeEmulator) -> bool {
+        let Some(entry) = self.entries.pop_back() else {
+            return false;
+        };
+
+        entry.before.apply(emulator);
+        let memory = &mut emulator.state.memory;
+        for (address, old, _) in entry.journal.writes.iter().rev() {
+            memory.write(*address, *old);
+        }
+        for byte in entry.journal.keyboard.iter().rev() {
+            memory.keyboard.input.push_front(*byte);
+        }
+        memory.host = entry.host;
+        // What was printed and already taken from the console cannot be taken back.
+        memory.console.output.truncate(entry.console_length);
+
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The most recent step that wrote the address: how many steps ago, the entry, and the value it wrote.
+    pub fn last_write(&self, address: u16) -> Option<(usize, &Entry, u8, u8)> {
+        self.entries.iter().rev().enumerate().find_map(|(age, entry)| {
+            entry
+                .journal
+                .writes
+                .iter()
+                .rev()
+                .find(|(candidate, _, _)| *candidate == address)
+                .map(|(_, old, new)| (age + 1, entry, *old, *new))
+        })
+    }
+
+    /// The most recent step that changed a register, as read by `register` from the registers before and after
+    /// the step.
+    pub fn last_change<T: PartialEq>(
+        &self,
+        current: &Registers,
+        register: impl Fn(&Registers) -> T,
+    ) -> Option<(usize, &Entry, T, T)> {
+        let mut after = *current;
+        for (age, entry) in self.entries.iter().rev().enumerate() {
+            let (old, new) = (register(&entry.before), register(&after));
+            if old != new {
+                return Some((age + 1, entry, old, new));
+            }
+            after = entry.before;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_program;
+    use crate::emulate::emulator::Emulator;
+    use crate::emulate::microcode::Microcode;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_undo() -> eyre::Result<()> {
+        let program = assemble_program(include_str!("../../../examples/fib.as"))?;
+        let mut emulator = MicrocodeEmulator::new(Arc::new(Microcode::from_steps()), &program.0)?;
+        let mut history = History::new(3);
+
+        let start = Registers::capture(&emulator);
+        history.record(&mut emulator, |emulator| emulator.step_instruction())?;
+        for _ in 0..4 {
+            history.record(&mut emulator, |emulator| emulator.clock())?;
+        }
+        assert_eq!(history.entries.len(), 3);
+
+        while history.undo(&mut emulator) {}
+        assert_eq!(emulator.step, 1);
+        assert_eq!(emulator.state.registers[0], 0x01);
+
+        // Only the oldest steps were forgotten.
+        assert!(Registers::capture(&emulator) != start);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_devices() -> eyre::Result<()> {
+        let program = assemble_program(include_str!("../../../examples/host.as"))?;
+        let mut emulator = MicrocodeEmulator::new(Arc::new(Microcode::from_steps()), &program.0)?;
+        let mut history = History::new(1_000);
+
+        while !emulator.halted {
+            history.record(&mut emulator, |emulator| emulator.step_instruction())?;
+        }
+        assert_eq!(emulator.state.memory.host.exit, Some(0x00));
+        let output = emulator.state.memory.console.output.clone();
+
+        // Stepping back over the exit resumes the machine.
+        history.undo(&mut emulator);
+        assert_eq!(emulator.state.memory.host.exit, None);
+        assert!(!emulator.halted);
+
+        while history.undo(&mut emulator) {}
+        assert!(emulator.state.memory.console.output.is_empty());
+        assert!(emulator.state.memory.host == Host::default());
+
+        // Running again prints the same, once.
+        emulator.run(1_000, |_| Ok(()))?;
+        assert_eq!(emulator.state.memory.console.output, output);
+        assert_eq!(emulator.state.memory.host.exit, Some(0x00));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,6 @@
+pub mod command;
+#[allow(clippy::module_inception)]
+pub mod debug;
+pub mod debugger;
+pub mod gdb;
+pub mod history;
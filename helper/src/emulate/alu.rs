@@ -0,0 +1,54 @@
+use crate::constants::control_line::ControlLine;
+use crate::constants::control_word::ControlWord;
+use crate::constants::flag::{Flag, Flags};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOperation {
+    Add,
+    Subtract,
+    Nand,
+    Xor,
+}
+
+impl From<&ControlWord> for AluOperation {
+    fn from(control_word: &ControlWord) -> Self {
+        match (control_word.has(ControlLine::AOPH), control_word.has(ControlLine::AOPL)) {
+            (false, false) => AluOperation::Add,
+            (false, true) => AluOperation::Subtract,
+            (true, false) => AluOperation::Nand,
+            (true, true) => AluOperation::Xor,
+        }
+    }
+}
+
+pub struct AluOutput {
+    pub result: u8,
+    pub flags: Flags,
+}
+
+/// The ALU is purely combinational: the adder and the subtractor always see both ALU registers, and the flags are
+/// wired straight into the control ROM address, so they follow the registers (and the selected operation) without
+/// being latched anywhere.
+pub fn alu(alu1: u8, alu2: u8, operation: AluOperation, carry_in: bool) -> AluOutput {
+    let carry = carry_in as u16;
+    let sum = alu1 as u16 + alu2 as u16 + carry;
+
+    let result = match operation {
+        AluOperation::Add => sum as u8,
+        AluOperation::Subtract => alu1.wrapping_sub(alu2).wrapping_sub(carry as u8),
+        AluOperation::Nand => !(alu1 & alu2),
+        AluOperation::Xor => alu1 ^ alu2,
+    };
+
+    let flags: Flags = [
+        (result == 0, Flag::FZ),
+        (sum > 0xFF, Flag::CO),
+        (alu2 as u16 + carry > alu1 as u16, Flag::A2G1),
+        (result & 0x80 != 0, Flag::NEG),
+    ]
+    .into_iter()
+    .filter_map(|(set, flag)| set.then_some(flag))
+    .collect();
+
+    AluOutput { result, flags }
+}
@@ -0,0 +1,185 @@
+use crate::assemble::symbols::Symbols;
+use crate::constants::machine_instruction::MachineInstruction;
+use crate::emulate::emulator::Emulator;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// Which parts of a program a run went through.
+pub struct Coverage {
+    /// How many times the instruction at each address was executed.
+    pub executions: BTreeMap<u16, u64>,
+    /// Every byte fetched as part of an executed instruction, operands included.
+    pub executed: BTreeSet<u16>,
+    /// How many times each conditional jump was taken and not taken.
+    pub branches: BTreeMap<u16, (u64, u64)>,
+    /// The instruction about to be executed, and whether it is a jump that will be taken.
+    next: (u16, MachineInstruction, bool),
+}
+
+impl Coverage {
+    /// Start recording from the current state of the emulator.
+    pub fn new(emulator: &impl Emulator) -> Self {
+        Coverage {
+            executions: BTreeMap::new(),
+            executed: BTreeSet::new(),
+            branches: BTreeMap::new(),
+            next: next_instruction(emulator),
+        }
+    }
+
+    /// Account for the instruction the emulator just executed.
+    pub fn after_instruction(&mut self, emulator: &impl Emulator) {
+        let (address, instruction, taken) = self.next;
+
+        *self.executions.entry(address).or_default() += 1;
+        for offset in 0..instruction.size() {
+            self.executed.insert(address.wrapping_add(offset));
+        }
+        if instruction.condition().is_some() {
+            let (taken_count, not_taken_count) = self.branches.entry(address).or_default();
+            if taken {
+                *taken_count += 1;
+            } else {
+                *not_taken_count += 1;
+            }
+        }
+
+        self.next = next_instruction(emulator);
+    }
+
+    /// The source with the execution count of each line, `#####` for the lines that never ran and a note on the
+    /// conditional jumps that only ever went one way; followed by a summary.
+    pub fn listing(&self, source: &str, symbols: &Symbols, program: &[u8]) -> String {
+        let line_addresses: BTreeMap<usize, u16> = symbols
+            .lines()
+            .iter()
+            .map(|(address, line)| (*line, *address))
+            .collect();
+        let mut executed_lines = 0;
+        let mut branch_directions = 0;
+        let mut listing = String::new();
+
+        for (index, text) in source.lines().enumerate() {
+            let Some(address) = line_addresses.get(&(index + 1)) else {
+                // Writing to a String cannot fail.
+                let _ = writeln!(listing, "{:>8} | {}", "-", text);
+                continue;
+            };
+
+            let executions = self.executions.get(address).copied().unwrap_or(0);
+            let count = if executions == 0 {
+                "#####".to_string()
+            } else {
+                executed_lines += 1;
+                executions.to_string()
+            };
+            let note = match self.branches.get(address) {
+                Some((taken, not_taken)) => {
+                    branch_directions += (*taken > 0) as usize + (*not_taken > 0) as usize;
+                    match (taken, not_taken) {
+                        (0, _) => "  <- never taken".to_string(),
+                        (_, 0) => "  <- always taken".to_string(),
+                        _ => format!("  <- taken {}, not taken {}", taken, not_taken),
+                    }
+                }
+                None => String::new(),
+            };
+            let _ = writeln!(listing, "{:>8} | {}{}", count, text, note);
+        }
+
+        let branches = symbols
+            .lines()
+            .iter()
+            .filter(|(address, _)| {
+                MachineInstruction::from(program[*address as usize])
+                    .condition()
+                    .is_some()
+            })
+            .count();
+        let executed_bytes = self
+            .executed
+            .iter()
+            .filter(|address| (**address as usize) < program.len())
+            .count();
+        let _ = writeln!(
+            listing,
+            "\nLines: {}/{}  Branch directions: {}/{}  Bytes: {}/{}",
+            executed_lines,
+            symbols.lines().len(),
+            branch_directions,
+            2 * branches,
+            executed_bytes,
+            program.len()
+        );
+
+        listing
+    }
+}
+
+fn next_instruction(emulator: &impl Emulator) -> (u16, MachineInstruction, bool) {
+    let state = emulator.state();
+    let address = state.instruction_pointer;
+    let instruction = MachineInstruction::from(state.memory.peek(address));
+    let flags = state.flags();
+    let taken = instruction.condition().is_some_and(|flag| flags.has(flag));
+    (address, instruction, taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_with_symbols;
+    use crate::emulate::isa_emulator::IsaEmulator;
+
+    #[test]
+    fn test_fibonacci() -> eyre::Result<()> {
+        let source = include_str!("../../../examples/fib.as");
+        let (program, symbols) = assemble_with_symbols(source)?;
+        let mut emulator = IsaEmulator::new(&program.0)?;
+
+        let mut coverage = Coverage::new(&emulator);
+        emulator.run(10_000, |emulator| {
+            coverage.after_instruction(emulator);
+            Ok(())
+        })?;
+
+        assert_eq!(coverage.executions[&0x04], 14);
+        // JCR at 0x08 is only taken when the sequence overflows.
+        assert_eq!(coverage.branches[&0x08], (1, 13));
+        assert_eq!(coverage.executed.len(), program.0.len());
+
+        let listing = coverage.listing(source, &symbols, &program.0);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[0], "       - | # Programm to calculate the Fibonacci sequence");
+        assert_eq!(lines[2], "       1 | LI A, 0x01");
+        assert_eq!(lines[6], "      14 |     ADD A, B");
+        assert_eq!(lines[11], "      14 |     JCR .halt  <- taken 1, not taken 13");
+        assert_eq!(
+            lines.last(),
+            Some(&"Lines: 10/10  Branch directions: 2/2  Bytes: 15/15")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_uncovered() -> eyre::Result<()> {
+        let source = "LI A, 0x01\nINC A\nJZR .skip\nHLT\n.skip\nHLT";
+        let (program, symbols) = assemble_with_symbols(source)?;
+        let mut emulator = IsaEmulator::new(&program.0)?;
+
+        let mut coverage = Coverage::new(&emulator);
+        emulator.run(100, |emulator| {
+            coverage.after_instruction(emulator);
+            Ok(())
+        })?;
+
+        let listing = coverage.listing(source, &symbols, &program.0);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[2], "       1 | JZR .skip  <- never taken");
+        assert_eq!(lines[5], "   ##### | HLT");
+        assert_eq!(lines.last(), Some(&"Lines: 4/5  Branch directions: 1/2  Bytes: 6/7"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use crate::emulate::framebuffer::{FRAMEBUFFER, FRAMEBUFFER_SIZE};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+/// The devices answer to the first addresses of the last page, instead of the RAM.
+pub const CONSOLE_OUTPUT: u16 = 0xFF00;
+pub const KEYBOARD_DATA: u16 = 0xFF01;
+pub const KEYBOARD_STATUS: u16 = 0xFF02;
+
+/// Host calls, for tests: writing these addresses asks the emulator to do something on the program's behalf.
+pub const HOST_EXIT: u16 = 0xFF10;
+pub const HOST_PRINT: u16 = 0xFF11;
+pub const HOST_ADDRESS_LOW: u16 = 0xFF12;
+pub const HOST_ADDRESS_HIGH: u16 = 0xFF13;
+pub const HOST_DUMP: u16 = 0xFF14;
+pub const HOST_EXPECTED: u16 = 0xFF15;
+pub const HOST_ASSERT: u16 = 0xFF16;
+
+/// The exit status of a failed `HOST_ASSERT`.
+pub const ASSERTION_FAILED: u8 = 0xFF;
+
+/// Whether the address belongs to a device rather than to plain RAM: the console and the keyboard, the host calls,
+/// and the framebuffer, which is RAM but is meant for the display.
+pub fn is_device(address: u16) -> bool {
+    let framebuffer = FRAMEBUFFER..FRAMEBUFFER + FRAMEBUFFER_SIZE as u16;
+    (CONSOLE_OUTPUT..=KEYBOARD_STATUS).contains(&address)
+        || (HOST_EXIT..=HOST_ASSERT).contains(&address)
+        || framebuffer.contains(&address)
+}
+
+/// Bits of `KEYBOARD_STATUS`.
+pub const KEYBOARD_AVAILABLE: u8 = 0b01;
+pub const KEYBOARD_CLOSED: u8 = 0b10;
+
+/// Write only: every byte stored at `CONSOLE_OUTPUT` is appended to the output, reading it gives 0x00.
+#[derive(Default, PartialEq, Eq)]
+pub struct Console {
+    pub output: Vec<u8>,
+    /// Also forward every byte to the host's stdout as soon as it is written.
+    pub echo: bool,
+}
+
+impl Console {
+    pub fn write(&mut self, value: u8) {
+        self.output.push(value);
+        if self.echo {
+            let mut stdout = std::io::stdout().lock();
+            // The program output is best effort: a closed pipe must not stop the machine.
+            let _ = stdout.write_all(&[value]);
+            let _ = stdout.flush();
+        }
+    }
+
+    pub fn write_str(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.write(byte);
+        }
+    }
+
+    /// The bytes written since the last call.
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+}
+
+/// Write only, every register reads as 0x00:
+/// - `HOST_EXIT` stops the machine, like HLT, with the value as exit status;
+/// - `HOST_PRINT` prints the value in hexadecimal on the console;
+/// - `HOST_DUMP` prints that many bytes of memory on the console, from the address set with `HOST_ADDRESS_LOW` and
+///   `HOST_ADDRESS_HIGH`;
+/// - `HOST_ASSERT` compares the value with the one written to `HOST_EXPECTED`: if they differ it prints both and
+///   exits with `ASSERTION_FAILED`.
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct Host {
+    pub exit: Option<u8>,
+    pub address: u16,
+    pub expected: u8,
+}
+
+/// Read only: `KEYBOARD_DATA` pops the next byte of input (0x00 when there is none), `KEYBOARD_STATUS` tells whether
+/// a byte is available and whether the input is over, so that programs can poll it.
+#[derive(Default)]
+pub struct Keyboard {
+    /// Input that has arrived but has not been read by the program yet.
+    pub input: VecDeque<u8>,
+    host: HostInput,
+}
+
+#[derive(Default)]
+enum HostInput {
+    /// Nothing but `input` will ever arrive.
+    #[default]
+    Closed,
+    /// Attached to stdin, which is only read once the program looks at the keyboard.
+    Pending,
+    Open(Receiver<u8>),
+}
+
+impl Keyboard {
+    /// A keyboard that types exactly these bytes.
+    pub fn scripted(input: &[u8]) -> Self {
+        Keyboard {
+            input: input.iter().copied().collect(),
+            host: HostInput::Closed,
+        }
+    }
+
+    /// A keyboard fed by the host's stdin, until it is closed.
+    pub fn stdin() -> Self {
+        Keyboard {
+            input: VecDeque::new(),
+            host: HostInput::Pending,
+        }
+    }
+
+    /// The next byte of input, if there is one yet.
+    pub fn read_data(&mut self) -> Option<u8> {
+        self.poll();
+        self.input.pop_front()
+    }
+
+    pub fn read_status(&mut self) -> u8 {
+        self.poll();
+        self.status()
+    }
+
+    /// What the program would read, without consuming anything.
+    pub fn peek_data(&self) -> u8 {
+        self.input.front().copied().unwrap_or(0x00)
+    }
+
+    pub fn status(&self) -> u8 {
+        if !self.input.is_empty() {
+            KEYBOARD_AVAILABLE
+        } else if matches!(self.host, HostInput::Closed) {
+            KEYBOARD_CLOSED
+        } else {
+            0x00
+        }
+    }
+
+    /// Move whatever the host has typed so far into the input, without blocking the machine.
+    fn poll(&mut self) {
+        if let HostInput::Pending = self.host {
+            // Reading stdin blocks: a thread does it, and hands the bytes over as they come.
+            let (sender, receiver) = mpsc::channel();
+            std::thread::spawn(move || {
+                for byte in std::io::stdin().lock().bytes() {
+                    let Ok(byte) = byte else { break };
+                    if sender.send(byte).is_err() {
+                        break;
+                    }
+                }
+            });
+            self.host = HostInput::Open(receiver);
+        }
+
+        if let HostInput::Open(receiver) = &self.host {
+            loop {
+                match receiver.try_recv() {
+                    Ok(byte) => self.input.push_back(byte),
+                    Err(TryRecvError::Empty) => return,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            }
+            self.host = HostInput::Closed;
+        }
+    }
+}
+
+/// Two keyboards are the same if the program would read the same from them.
+impl PartialEq for Keyboard {
+    fn eq(&self, other: &Self) -> bool {
+        self.input == other.input && self.status() == other.status()
+    }
+}
+
+impl Eq for Keyboard {}
@@ -0,0 +1,270 @@
+use crate::constants::general_register::GeneralRegister;
+use crate::constants::machine_instruction::MachineInstruction;
+use crate::emulate::emulator::Emulator;
+use crate::emulate::isa_emulator::IsaEmulator;
+use crate::emulate::machine_state::MachineState;
+use crate::emulate::memory::MEMORY_SIZE;
+use crate::emulate::microcode::Microcode;
+use crate::emulate::microcode_emulator::MicrocodeEmulator;
+use crate::word_bytes::WordBytes;
+use eyre::{bail, WrapErr};
+use std::sync::Arc;
+
+const PROGRAMS: u64 = 256;
+const PROGRAM_INSTRUCTIONS: usize = 48;
+const MAX_INSTRUCTIONS: u64 = 256;
+
+/// Values that sit on the edges of the ALU and of the pages, picked more often than the others.
+const INTERESTING_BYTES: [u8; 6] = [0x00, 0x01, 0x7F, 0x80, 0xFE, 0xFF];
+
+/// Xorshift generator: good enough to pick instructions, and reproducible from the seed printed on failure.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Random(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn byte(&mut self) -> u8 {
+        if self.below(4) == 0 {
+            INTERESTING_BYTES[self.below(INTERESTING_BYTES.len())]
+        } else {
+            self.next() as u8
+        }
+    }
+}
+
+fn opcode(instruction: MachineInstruction) -> u8 {
+    (0..=0xFF)
+        .find(|&value| MachineInstruction::from(value) == instruction)
+        .expect("every instruction has an opcode")
+}
+
+/// The operands that follow an opcode, resolved once the whole body has been laid out.
+enum Operand {
+    None,
+    Byte(u8),
+    /// Address of a random instruction of the body.
+    Absolute,
+    /// Offset to a random instruction of the body, or the closest one that is in range.
+    Relative,
+}
+
+impl Operand {
+    fn len(&self) -> u16 {
+        match self {
+            Operand::None => 0,
+            Operand::Byte(_) | Operand::Relative => 1,
+            Operand::Absolute => 2,
+        }
+    }
+}
+
+/// Generate a memory image: a prelude at 0x0000 sets up the stack and the registers, then jumps to a random body that
+/// straddles a page boundary, so that relative jumps and PJMP targets cross pages in both directions. Jump targets
+/// are always instruction boundaries of the body, the last instruction is HLT.
+fn random_program(random: &mut Random) -> Vec<u8> {
+    let mut body: Vec<(u8, Operand)> = Vec::new();
+    for _ in 0..PROGRAM_INSTRUCTIONS {
+        let value = random.next() as u8;
+        let operand = match MachineInstruction::from(value) {
+            // Halting early would make the rest of the program pointless.
+            MachineInstruction::HLT => continue,
+            MachineInstruction::LI { .. } | MachineInstruction::ADDI { .. } | MachineInstruction::SPOF => {
+                Operand::Byte(random.byte())
+            }
+            MachineInstruction::PJMP => Operand::Absolute,
+            MachineInstruction::JCR | MachineInstruction::JZR | MachineInstruction::JNR | MachineInstruction::JLTR => {
+                Operand::Relative
+            }
+            _ => Operand::None,
+        };
+        body.push((value, operand));
+    }
+    body.push((opcode(MachineInstruction::HLT), Operand::None));
+
+    let body_length: u16 = body.iter().map(|(_, operand)| 1 + operand.len()).sum();
+    let page = 1 + random.below(0x70) as u16;
+    let origin = (page << 8) + 0x100 - random.below(body_length as usize) as u16;
+
+    let mut addresses = Vec::new();
+    let mut address = origin;
+    for (_, operand) in body.iter() {
+        addresses.push(address);
+        address += 1 + operand.len();
+    }
+
+    // Keep the stack far from the code, close to a page boundary.
+    let stack_pointer = u16::from_be_bytes([0x80 | random.byte(), random.byte()]);
+    let (a, b, c, d) = (
+        GeneralRegister::A,
+        GeneralRegister::B,
+        GeneralRegister::C,
+        GeneralRegister::D,
+    );
+    let prelude = [
+        vec![opcode(MachineInstruction::LI { dst: a }), stack_pointer.low()],
+        vec![opcode(MachineInstruction::LI { dst: b }), stack_pointer.high()],
+        vec![opcode(MachineInstruction::SPSL { src: a })],
+        vec![opcode(MachineInstruction::SPSH { src: b })],
+        vec![opcode(MachineInstruction::LI { dst: a }), random.byte()],
+        vec![opcode(MachineInstruction::LI { dst: b }), random.byte()],
+        vec![opcode(MachineInstruction::LI { dst: c }), random.byte()],
+        vec![opcode(MachineInstruction::LI { dst: d }), random.byte()],
+        vec![opcode(MachineInstruction::PJMP), origin.high(), origin.low()],
+        vec![opcode(MachineInstruction::JMP)],
+    ];
+
+    let mut program: Vec<u8> = prelude.concat();
+    program.resize(origin as usize, opcode(MachineInstruction::HLT));
+    for (index, (value, operand)) in body.iter().enumerate() {
+        program.push(*value);
+        match operand {
+            Operand::None => {}
+            Operand::Byte(byte) => program.push(*byte),
+            Operand::Absolute => program.extend(addresses[random.below(addresses.len())].to_be_bytes()),
+            Operand::Relative => {
+                let target = addresses[random.below(addresses.len())];
+                let offset_address = addresses[index] + 1;
+                // Pick the closest instruction that is reachable with a signed byte.
+                let reachable = addresses
+                    .iter()
+                    .filter(|&&address| (address as i32 - offset_address as i32).abs() <= i8::MAX as i32)
+                    .min_by_key(|&&address| (address as i32 - target as i32).abs())
+                    .expect("the next instruction is always reachable");
+                program.push(reachable.wrapping_sub(offset_address) as u8);
+            }
+        }
+    }
+
+    program
+}
+
+/// Names of the architectural registers that differ between the two states. The ALU registers are not architectural,
+/// only the flags computed from them are, and only while the ISA defines them.
+pub(super) fn differences(microcode: &MachineState, isa_emulator: &IsaEmulator) -> Vec<String> {
+    let isa = &isa_emulator.state;
+    let mut differences = Vec::new();
+    for (index, name) in ["A", "B", "C", "D"].into_iter().enumerate() {
+        if microcode.registers[index] != isa.registers[index] {
+            differences.push(name.to_string());
+        }
+    }
+    if isa_emulator.flags_defined && microcode.flags() != isa.flags() {
+        differences.push("flags".to_string());
+    }
+    for (name, microcode, isa) in [
+        ("IP", microcode.instruction_pointer, isa.instruction_pointer),
+        ("SP", microcode.stack_pointer, isa.stack_pointer),
+        ("JMP", microcode.jump_register, isa.jump_register),
+        ("RET", microcode.return_register, isa.return_register),
+        ("MEM", microcode.memory_register, isa.memory_register),
+    ] {
+        if microcode != isa {
+            differences.push(name.to_string());
+        }
+    }
+    differences
+}
+
+/// Run the program in lockstep on both emulators, and fail at the first instruction after which they disagree.
+fn compare(microcode: Arc<Microcode>, program: &[u8]) -> eyre::Result<()> {
+    let mut microcode_emulator = MicrocodeEmulator::new(microcode, program)?;
+    let mut isa_emulator = IsaEmulator::new(program)?;
+
+    for executed in 0..MAX_INSTRUCTIONS {
+        if isa_emulator.halted {
+            break;
+        }
+
+        let address = isa_emulator.state.instruction_pointer;
+        let value = isa_emulator.state.memory.peek(address);
+        // Every store goes through the memory register, so the only byte an instruction can touch is the one it
+        // points to before running.
+        let memory_register = isa_emulator.state.memory_register;
+
+        microcode_emulator
+            .step_instruction()
+            .wrap_err_with(|| format!("executing {:?} at {:0>4x}", MachineInstruction::from(value), address))?;
+        isa_emulator.step_instruction()?;
+
+        let mut differences = differences(&microcode_emulator.state, &isa_emulator);
+        if microcode_emulator.halted != isa_emulator.halted {
+            differences.push("halted".to_string());
+        }
+        let microcode_byte = microcode_emulator.state.memory.peek(memory_register);
+        let isa_byte = isa_emulator.state.memory.peek(memory_register);
+        if microcode_byte != isa_byte {
+            differences.push(format!(
+                "mem[{:0>4x}] ({:0>2x} != {:0>2x})",
+                memory_register, microcode_byte, isa_byte
+            ));
+        }
+
+        if !differences.is_empty() {
+            bail!(
+                "divergence in {} after {} instructions, executing {:?} at {:0>4x}\nmicrocode:\n{}isa:\n{}",
+                differences.join(", "),
+                executed,
+                MachineInstruction::from(value),
+                address,
+                microcode_emulator.state,
+                isa_emulator.state
+            );
+        }
+
+        // Undefined flags are whatever the hardware leaves in the ALU: take those, so that a program branching on
+        // them still runs the same way on both.
+        if !isa_emulator.flags_defined {
+            isa_emulator.state.alu1 = microcode_emulator.state.alu1;
+            isa_emulator.state.alu2 = microcode_emulator.state.alu2;
+        }
+    }
+
+    if microcode_emulator.state.memory == isa_emulator.state.memory {
+        return Ok(());
+    }
+    for address in 0..MEMORY_SIZE {
+        let address = address as u16;
+        let microcode_byte = microcode_emulator.state.memory.peek(address);
+        let isa_byte = isa_emulator.state.memory.peek(address);
+        if microcode_byte != isa_byte {
+            bail!(
+                "divergence in mem[{:0>4x}]: microcode {:0>2x}, isa {:0>2x}",
+                address,
+                microcode_byte,
+                isa_byte
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_programs() -> eyre::Result<()> {
+        let microcode = Arc::new(Microcode::from_steps());
+
+        for seed in 0..PROGRAMS {
+            let program = random_program(&mut Random::new(seed));
+            compare(microcode.clone(), &program).wrap_err_with(|| format!("random program with seed {}", seed))?;
+        }
+
+        Ok(())
+    }
+}
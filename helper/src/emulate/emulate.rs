@@ -0,0 +1,381 @@
+use crate::assemble::assemble::assemble_with_symbols;
+use crate::assemble::symbols::Symbols;
+use crate::emulate::coverage::Coverage;
+use crate::emulate::devices::Keyboard;
+use crate::emulate::emulator::Emulator;
+use crate::emulate::framebuffer;
+use crate::emulate::isa_emulator::IsaEmulator;
+use crate::emulate::machine_state::MachineState;
+use crate::emulate::microcode::Microcode;
+use crate::emulate::microcode_emulator::MicrocodeEmulator;
+use crate::emulate::profile::Profile;
+use crate::emulate::snapshot::Snapshot;
+use crate::emulate::stack::StackCheck;
+use crate::emulate::strict::StrictCheck;
+use crate::emulate::trace::Trace;
+use crate::emulate::vcd::Vcd;
+use eyre::bail;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Arc;
+
+const DEFAULT_MAX_INSTRUCTIONS: u64 = 1_000_000;
+/// How often the live display looks for changes in the framebuffer, in instructions.
+const DISPLAY_INTERVAL: u64 = 1_000;
+
+pub struct EmulateOptions {
+    pub file: String,
+    pub max_instructions: u64,
+    /// Run the instruction level emulator instead of the microcode one.
+    pub isa: bool,
+    /// Feed the keyboard from this file instead of stdin.
+    pub input: Option<String>,
+    /// Save the framebuffer to this PPM image at `HLT`.
+    pub framebuffer: Option<String>,
+    /// Draw the framebuffer on the terminal while the program runs.
+    pub display: bool,
+    /// Report where the clock cycles went.
+    pub profile: bool,
+    /// Show which lines of the source were executed and which way the conditional jumps went.
+    pub coverage: bool,
+    /// Resume from this snapshot instead of starting from reset.
+    pub restore: Option<String>,
+    /// Save a snapshot to this file when the run stops, whether the machine halted or not.
+    pub snapshot: Option<String>,
+    /// Stop at the first stack overflow, underflow or SPOF outside the stack.
+    pub stack_check: bool,
+    /// Stop at the first read of uninitialized RAM, execution of data or unused opcode.
+    pub strict: bool,
+    /// Write every executed instruction to this file, as JSON Lines if it ends in `.jsonl`.
+    pub trace: Option<String>,
+    /// Add every clock cycle of the instructions to the trace.
+    pub trace_microsteps: bool,
+    /// Write the control lines, the bus and the registers of every clock cycle to this Value Change Dump.
+    pub vcd: Option<String>,
+    /// Run the microcode of the `rom01.img` and `rom02.img` burned in this directory instead of the one in the source.
+    pub roms: Option<String>,
+}
+
+impl EmulateOptions {
+    pub fn parse(args: &[String]) -> eyre::Result<Self> {
+        let mut file = None;
+        let mut max_instructions = DEFAULT_MAX_INSTRUCTIONS;
+        let mut isa = false;
+        let mut input = None;
+        let mut framebuffer = None;
+        let mut display = false;
+        let mut profile = false;
+        let mut coverage = false;
+        let mut restore = None;
+        let mut snapshot = None;
+        let mut stack_check = false;
+        let mut strict = false;
+        let mut trace = None;
+        let mut trace_microsteps = false;
+        let mut vcd = None;
+        let mut roms = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--max-instructions" => {
+                    let Some(value) = args.next() else {
+                        bail!("--max-instructions requires a value");
+                    };
+                    max_instructions = value.parse()?;
+                }
+                "--isa" => isa = true,
+                "--input" => {
+                    let Some(value) = args.next() else {
+                        bail!("--input requires a file");
+                    };
+                    input = Some(value.to_string());
+                }
+                "--framebuffer" => {
+                    let Some(value) = args.next() else {
+                        bail!("--framebuffer requires a file");
+                    };
+                    framebuffer = Some(value.to_string());
+                }
+                "--display" => display = true,
+                "--profile" => profile = true,
+                "--coverage" => coverage = true,
+                "--stack-check" => stack_check = true,
+                "--strict" => strict = true,
+                "--trace" => {
+                    let Some(value) = args.next() else {
+                        bail!("--trace requires a file");
+                    };
+                    trace = Some(value.to_string());
+                }
+                "--trace-microsteps" => trace_microsteps = true,
+                "--vcd" => {
+                    let Some(value) = args.next() else {
+                        bail!("--vcd requires a file");
+                    };
+                    vcd = Some(value.to_string());
+                }
+                "--roms" => {
+                    let Some(value) = args.next() else {
+                        bail!("--roms requires the directory of rom01.img and rom02.img");
+                    };
+                    roms = Some(value.to_string());
+                }
+                "--restore" => {
+                    let Some(value) = args.next() else {
+                        bail!("--restore requires a file");
+                    };
+                    restore = Some(value.to_string());
+                }
+                "--snapshot" => {
+                    let Some(value) = args.next() else {
+                        bail!("--snapshot requires a file");
+                    };
+                    snapshot = Some(value.to_string());
+                }
+                other if other.starts_with("--") => bail!("Unknown option: {}", other),
+                other if file.is_none() => file = Some(other.to_string()),
+                other => bail!("Unexpected argument: {}", other),
+            }
+        }
+
+        if isa && profile {
+            bail!("--profile counts clock cycles, it needs the microcode emulator");
+        }
+        if strict && restore.is_some() {
+            bail!("--strict tracks which RAM was written from reset, it cannot resume from a snapshot");
+        }
+        if trace_microsteps && (isa || trace.is_none()) {
+            bail!("--trace-microsteps adds the clock cycles to --trace, it needs the microcode emulator");
+        }
+        if isa && vcd.is_some() {
+            bail!("--vcd dumps the control lines, it needs the microcode emulator");
+        }
+        if isa && roms.is_some() {
+            bail!("--roms runs the burned microcode, it needs the microcode emulator");
+        }
+
+        let Some(file) = file else {
+            bail!("Usage: emulate <file.as> [--isa] [--max-instructions <instructions>] [--input <file>] [--framebuffer <file.ppm>] [--display] [--profile] [--coverage] [--restore <snapshot>] [--snapshot <snapshot>] [--stack-check] [--strict] [--trace <file[.jsonl]>] [--trace-microsteps] [--vcd <file.vcd>] [--roms <dir>]");
+        };
+
+        Ok(EmulateOptions {
+            file,
+            max_instructions,
+            isa,
+            input,
+            framebuffer,
+            display,
+            profile,
+            coverage,
+            restore,
+            snapshot,
+            stack_check,
+            strict,
+            trace,
+            trace_microsteps,
+            vcd,
+            roms,
+        })
+    }
+}
+
+/// Returns the exit status of the program: the one it gave to the host, or 0 if it stopped with HLT.
+pub fn emulate(options: EmulateOptions) -> eyre::Result<u8> {
+    let file_contents = fs::read(&options.file)?;
+    let file_contents = String::from_utf8(file_contents)?;
+    let file_contents: &'static str = file_contents.leak();
+
+    let (program, symbols) = assemble_with_symbols(file_contents)?;
+
+    // stdout belongs to the program's console, the summary goes to stderr.
+    let (exit, coverage) = if options.isa {
+        let mut emulator = IsaEmulator::new(&program.0)?;
+        restore(&mut emulator, &options)?;
+        let coverage = run_checked(&mut emulator, &options, &program.0, &symbols, |_| Ok(()))?;
+        (emulator.state.memory.host.exit, coverage)
+    } else {
+        let microcode = match &options.roms {
+            Some(dir) => {
+                let dir = Path::new(dir);
+                Microcode::from_images(
+                    &fs::read_to_string(dir.join("rom01.img"))?,
+                    &fs::read_to_string(dir.join("rom02.img"))?,
+                )?
+            }
+            None => Microcode::from_steps(),
+        };
+        let mut emulator = MicrocodeEmulator::new(Arc::new(microcode), &program.0)?;
+        restore(&mut emulator, &options)?;
+        if options.trace.is_some() || options.vcd.is_some() {
+            emulator.clocks = Some(Vec::new());
+        }
+        let mut profile = Profile::new(&emulator);
+        let mut vcd = match &options.vcd {
+            Some(file) => Some(Vcd::create(file, &emulator)?),
+            None => None,
+        };
+        let coverage = run_checked(&mut emulator, &options, &program.0, &symbols, |emulator| {
+            if let Some(vcd) = &mut vcd {
+                vcd.after_instruction(emulator.clocks())?;
+            }
+            if options.profile {
+                profile.after_instruction(emulator);
+            }
+            Ok(())
+        })?;
+        if options.profile {
+            eprint!("\n{}", profile.report(&symbols, &emulator.state.memory));
+        }
+        (emulator.state.memory.host.exit, coverage)
+    };
+
+    if options.coverage {
+        eprint!("\n{}", coverage.listing(file_contents, &symbols, &program.0));
+    }
+    Ok(exit.unwrap_or(0))
+}
+
+/// Run the program with the options both emulators support, then print how it stopped and the final state.
+/// `after_instruction` handles the options only the caller knows about. The coverage is returned so that the caller
+/// can print it after its own reports.
+fn run_checked<E: Emulator>(
+    emulator: &mut E,
+    options: &EmulateOptions,
+    program: &[u8],
+    symbols: &Symbols,
+    mut after_instruction: impl FnMut(&E) -> eyre::Result<()>,
+) -> eyre::Result<Coverage> {
+    let mut coverage = Coverage::new(emulator);
+    let mut stack = StackCheck::new(emulator, program);
+    let mut strict = StrictCheck::new(program, symbols);
+    if options.strict {
+        strict.check_next(emulator, symbols)?;
+    }
+    let mut trace = trace(emulator, options)?;
+    let instructions = run(emulator, options, |emulator| {
+        if let Some(trace) = &mut trace {
+            trace.after_instruction(emulator, emulator.clocks())?;
+        }
+        after_instruction(emulator)?;
+        if options.coverage {
+            coverage.after_instruction(emulator);
+        }
+        if options.stack_check {
+            stack.after_instruction(emulator, symbols)?;
+        }
+        if options.strict {
+            strict.check_next(emulator, symbols)?;
+        }
+        Ok(())
+    })?;
+
+    match emulator.cycles() {
+        Some(cycles) => eprintln!(
+            "{} after {} instructions ({} cycles)",
+            stopped(emulator.state()),
+            instructions,
+            cycles
+        ),
+        None => eprintln!("{} after {} instructions", stopped(emulator.state()), instructions),
+    }
+    eprint!("{}", emulator.state());
+    Ok(coverage)
+}
+
+/// How the program stopped: with HLT, or through the host.
+fn stopped(state: &MachineState) -> String {
+    match state.memory.host.exit {
+        Some(status) => format!("Exited with status {}", status),
+        None => "Halted".to_string(),
+    }
+}
+
+/// Start from the snapshot, if any: the program is still assembled for its labels, but the RAM comes from the snapshot.
+fn restore(emulator: &mut impl Emulator, options: &EmulateOptions) -> eyre::Result<()> {
+    if let Some(file) = &options.restore {
+        emulator.restore(&Snapshot::parse(&fs::read_to_string(file)?)?)?;
+    }
+    Ok(())
+}
+
+fn trace(emulator: &impl Emulator, options: &EmulateOptions) -> eyre::Result<Option<Trace<BufWriter<File>>>> {
+    match &options.trace {
+        Some(file) => Ok(Some(Trace::create(file, options.trace_microsteps, emulator)?)),
+        None => Ok(None),
+    }
+}
+
+/// Attach the devices to the host, and run the program until it halts.
+fn run<E: Emulator>(
+    emulator: &mut E,
+    options: &EmulateOptions,
+    mut after_instruction: impl FnMut(&E) -> eyre::Result<()>,
+) -> eyre::Result<u64> {
+    let memory = &mut emulator.state_mut().memory;
+    memory.console.echo = true;
+    let pending = std::mem::take(&mut memory.keyboard.input);
+    memory.keyboard = match &options.input {
+        Some(file) => Keyboard::scripted(&fs::read(file)?),
+        None => Keyboard::stdin(),
+    };
+    // The input a snapshot had not read yet comes first.
+    let input = std::mem::take(&mut memory.keyboard.input);
+    memory.keyboard.input = pending.into_iter().chain(input).collect();
+
+    let mut display = Display::default();
+    let result = emulator.run(options.max_instructions, |emulator| {
+        if options.display {
+            display.refresh(emulator.state(), false);
+        }
+        after_instruction(emulator)
+    });
+    if let Some(file) = &options.snapshot {
+        fs::write(file, emulator.snapshot().to_string())?;
+    }
+    let instructions = result?;
+    if options.display {
+        display.refresh(emulator.state(), true);
+    }
+
+    let memory = &emulator.state().memory;
+    if memory.console.output.last().is_some_and(|byte| *byte != b'\n') {
+        // Keep the summary on its own line.
+        eprintln!();
+    }
+    if let Some(file) = &options.framebuffer {
+        fs::write(file, framebuffer::to_ppm(&framebuffer::pixels(memory)))?;
+    }
+
+    Ok(instructions)
+}
+
+/// Draws the framebuffer on the terminal while the program runs.
+#[derive(Default)]
+struct Display {
+    instructions: u64,
+    /// What is on the screen, so that it is only redrawn when the program changes it.
+    shown: Option<Vec<u8>>,
+}
+
+impl Display {
+    fn refresh(&mut self, state: &MachineState, force: bool) {
+        self.instructions += 1;
+        if !force && !self.instructions.is_multiple_of(DISPLAY_INTERVAL) {
+            return;
+        }
+
+        let pixels = framebuffer::pixels(&state.memory);
+        if self.shown.as_ref() == Some(&pixels) {
+            return;
+        }
+        if self.shown.is_none() {
+            // Clear the screen once, then draw over the previous frame.
+            eprint!("\x1b[2J");
+        }
+        eprint!("\x1b[H{}", framebuffer::to_terminal(&pixels));
+        self.shown = Some(pixels);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::emulate::machine_state::MachineState;
+use crate::emulate::snapshot::Snapshot;
+use crate::emulate::trace::Clock;
+use eyre::bail;
+
+/// Common interface of the emulators, so that the tools built on top of them can drive either one.
+pub trait Emulator {
+    fn state(&self) -> &MachineState;
+
+    fn state_mut(&mut self) -> &mut MachineState;
+
+    fn halted(&self) -> bool;
+
+    /// Execute a whole instruction, fetch included.
+    fn step_instruction(&mut self) -> eyre::Result<()>;
+
+    /// Everything needed to resume the run later.
+    fn snapshot(&self) -> Snapshot;
+
+    fn restore(&mut self, snapshot: &Snapshot) -> eyre::Result<()>;
+
+    /// The clock cycles executed since reset, for the emulators that have them.
+    fn cycles(&self) -> Option<u64> {
+        None
+    }
+
+    /// The clock cycles of the last instruction, when the emulator records them.
+    fn clocks(&self) -> &[Clock] {
+        &[]
+    }
+
+    /// Run until the machine halts, calling back after every instruction: an error from the callback stops the run.
+    /// Returns the number of executed instructions.
+    fn run(
+        &mut self,
+        max_instructions: u64,
+        mut after_instruction: impl FnMut(&Self) -> eyre::Result<()>,
+    ) -> eyre::Result<u64>
+    where
+        Self: Sized,
+    {
+        let mut instructions = 0;
+        while !self.halted() {
+            if instructions >= max_instructions {
+                bail!("the machine did not halt within {} instructions", max_instructions);
+            }
+            self.step_instruction()?;
+            instructions += 1;
+            after_instruction(self)?;
+        }
+        Ok(instructions)
+    }
+}
@@ -0,0 +1,335 @@
+//! Runs every opcode on both emulators from every starting state that can influence it, and compares the results.
+//!
+//! The flags are not a register of their own, they are computed from A1 and A2 by the operation the control word
+//! selects, so they can change in the middle of a step. Whether an instruction depends on them is decided over all 16
+//! values of `Flags`, and the ones that do start from one (A1, A2) pair for each way the ALU can set the flags under
+//! its four operations, with and without carry in. This covers every value the control ROM can see, except the four
+//! with both FZ and NEG set: a result cannot be zero and negative at the same time.
+
+use crate::constants::flag::Flags;
+use crate::constants::general_register::GeneralRegister;
+use crate::constants::machine_instruction::{steps, MachineInstruction};
+use crate::emulate::alu::{alu, AluOperation};
+use crate::emulate::differential::differences;
+use crate::emulate::emulator::Emulator;
+use crate::emulate::isa_emulator::IsaEmulator;
+use crate::emulate::machine_state::MachineState;
+use crate::emulate::microcode::Microcode;
+use crate::emulate::microcode_emulator::MicrocodeEmulator;
+use crate::word_bytes::WordBytes;
+use eyre::{bail, WrapErr};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+/// Where every case starts from: distinct values everywhere, so that a wrong select line shows up as a difference.
+const REGISTERS: [u8; 4] = [0x11, 0x22, 0x33, 0x44];
+const INSTRUCTION_POINTER: u16 = 0x1234;
+const STACK_POINTER: u16 = 0x8765;
+const JUMP_REGISTER: u16 = 0x2345;
+const RETURN_REGISTER: u16 = 0x3456;
+const MEMORY_REGISTER: u16 = 0x4567;
+
+/// Sets up the operands of an instruction from a value of its domain.
+type Setup = fn(&mut MachineState, u32, MachineInstruction);
+
+/// The values that can influence the result of an instruction, and how to put them in the machine. The domains are
+/// small enough to be enumerated completely.
+fn domain(instruction: MachineInstruction) -> (u32, Setup) {
+    match instruction {
+        MachineInstruction::NOP
+        | MachineInstruction::HLT
+        | MachineInstruction::ZERO { .. }
+        | MachineInstruction::PEEK => (1, |_, _, _| {}),
+        MachineInstruction::MV { .. }
+        | MachineInstruction::MEMW { .. }
+        | MachineInstruction::MSRL { .. }
+        | MachineInstruction::MSRH { .. }
+        | MachineInstruction::SPSL { .. }
+        | MachineInstruction::SPSH { .. }
+        | MachineInstruction::INC { .. }
+        | MachineInstruction::DEC { .. }
+        | MachineInstruction::NEG { .. } => (0x100, |state, value, instruction| {
+            state.set_register(register_of(instruction), value as u8)
+        }),
+        MachineInstruction::MEMR { .. } | MachineInstruction::RTRL | MachineInstruction::RTRH => {
+            (0x100, |state, value, _| {
+                state.memory.write(state.memory_register, value as u8)
+            })
+        }
+        MachineInstruction::LI { .. } => (0x100, |state, value, _| {
+            state
+                .memory
+                .write(state.instruction_pointer.wrapping_add(1), value as u8)
+        }),
+        MachineInstruction::RTWL | MachineInstruction::RTWH => {
+            (0x1_00_00, |state, value, _| state.return_register = value as u16)
+        }
+        MachineInstruction::ADD { acc, val }
+        | MachineInstruction::SUB { acc, val }
+        | MachineInstruction::NAND { acc, val }
+        | MachineInstruction::XOR { acc, val }
+            if acc == val =>
+        {
+            (0x100, |state, value, instruction| {
+                state.set_register(register_of(instruction), value as u8)
+            })
+        }
+        MachineInstruction::ADD { .. }
+        | MachineInstruction::SUB { .. }
+        | MachineInstruction::NAND { .. }
+        | MachineInstruction::XOR { .. } => (0x1_00_00, |state, value, instruction| {
+            let (acc, val) = match instruction {
+                MachineInstruction::ADD { acc, val }
+                | MachineInstruction::SUB { acc, val }
+                | MachineInstruction::NAND { acc, val }
+                | MachineInstruction::XOR { acc, val } => (acc, val),
+                _ => unreachable!(),
+            };
+            state.set_register(acc, (value >> 8) as u8);
+            state.set_register(val, value as u8);
+        }),
+        MachineInstruction::ADDI { .. } => (0x1_00_00, |state, value, instruction| {
+            state.set_register(register_of(instruction), (value >> 8) as u8);
+            state
+                .memory
+                .write(state.instruction_pointer.wrapping_add(1), value as u8);
+        }),
+        MachineInstruction::PJMP => (0x1_00_00, |state, value, _| {
+            state
+                .memory
+                .write(state.instruction_pointer.wrapping_add(1), (value >> 8) as u8);
+            state
+                .memory
+                .write(state.instruction_pointer.wrapping_add(2), value as u8);
+        }),
+        MachineInstruction::JMP | MachineInstruction::JAL => {
+            (0x1_00_00, |state, value, _| state.jump_register = value as u16)
+        }
+        MachineInstruction::RET => (0x1_00_00, |state, value, _| state.return_register = value as u16),
+        // Every position of the instruction in its page, with every offset.
+        MachineInstruction::JCR | MachineInstruction::JZR | MachineInstruction::JNR | MachineInstruction::JLTR => {
+            (0x1_00_00, |state, value, _| {
+                state.instruction_pointer = state.instruction_pointer.with_byte(false, (value >> 8) as u8);
+                state
+                    .memory
+                    .write(state.instruction_pointer.wrapping_add(1), value as u8);
+            })
+        }
+        MachineInstruction::PUSH | MachineInstruction::PULL => {
+            (0x1_00_00, |state, value, _| state.stack_pointer = value as u16)
+        }
+        // Every offset from every position in the page, on a regular page and on the last one.
+        MachineInstruction::SPOF => (0x2_00_00, |state, value, _| {
+            let high = if value > 0xFFFF { 0xFF } else { STACK_POINTER.high() };
+            state.stack_pointer = u16::from_be_bytes([high, (value >> 8) as u8]);
+            state
+                .memory
+                .write(state.instruction_pointer.wrapping_add(1), value as u8);
+        }),
+    }
+}
+
+/// The only register operand of an instruction.
+fn register_of(instruction: MachineInstruction) -> GeneralRegister {
+    match instruction {
+        MachineInstruction::MV { src, .. }
+        | MachineInstruction::MEMW { src }
+        | MachineInstruction::MSRL { src }
+        | MachineInstruction::MSRH { src }
+        | MachineInstruction::SPSL { src }
+        | MachineInstruction::SPSH { src } => src,
+        MachineInstruction::INC { dst }
+        | MachineInstruction::DEC { dst }
+        | MachineInstruction::NEG { dst }
+        | MachineInstruction::ADDI { dst } => dst,
+        MachineInstruction::ADD { acc, .. }
+        | MachineInstruction::SUB { acc, .. }
+        | MachineInstruction::NAND { acc, .. }
+        | MachineInstruction::XOR { acc, .. } => acc,
+        _ => unreachable!("{:?} has no single register operand", instruction),
+    }
+}
+
+/// Every way the ALU can compute the flags: the control word picks one of them at each step.
+const ALU_MODES: [(AluOperation, bool); 8] = [
+    (AluOperation::Add, false),
+    (AluOperation::Add, true),
+    (AluOperation::Subtract, false),
+    (AluOperation::Subtract, true),
+    (AluOperation::Nand, false),
+    (AluOperation::Nand, true),
+    (AluOperation::Xor, false),
+    (AluOperation::Xor, true),
+];
+
+/// An (A1, A2) pair for each combination of the flags they give under every ALU mode: the microcode cannot tell apart
+/// two pairs from the same combination.
+fn flags_representatives() -> Vec<(u8, u8)> {
+    let mut representatives: BTreeMap<[u8; 8], (u8, u8)> = BTreeMap::new();
+    for alu1 in 0..=0xFF {
+        for alu2 in 0..=0xFF {
+            let flags = ALU_MODES.map(|(operation, carry_in)| alu(alu1, alu2, operation, carry_in).flags.value());
+            representatives.entry(flags).or_insert((alu1, alu2));
+        }
+    }
+    representatives.into_values().collect()
+}
+
+/// Whether the microprogram of the instruction depends on the flags at all: if it does not, the flags the
+/// instruction starts with cannot change its result.
+fn depends_on_flags(instruction: MachineInstruction) -> bool {
+    let unconditional = steps(instruction, Flags::from(0));
+    (1..=0b1111).any(|flags| steps(instruction, Flags::from(flags)) != unconditional)
+}
+
+struct Checker {
+    microcode_emulator: MicrocodeEmulator,
+    isa_emulator: IsaEmulator,
+    flags_representatives: Vec<(u8, u8)>,
+}
+
+impl Checker {
+    fn new() -> eyre::Result<Self> {
+        Ok(Checker {
+            microcode_emulator: MicrocodeEmulator::new(Arc::new(Microcode::from_steps()), &[])?,
+            isa_emulator: IsaEmulator::new(&[])?,
+            flags_representatives: flags_representatives(),
+        })
+    }
+
+    /// Execute the instruction from every starting point of its domain, with every starting flags combination it
+    /// could care about.
+    fn check_opcodes(&mut self, opcodes: RangeInclusive<u8>) -> eyre::Result<()> {
+        for opcode in opcodes {
+            let instruction = MachineInstruction::from(opcode);
+            let (size, setup) = domain(instruction);
+            let flags = if depends_on_flags(instruction) {
+                self.flags_representatives.clone()
+            } else {
+                self.flags_representatives[..1].to_vec()
+            };
+
+            for (alu1, alu2) in flags {
+                for value in 0..size {
+                    self.check(opcode, |state| {
+                        state.alu1 = alu1;
+                        state.alu2 = alu2;
+                        setup(state, value, instruction);
+                    })
+                    .wrap_err_with(|| {
+                        format!(
+                            "{:?} (opcode {:0>2x}) with A1={:0>2x} A2={:0>2x} and operands {:0>4x}",
+                            instruction, opcode, alu1, alu2, value
+                        )
+                    })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check(&mut self, opcode: u8, setup: impl Fn(&mut MachineState)) -> eyre::Result<()> {
+        for state in [&mut self.microcode_emulator.state, &mut self.isa_emulator.state] {
+            state.registers = REGISTERS;
+            state.instruction_pointer = INSTRUCTION_POINTER;
+            state.stack_pointer = STACK_POINTER;
+            state.jump_register = JUMP_REGISTER;
+            state.return_register = RETURN_REGISTER;
+            state.memory_register = MEMORY_REGISTER;
+            setup(state);
+            state.memory.write(state.instruction_pointer, opcode);
+        }
+        self.microcode_emulator.halted = false;
+        self.microcode_emulator.step = 0;
+        self.isa_emulator.halted = false;
+        self.isa_emulator.flags_defined = true;
+
+        // Every byte the instruction reads or could write, whether it goes through the memory register or the IP.
+        let before = &self.isa_emulator.state;
+        let touched = [
+            before.memory_register,
+            before.stack_pointer,
+            before.instruction_pointer,
+            before.instruction_pointer.wrapping_add(1),
+            before.instruction_pointer.wrapping_add(2),
+        ];
+
+        self.microcode_emulator.step_instruction()?;
+        self.isa_emulator.step_instruction()?;
+
+        let mut differences = differences(&self.microcode_emulator.state, &self.isa_emulator);
+        let microcode = &self.microcode_emulator.state;
+        let isa = &self.isa_emulator.state;
+        if self.microcode_emulator.halted != self.isa_emulator.halted {
+            differences.push("halted".to_string());
+        }
+        if !self.microcode_emulator.halted && self.microcode_emulator.step != 0 {
+            differences.push(format!("step {}", self.microcode_emulator.step));
+        }
+        for address in touched.into_iter().chain([isa.memory_register, isa.stack_pointer]) {
+            if microcode.memory.peek(address) != isa.memory.peek(address) {
+                differences.push(format!("mem[{:0>4x}]", address));
+            }
+        }
+
+        if !differences.is_empty() {
+            bail!(
+                "divergence in {}\nmicrocode:\n{}isa:\n{}",
+                differences.join(", "),
+                microcode,
+                isa
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::flag::Flag;
+
+    #[test]
+    fn test_flags_representatives() {
+        let covered: Vec<u8> = flags_representatives()
+            .into_iter()
+            .flat_map(|(alu1, alu2)| ALU_MODES.map(|(operation, carry_in)| alu(alu1, alu2, operation, carry_in).flags))
+            .map(|flags| flags.value())
+            .collect();
+        for value in 0..=0b1111 {
+            let flags = Flags::from(value);
+            let impossible = flags.has(Flag::FZ) && flags.has(Flag::NEG);
+            assert_eq!(covered.contains(&value), !impossible, "{:0>4b}", value);
+        }
+    }
+
+    // Split along the groups of the encoding, so that the tests run in parallel.
+
+    #[test]
+    fn test_moves_and_memory() -> eyre::Result<()> {
+        Checker::new()?.check_opcodes(0x00..=0x3F)
+    }
+
+    #[test]
+    fn test_alu() -> eyre::Result<()> {
+        Checker::new()?.check_opcodes(0x40..=0x7F)
+    }
+
+    #[test]
+    fn test_immediate_and_unary_alu() -> eyre::Result<()> {
+        Checker::new()?.check_opcodes(0x80..=0xBF)
+    }
+
+    #[test]
+    fn test_jumps() -> eyre::Result<()> {
+        Checker::new()?.check_opcodes(0xC0..=0xCF)
+    }
+
+    #[test]
+    fn test_stack() -> eyre::Result<()> {
+        Checker::new()?.check_opcodes(0xD0..=0xFF)
+    }
+}
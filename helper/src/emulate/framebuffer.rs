@@ -0,0 +1,102 @@
+use crate::emulate::memory::Memory;
+use std::fmt::Write;
+
+/// The framebuffer is plain RAM, read by the display: one byte per pixel, row after row.
+pub const FRAMEBUFFER: u16 = 0xF800;
+pub const WIDTH: usize = 32;
+pub const HEIGHT: usize = 32;
+pub const FRAMEBUFFER_SIZE: usize = WIDTH * HEIGHT;
+
+/// The pixels of the framebuffer, as they are in memory.
+pub fn pixels(memory: &Memory) -> Vec<u8> {
+    (0..FRAMEBUFFER_SIZE)
+        .map(|offset| memory.peek(FRAMEBUFFER + offset as u16))
+        .collect()
+}
+
+/// Pixels are RGB332: three bits of red, three of green and two of blue.
+pub fn color(pixel: u8) -> [u8; 3] {
+    let red = pixel >> 5;
+    let green = (pixel >> 2) & 0b111;
+    let blue = pixel & 0b11;
+    [scale(red, 0b111), scale(green, 0b111), scale(blue, 0b11)]
+}
+
+/// Stretch a color component from `0..=max` to `0..=0xFF`, rounding to the closest.
+fn scale(component: u8, max: u16) -> u8 {
+    ((component as u16 * 0xFF + max / 2) / max) as u8
+}
+
+/// Binary PPM image of the framebuffer.
+pub fn to_ppm(pixels: &[u8]) -> Vec<u8> {
+    let mut image = format!("P6\n{} {}\n255\n", WIDTH, HEIGHT).into_bytes();
+    for pixel in pixels {
+        image.extend_from_slice(&color(*pixel));
+    }
+    image
+}
+
+/// The framebuffer drawn with true color escape codes: every character is an upper half block, so that it shows two
+/// pixels stacked on top of each other.
+pub fn to_terminal(pixels: &[u8]) -> String {
+    let mut output = String::new();
+    for rows in pixels.chunks(2 * WIDTH) {
+        let (top, bottom) = rows.split_at(WIDTH);
+        for (top, bottom) in top.iter().zip(bottom) {
+            let [top_red, top_green, top_blue] = color(*top);
+            let [bottom_red, bottom_green, bottom_blue] = color(*bottom);
+            // Writing to a String cannot fail.
+            let _ = write!(
+                output,
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                top_red, top_green, top_blue, bottom_red, bottom_green, bottom_blue
+            );
+        }
+        output += "\x1b[0m\n";
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color() {
+        assert_eq!(color(0x00), [0x00, 0x00, 0x00]);
+        assert_eq!(color(0xFF), [0xFF, 0xFF, 0xFF]);
+        assert_eq!(color(0b1110_0000), [0xFF, 0x00, 0x00]);
+        assert_eq!(color(0b0001_0001), [0x00, 0x92, 0x55]);
+    }
+
+    #[test]
+    fn test_to_ppm() -> eyre::Result<()> {
+        let mut memory = Memory::new(&[])?;
+        memory.write(FRAMEBUFFER, 0b1110_0000);
+        memory.write(FRAMEBUFFER + WIDTH as u16, 0b0000_0011);
+
+        let image = to_ppm(&pixels(&memory));
+
+        let header = b"P6\n32 32\n255\n";
+        assert_eq!(&image[..header.len()], header);
+        let data = &image[header.len()..];
+        assert_eq!(data.len(), FRAMEBUFFER_SIZE * 3);
+        assert_eq!(data[..6], [0xFF, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(data[WIDTH * 3..WIDTH * 3 + 3], [0x00, 0x00, 0xFF]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_terminal() -> eyre::Result<()> {
+        let mut memory = Memory::new(&[])?;
+        memory.write(FRAMEBUFFER, 0xFF);
+
+        let output = to_terminal(&pixels(&memory));
+
+        assert_eq!(output.lines().count(), HEIGHT / 2);
+        assert!(output.starts_with("\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m\u{2580}\x1b[38;2;0;0;0m"));
+
+        Ok(())
+    }
+}
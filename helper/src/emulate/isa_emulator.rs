@@ -0,0 +1,290 @@
+use crate::constants::general_register::GeneralRegister;
+use crate::constants::machine_instruction::MachineInstruction;
+use crate::emulate::emulator::Emulator;
+use crate::emulate::machine_state::MachineState;
+use crate::emulate::snapshot::Snapshot;
+use crate::word_bytes::WordBytes;
+use eyre::bail;
+
+/// Instruction level emulator: executes the semantics documented on `MachineInstruction` directly, without going
+/// through the control words. This is the reference the microcode is supposed to implement.
+///
+/// The ALU registers hold the operands of the last ALU instruction, the flags are computed from them. PUSH, PULL, SPOF
+/// and the relative jumps that are taken go through the ALU too, and leave the flags undefined: the model does not
+/// touch the ALU registers then, it only records that the flags mean nothing until the next ALU instruction.
+pub struct IsaEmulator {
+    pub state: MachineState,
+    pub halted: bool,
+    /// Whether the flags are the ones of the last ALU instruction, or of the reset.
+    pub flags_defined: bool,
+}
+
+impl IsaEmulator {
+    pub fn new(program: &[u8]) -> eyre::Result<Self> {
+        Ok(IsaEmulator {
+            state: MachineState::new(program)?,
+            halted: false,
+            flags_defined: true,
+        })
+    }
+
+    /// Read the byte pointed by the instruction pointer, and advance it.
+    fn fetch(&mut self) -> u8 {
+        let value = self.state.memory.read(self.state.instruction_pointer);
+        self.state.instruction_pointer = self.state.instruction_pointer.wrapping_add(1);
+        value
+    }
+
+    fn alu_operation(&mut self, dst: GeneralRegister, alu1: u8, alu2: u8, operation: fn(u8, u8) -> u8) {
+        self.state.alu1 = alu1;
+        self.state.alu2 = alu2;
+        self.flags_defined = true;
+        self.state.set_register(dst, operation(alu1, alu2));
+    }
+
+    fn jump_relative(&mut self, taken: bool) {
+        if !taken {
+            self.state.instruction_pointer = self.state.instruction_pointer.wrapping_add(1);
+            return;
+        }
+
+        let offset = self.state.memory.read(self.state.instruction_pointer);
+        self.state.instruction_pointer = self.state.instruction_pointer.wrapping_add(offset as i8 as u16);
+        self.flags_defined = false;
+    }
+}
+
+impl Emulator for IsaEmulator {
+    fn state(&self) -> &MachineState {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut MachineState {
+        &mut self.state
+    }
+
+    fn halted(&self) -> bool {
+        self.halted
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::new(&self.state, self.halted)
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) -> eyre::Result<()> {
+        let (state, halted) = snapshot.state()?;
+        // A halted machine stays in the middle of HLT.
+        if !halted && snapshot.get::<u8>("step")?.unwrap_or(0) != 0 {
+            bail!("The snapshot was taken in the middle of an instruction, only the microcode emulator can resume it");
+        }
+        (self.state, self.halted) = (state, halted);
+        self.flags_defined = true;
+        Ok(())
+    }
+
+    fn step_instruction(&mut self) -> eyre::Result<()> {
+        let instruction = MachineInstruction::from(self.fetch());
+        let flags = self.state.flags();
+
+        match instruction {
+            MachineInstruction::NOP => {}
+            MachineInstruction::MV { dst, src } => {
+                let value = self.state.register(src);
+                self.state.set_register(dst, value);
+            }
+            MachineInstruction::MEMR { dst } => {
+                let value = self.state.memory.read(self.state.memory_register);
+                self.state.set_register(dst, value);
+            }
+            MachineInstruction::MEMW { src } => {
+                let value = self.state.register(src);
+                self.state.memory.write(self.state.memory_register, value);
+            }
+            MachineInstruction::MSRL { src } => {
+                let value = self.state.register(src);
+                self.state.memory_register = self.state.memory_register.with_byte(false, value);
+            }
+            MachineInstruction::MSRH { src } => {
+                let value = self.state.register(src);
+                self.state.memory_register = self.state.memory_register.with_byte(true, value);
+            }
+            MachineInstruction::LI { dst } => {
+                let value = self.fetch();
+                self.state.set_register(dst, value);
+            }
+            MachineInstruction::ZERO { dst } => self.state.set_register(dst, 0x00),
+            MachineInstruction::RTWL => {
+                let value = self.state.return_register.low();
+                self.state.memory.write(self.state.memory_register, value);
+            }
+            MachineInstruction::RTWH => {
+                let value = self.state.return_register.high();
+                self.state.memory.write(self.state.memory_register, value);
+            }
+            MachineInstruction::RTRL => {
+                let value = self.state.memory.read(self.state.memory_register);
+                self.state.return_register = self.state.return_register.with_byte(false, value);
+            }
+            MachineInstruction::RTRH => {
+                let value = self.state.memory.read(self.state.memory_register);
+                self.state.return_register = self.state.return_register.with_byte(true, value);
+            }
+            MachineInstruction::ADD { acc, val } => self.alu_operation(
+                acc,
+                self.state.register(acc),
+                self.state.register(val),
+                u8::wrapping_add,
+            ),
+            MachineInstruction::SUB { acc, val } => self.alu_operation(
+                acc,
+                self.state.register(acc),
+                self.state.register(val),
+                u8::wrapping_sub,
+            ),
+            MachineInstruction::NAND { acc, val } => {
+                self.alu_operation(acc, self.state.register(acc), self.state.register(val), |a, b| !(a & b))
+            }
+            MachineInstruction::XOR { acc, val } => {
+                self.alu_operation(acc, self.state.register(acc), self.state.register(val), |a, b| a ^ b)
+            }
+            MachineInstruction::ADDI { dst } => {
+                let value = self.fetch();
+                self.alu_operation(dst, self.state.register(dst), value, u8::wrapping_add)
+            }
+            MachineInstruction::INC { dst } => {
+                self.alu_operation(dst, self.state.register(dst), 0x01, u8::wrapping_add)
+            }
+            MachineInstruction::DEC { dst } => {
+                self.alu_operation(dst, self.state.register(dst), 0x01, u8::wrapping_sub)
+            }
+            MachineInstruction::NEG { dst } => {
+                self.alu_operation(dst, 0x00, self.state.register(dst), u8::wrapping_sub)
+            }
+            MachineInstruction::PJMP => {
+                let high = self.fetch();
+                let low = self.fetch();
+                self.state.jump_register = u16::from_be_bytes([high, low]);
+            }
+            MachineInstruction::JMP => self.state.instruction_pointer = self.state.jump_register,
+            MachineInstruction::JAL => {
+                self.state.return_register = self.state.instruction_pointer;
+                self.state.instruction_pointer = self.state.jump_register;
+            }
+            MachineInstruction::RET => self.state.instruction_pointer = self.state.return_register,
+            MachineInstruction::JCR | MachineInstruction::JZR | MachineInstruction::JNR | MachineInstruction::JLTR => {
+                self.jump_relative(instruction.condition().is_some_and(|flag| flags.has(flag)))
+            }
+            MachineInstruction::SPSL { src } => {
+                let value = self.state.register(src);
+                self.state.stack_pointer = self.state.stack_pointer.with_byte(false, value);
+            }
+            MachineInstruction::SPSH { src } => {
+                let value = self.state.register(src);
+                self.state.stack_pointer = self.state.stack_pointer.with_byte(true, value);
+            }
+            MachineInstruction::PUSH => {
+                let stack_pointer = self.state.stack_pointer;
+                self.state.memory_register = stack_pointer;
+                self.state.stack_pointer = stack_pointer.wrapping_sub(1);
+                self.flags_defined = false;
+            }
+            MachineInstruction::PULL => {
+                let stack_pointer = self.state.stack_pointer;
+                self.state.stack_pointer = stack_pointer.wrapping_add(1);
+                self.state.memory_register = self.state.stack_pointer;
+                self.flags_defined = false;
+            }
+            MachineInstruction::PEEK => self.state.memory_register = self.state.stack_pointer,
+            MachineInstruction::SPOF => {
+                let offset = self.fetch();
+                self.state.memory_register = self.state.stack_pointer.wrapping_add(offset as u16);
+                self.flags_defined = false;
+            }
+            MachineInstruction::HLT => self.halted = true,
+        }
+        if self.state.memory.host.exit.is_some() {
+            self.halted = true;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_program;
+
+    fn run(program: &'static str) -> eyre::Result<IsaEmulator> {
+        let program = assemble_program(program)?;
+        let mut emulator = IsaEmulator::new(&program.0)?;
+        emulator.run(10_000, |_| Ok(()))?;
+        Ok(emulator)
+    }
+
+    #[test]
+    fn test_fibonacci() -> eyre::Result<()> {
+        let emulator = run(include_str!("../../../examples/fib.as"))?;
+
+        assert_eq!(emulator.state.registers, [0xe9, 0x79, 0x00, 0x79]);
+        assert_eq!(emulator.state.instruction_pointer, 0x0f);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_arithmetic() -> eyre::Result<()> {
+        let emulator = run("\
+            LI A, 0x05
+            NEG A
+            LI B, 0xF0
+            LI C, 0x0F
+            NAND B, C
+            ADDI C, 0x10
+            LI D, 0x00
+            DEC D
+            HLT")?;
+
+        assert_eq!(emulator.state.registers, [0xfb, 0xff, 0x1f, 0xff]);
+        assert_eq!((emulator.state.alu1, emulator.state.alu2), (0x00, 0x01));
+        assert!(emulator.flags_defined);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_call_and_return() -> eyre::Result<()> {
+        let emulator = run("\
+            PJMP :function
+            JAL
+            HLT
+            :function
+            LI A, 0x2a
+            RET")?;
+
+        assert_eq!(emulator.state.registers[0], 0x2a);
+        assert_eq!(emulator.state.return_register, 0x0004);
+        assert_eq!(emulator.state.instruction_pointer, 0x0005);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stack() -> eyre::Result<()> {
+        let emulator = run("\
+            ZERO A
+            LI B, 0x80
+            SPSL A
+            SPSH B
+            PUSH
+            PUSH
+            SPOF 3
+            HLT")?;
+
+        assert_eq!(emulator.state.stack_pointer, 0x7ffe);
+        assert_eq!(emulator.state.memory_register, 0x8001);
+        assert!(!emulator.flags_defined);
+
+        Ok(())
+    }
+}
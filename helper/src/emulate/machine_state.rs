@@ -0,0 +1,96 @@
+use crate::constants::flag::{Flag, Flags};
+use crate::constants::general_register::GeneralRegister;
+use crate::emulate::alu::{alu, AluOperation};
+use crate::emulate::memory::Memory;
+
+/// The architectural state of the machine: everything that survives from one instruction to the next.
+pub struct MachineState {
+    pub registers: [u8; 4],
+    pub alu1: u8,
+    pub alu2: u8,
+    pub instruction_pointer: u16,
+    pub stack_pointer: u16,
+    pub jump_register: u16,
+    pub return_register: u16,
+    pub memory_register: u16,
+    pub memory: Memory,
+}
+
+impl MachineState {
+    pub fn new(program: &[u8]) -> eyre::Result<Self> {
+        Ok(MachineState {
+            registers: [0; 4],
+            alu1: 0,
+            alu2: 0,
+            instruction_pointer: 0,
+            stack_pointer: 0,
+            jump_register: 0,
+            return_register: 0,
+            memory_register: 0,
+            memory: Memory::new(program)?,
+        })
+    }
+
+    /// Same as the reset line: clear every register, but keep the content of the RAM.
+    pub fn reset(&mut self) {
+        self.registers = [0; 4];
+        self.alu1 = 0;
+        self.alu2 = 0;
+        self.instruction_pointer = 0;
+        self.stack_pointer = 0;
+        self.jump_register = 0;
+        self.return_register = 0;
+        self.memory_register = 0;
+    }
+
+    pub fn register(&self, register: GeneralRegister) -> u8 {
+        self.registers[register as usize]
+    }
+
+    pub fn set_register(&mut self, register: GeneralRegister, value: u8) {
+        self.registers[register as usize] = value;
+    }
+
+    /// The flags as seen by the control ROM while the ALU is idle (i.e. adding without carry).
+    pub fn flags(&self) -> Flags {
+        alu(self.alu1, self.alu2, AluOperation::Add, false).flags
+    }
+}
+
+impl core::fmt::Display for MachineState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let flags = self.flags();
+        let flag_names: Vec<&str> = [
+            (Flag::FZ, "FZ"),
+            (Flag::CO, "CO"),
+            (Flag::A2G1, "A2G1"),
+            (Flag::NEG, "NEG"),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| flags.has(*flag))
+        .map(|(_, name)| name)
+        .collect();
+
+        writeln!(
+            f,
+            "A: {:0>2x}  B: {:0>2x}  C: {:0>2x}  D: {:0>2x}",
+            self.registers[0], self.registers[1], self.registers[2], self.registers[3]
+        )?;
+        writeln!(
+            f,
+            "A1: {:0>2x}  A2: {:0>2x}  flags: [{}]",
+            self.alu1,
+            self.alu2,
+            flag_names.join(" ")
+        )?;
+        writeln!(
+            f,
+            "IP: {:0>4x}  SP: {:0>4x}  JMP: {:0>4x}  RET: {:0>4x}  MEM: {:0>4x}",
+            self.instruction_pointer,
+            self.stack_pointer,
+            self.jump_register,
+            self.return_register,
+            self.memory_register
+        )
+    }
+}
@@ -0,0 +1,202 @@
+use crate::emulate::devices::{
+    Console, Host, Keyboard, ASSERTION_FAILED, CONSOLE_OUTPUT, HOST_ADDRESS_HIGH, HOST_ADDRESS_LOW, HOST_ASSERT,
+    HOST_DUMP, HOST_EXIT, HOST_EXPECTED, HOST_PRINT, KEYBOARD_DATA, KEYBOARD_STATUS,
+};
+use crate::word_bytes::WordBytes;
+use eyre::bail;
+
+pub const MEMORY_SIZE: usize = 0x1_00_00;
+
+#[derive(PartialEq, Eq)]
+pub struct Memory {
+    ram: Vec<u8>,
+    pub console: Console,
+    pub keyboard: Keyboard,
+    pub host: Host,
+    /// When present, records the side effects of the machine so that they can be undone.
+    pub journal: Option<Journal>,
+}
+
+/// The RAM writes (address, old value, new value) and the keyboard input consumed, in order.
+#[derive(Default, PartialEq, Eq)]
+pub struct Journal {
+    pub writes: Vec<(u16, u8, u8)>,
+    pub keyboard: Vec<u8>,
+}
+
+impl Memory {
+    pub fn new(program: &[u8]) -> eyre::Result<Self> {
+        if program.len() > MEMORY_SIZE {
+            bail!("the program does not fit in memory: {} bytes", program.len());
+        }
+        let mut ram = vec![0; MEMORY_SIZE];
+        ram[..program.len()].copy_from_slice(program);
+        Ok(Memory {
+            ram,
+            console: Console::default(),
+            keyboard: Keyboard::default(),
+            host: Host::default(),
+            journal: None,
+        })
+    }
+
+    /// Read like the machine does: reading a device can consume its data.
+    pub fn read(&mut self, address: u16) -> u8 {
+        match address {
+            KEYBOARD_DATA => {
+                let value = self.keyboard.read_data();
+                if let (Some(journal), Some(value)) = (&mut self.journal, value) {
+                    journal.keyboard.push(value);
+                }
+                value.unwrap_or(0x00)
+            }
+            KEYBOARD_STATUS => self.keyboard.read_status(),
+            _ => self.peek(address),
+        }
+    }
+
+    /// Read without side effects, for the tools looking at the machine from the outside.
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            CONSOLE_OUTPUT | HOST_EXIT..=HOST_ASSERT => 0x00,
+            KEYBOARD_DATA => self.keyboard.peek_data(),
+            KEYBOARD_STATUS => self.keyboard.status(),
+            _ => self.ram[address as usize],
+        }
+    }
+
+    /// The whole RAM, including what is hidden behind the devices.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            CONSOLE_OUTPUT => self.console.write(value),
+            // The keyboard is read only.
+            KEYBOARD_DATA | KEYBOARD_STATUS => {}
+            HOST_EXIT => self.host.exit = Some(value),
+            HOST_PRINT => self.console.write_str(&format!("{:0>2x}\n", value)),
+            HOST_ADDRESS_LOW => self.host.address = self.host.address.with_byte(false, value),
+            HOST_ADDRESS_HIGH => self.host.address = self.host.address.with_byte(true, value),
+            HOST_DUMP => self.dump(value),
+            HOST_EXPECTED => self.host.expected = value,
+            HOST_ASSERT => {
+                if value != self.host.expected {
+                    let expected = self.host.expected;
+                    let message = format!("assertion failed: expected {:0>2x}, got {:0>2x}\n", expected, value);
+                    self.console.write_str(&message);
+                    self.host.exit = Some(ASSERTION_FAILED);
+                }
+            }
+            _ => {
+                if let Some(journal) = &mut self.journal {
+                    journal.writes.push((address, self.ram[address as usize], value));
+                }
+                self.ram[address as usize] = value;
+            }
+        }
+    }
+}
+
+impl Memory {
+    /// Print `length` bytes from the address of the host, 16 per line like the debugger does.
+    fn dump(&mut self, length: u8) {
+        let start = self.host.address;
+        let mut text = String::new();
+        for offset in (0..length as u16).step_by(0x10) {
+            let address = start.wrapping_add(offset);
+            let bytes: Vec<String> = (offset..(offset + 0x10).min(length as u16))
+                .map(|index| format!("{:0>2x}", self.peek(start.wrapping_add(index))))
+                .collect();
+            text += &format!("{:0>4x}: {}\n", address, bytes.join(" "));
+        }
+        self.console.write_str(&text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulate::devices::{KEYBOARD_AVAILABLE, KEYBOARD_CLOSED};
+
+    #[test]
+    fn test_program_too_large() {
+        assert!(Memory::new(&vec![0x00; MEMORY_SIZE]).is_ok());
+        assert!(Memory::new(&vec![0x00; MEMORY_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_console() -> eyre::Result<()> {
+        let mut memory = Memory::new(&[])?;
+
+        memory.write(CONSOLE_OUTPUT, b'h');
+        memory.write(CONSOLE_OUTPUT, b'i');
+        memory.write(CONSOLE_OUTPUT - 1, 0x42);
+
+        assert_eq!(memory.console.take(), b"hi");
+        assert!(memory.console.take().is_empty());
+        assert_eq!(memory.read(CONSOLE_OUTPUT), 0x00);
+        assert_eq!(memory.read(CONSOLE_OUTPUT - 1), 0x42);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyboard() -> eyre::Result<()> {
+        let mut memory = Memory::new(&[])?;
+        memory.keyboard = Keyboard::scripted(b"ok");
+
+        assert_eq!(memory.read(KEYBOARD_STATUS), KEYBOARD_AVAILABLE);
+        assert_eq!(memory.peek(KEYBOARD_DATA), b'o');
+        assert_eq!(memory.read(KEYBOARD_DATA), b'o');
+        assert_eq!(memory.read(KEYBOARD_DATA), b'k');
+        assert_eq!(memory.read(KEYBOARD_STATUS), KEYBOARD_CLOSED);
+        assert_eq!(memory.read(KEYBOARD_DATA), 0x00);
+
+        memory.write(KEYBOARD_DATA, 0x42);
+        assert_eq!(memory.read(KEYBOARD_DATA), 0x00);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_host() -> eyre::Result<()> {
+        let mut memory = Memory::new(&[0x01, 0x02, 0x03])?;
+
+        memory.write(HOST_PRINT, 0x2a);
+        memory.write(HOST_ADDRESS_LOW, 0x01);
+        memory.write(HOST_DUMP, 0x02);
+        memory.write(HOST_EXPECTED, 0x42);
+        memory.write(HOST_ASSERT, 0x42);
+        assert_eq!(memory.console.take(), b"2a\n0001: 02 03\n");
+        assert_eq!(memory.host.exit, None);
+
+        memory.write(HOST_ASSERT, 0x43);
+        assert_eq!(memory.console.take(), b"assertion failed: expected 42, got 43\n");
+        assert_eq!(memory.host.exit, Some(ASSERTION_FAILED));
+        memory.write(HOST_EXIT, 0x00);
+        assert_eq!(memory.host.exit, Some(0x00));
+        assert_eq!(memory.read(HOST_EXIT), 0x00);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal() -> eyre::Result<()> {
+        let mut memory = Memory::new(&[0x11])?;
+        memory.keyboard = Keyboard::scripted(b"k");
+        memory.journal = Some(Journal::default());
+
+        memory.write(0x0000, 0x22);
+        memory.write(CONSOLE_OUTPUT, b'!');
+        memory.read(KEYBOARD_DATA);
+        memory.read(KEYBOARD_DATA);
+
+        let journal = memory.journal.unwrap();
+        assert_eq!(journal.writes, vec![(0x0000, 0x11, 0x22)]);
+        assert_eq!(journal.keyboard, b"k");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use crate::constants::control_word::ControlWord;
+use crate::constants::flag::Flags;
+use crate::constants::machine_instruction::{steps, MachineInstruction};
+use eyre::{bail, WrapErr};
+
+pub const MICROCODE_SIZE: usize = 0x1_00_00;
+/// The control word has 38 bits: the high ROM only uses the low 6 bits of its words.
+const HIGH_ROM_MASK: u32 = 0b11_1111;
+
+/// The content of the two control ROMs, addressed exactly like the hardware does: `flags << 12 | step << 8 | instruction`.
+#[derive(PartialEq, Eq)]
+pub struct Microcode(Vec<ControlWord>);
+
+impl Microcode {
+    pub fn from_steps() -> Self {
+        let mut words = vec![ControlWord::from_lines(&[]); MICROCODE_SIZE];
+
+        for flags in 0..=0b11_11u8 {
+            for instruction_value in 0..=0xFFu8 {
+                let instruction = MachineInstruction::from(instruction_value);
+                for (step, control_word) in steps(instruction, Flags::from(flags)).into_iter().enumerate() {
+                    words[Microcode::address(instruction_value, step as u8, flags)] = control_word;
+                }
+            }
+        }
+
+        Microcode(words)
+    }
+
+    /// Load the images written by `burn`, the ones that go into the hardware: `rom01.img` holds the low 32 bits of
+    /// every control word, `rom02.img` the high ones.
+    pub fn from_images(rom1: &str, rom2: &str) -> eyre::Result<Self> {
+        let low = parse_image(rom1).wrap_err("rom01.img")?;
+        let high = parse_image(rom2).wrap_err("rom02.img")?;
+
+        let mut words = vec![ControlWord::from_lines(&[]); MICROCODE_SIZE];
+        for (address, word) in words.iter_mut().enumerate() {
+            // Like Logisim, the words missing at the end of an image are zeros.
+            let low = low.get(address).copied().unwrap_or(0);
+            let high = high.get(address).copied().unwrap_or(0);
+            if high & !HIGH_ROM_MASK != 0 {
+                bail!(
+                    "rom02.img: {:0>8x} at {:0>4x} does not fit the 38 bits of the control word",
+                    high,
+                    address
+                );
+            }
+            *word = ControlWord::from_value(((high as u64) << 32) | low as u64);
+        }
+
+        Ok(Microcode(words))
+    }
+
+    pub fn address(instruction: u8, step: u8, flags: u8) -> usize {
+        ((flags as usize) << 12) | ((step as usize) << 8) | (instruction as usize)
+    }
+
+    pub fn control_word(&self, instruction: u8, step: u8, flags: Flags) -> ControlWord {
+        self.0[Microcode::address(instruction, step, flags.value())]
+    }
+
+    /// The words of the two control ROMs, by ROM address: the low 32 bits of every control word, and the high ones.
+    pub fn rom_words(&self) -> (Vec<u64>, Vec<u64>) {
+        let low = self.0.iter().map(|word| word.value() & 0xFF_FF_FF_FF).collect();
+        let high = self.0.iter().map(|word| word.value() >> 32).collect();
+        (low, high)
+    }
+}
+
+/// The words of a Logisim image: the `v3.0 hex` (or `v2.0 raw`) header, then hexadecimal words separated by whitespace,
+/// where `4*0` stands for 4 times the word 0, and `#` starts a comment. The `contents` of the memories saved in a
+/// `.circ` file are the same, with an `addr/data:` header.
+pub fn parse_image(image: &str) -> eyre::Result<Vec<u32>> {
+    let mut lines = image.lines();
+    match lines.next() {
+        Some(header)
+            if header.starts_with("v3.0 hex") || header.starts_with("v2.0 raw") || header.starts_with("addr/data:") => {}
+        header => bail!("not a Logisim image, the header is {:?}", header.unwrap_or_default()),
+    }
+
+    let mut words = Vec::new();
+    for line in lines {
+        let line = line.split('#').next().unwrap_or_default();
+        for token in line.split_whitespace() {
+            let (count, word) = match token.split_once('*') {
+                Some((count, word)) => (
+                    count.parse().wrap_err_with(|| format!("Invalid count: {}", token))?,
+                    word,
+                ),
+                None => (1, token),
+            };
+            let word = u32::from_str_radix(word, 16).wrap_err_with(|| format!("Invalid word: {}", token))?;
+            words.extend(std::iter::repeat_n(word, count));
+        }
+    }
+    if words.len() > MICROCODE_SIZE {
+        bail!("{} words, the ROM only has {}", words.len(), MICROCODE_SIZE);
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_program;
+    use crate::burn;
+    use crate::emulate::emulator::Emulator;
+    use crate::emulate::microcode_emulator::MicrocodeEmulator;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_images() -> eyre::Result<()> {
+        let (rom1, rom2) = burn::images()?;
+        let microcode = Microcode::from_images(&rom1, &rom2)?;
+        assert!(microcode == Microcode::from_steps());
+
+        let program = assemble_program(include_str!("../../../examples/fib.as"))?;
+        let mut emulator = MicrocodeEmulator::new(Arc::new(microcode), &program.0)?;
+        emulator.run(10_000, |_| Ok(()))?;
+        assert_eq!(emulator.state.registers, [0xe9, 0x79, 0x00, 0x79]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_image() -> eyre::Result<()> {
+        assert_eq!(
+            parse_image("v3.0 hex words plain\n0000001f 3*0 # comment\nff\n")?,
+            vec![0x1f, 0, 0, 0, 0xff]
+        );
+        assert_eq!(parse_image("addr/data: 16 32\n2*c000 1\n")?, vec![0xc000, 0xc000, 1]);
+        assert!(parse_image("0000001f\n").is_err());
+        assert!(parse_image("v3.0 hex words plain\nxyz\n").is_err());
+        assert!(parse_image(&format!("v3.0 hex words plain\n{}*0\n", MICROCODE_SIZE + 1)).is_err());
+
+        let (rom1, _) = burn::images()?;
+        assert!(Microcode::from_images(&rom1, "v3.0 hex words plain\n40\n").is_err());
+
+        Ok(())
+    }
+}
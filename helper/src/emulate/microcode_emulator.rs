@@ -0,0 +1,426 @@
+use crate::constants::control_line::ControlLine;
+use crate::constants::control_word::ControlWord;
+use crate::constants::flag::Flags;
+use crate::emulate::alu::{alu, AluOperation, AluOutput};
+use crate::emulate::emulator::Emulator;
+use crate::emulate::machine_state::MachineState;
+use crate::emulate::microcode::Microcode;
+use crate::emulate::snapshot::Snapshot;
+use crate::emulate::trace::{Clock, Values};
+use crate::emulate::watchpoint::Access;
+use crate::word_bytes::WordBytes;
+use eyre::bail;
+use std::sync::Arc;
+
+/// The flags feed the control ROM address, and the control word selects the ALU operation that produces the flags:
+/// give the loop a few rounds to settle before declaring it unstable.
+const MAX_SETTLE_ROUNDS: usize = 4;
+
+/// Cycle accurate emulator: every clock looks up the control word in the microcode, drives the bus and latches the
+/// registers, exactly like the control ROMs do in the circuit.
+pub struct MicrocodeEmulator {
+    pub state: MachineState,
+    pub instruction_register: u8,
+    pub step: u8,
+    pub halted: bool,
+    pub cycles: u64,
+    /// When present, records the data accesses of the machine, for the watchpoints.
+    pub accesses: Option<Vec<Access>>,
+    /// When present, records the clock cycles of the instruction being executed, for the trace.
+    pub clocks: Option<Vec<Clock>>,
+    microcode: Arc<Microcode>,
+}
+
+impl MicrocodeEmulator {
+    pub fn new(microcode: Arc<Microcode>, program: &[u8]) -> eyre::Result<Self> {
+        Ok(MicrocodeEmulator {
+            state: MachineState::new(program)?,
+            instruction_register: 0,
+            step: 0,
+            halted: false,
+            cycles: 0,
+            accesses: None,
+            clocks: None,
+            microcode,
+        })
+    }
+
+    /// Execute a single clock cycle.
+    pub fn clock(&mut self) -> eyre::Result<()> {
+        if self.halted {
+            bail!("the machine is halted");
+        }
+
+        let control_word = self.control_word()?;
+        let alu_output = self.alu(&control_word);
+        let bus = self.bus(&control_word, &alu_output)?;
+        let step = self.step;
+
+        self.cycles += 1;
+
+        if control_word.has(ControlLine::HLT) {
+            // The halt line gates the clock: nothing else in this word gets latched.
+            self.halted = true;
+            self.record_clock(step, control_word, bus, alu_output.flags);
+            return Ok(());
+        }
+
+        self.latch(&control_word, bus);
+        if self.state.memory.host.exit.is_some() {
+            // The host stops the clock at once, like HLT.
+            self.halted = true;
+        }
+        self.record_clock(step, control_word, bus, alu_output.flags);
+
+        Ok(())
+    }
+
+    fn record_clock(&mut self, step: u8, control_word: ControlWord, bus: u8, flags: Flags) {
+        if let Some(clocks) = &mut self.clocks {
+            clocks.push(Clock {
+                step,
+                control_word,
+                bus,
+                flags,
+                after: Values::capture(&self.state),
+            });
+        }
+    }
+
+    /// The control word the next clock executes, once the flags have settled.
+    pub fn control_word(&self) -> eyre::Result<ControlWord> {
+        let mut flags = self.state.flags();
+        for _ in 0..MAX_SETTLE_ROUNDS {
+            let control_word = self.microcode.control_word(self.instruction_register, self.step, flags);
+            let settled_flags = self.alu(&control_word).flags;
+            if settled_flags == flags {
+                return Ok(control_word);
+            }
+            flags = settled_flags;
+        }
+
+        bail!(
+            "the flags never settle for instruction {:0>2x} at step {}",
+            self.instruction_register,
+            self.step
+        )
+    }
+
+    fn alu(&self, control_word: &ControlWord) -> AluOutput {
+        alu(
+            self.state.alu1,
+            self.state.alu2,
+            AluOperation::from(control_word),
+            control_word.has(ControlLine::CI),
+        )
+    }
+
+    fn memory_address(&self, control_word: &ControlWord) -> u16 {
+        if control_word.has(ControlLine::MIS) {
+            self.state.memory_register
+        } else {
+            self.state.instruction_pointer
+        }
+    }
+
+    /// Only the accesses through the memory register are data, the others fetch the program.
+    fn record_access(&mut self, control_word: &ControlWord, address: u16, value: u8, write: bool) {
+        if let Some(accesses) = &mut self.accesses {
+            if control_word.has(ControlLine::MIS) {
+                accesses.push(Access { address, value, write });
+            }
+        }
+    }
+
+    fn bus(&mut self, control_word: &ControlWord, alu_output: &AluOutput) -> eyre::Result<u8> {
+        let mut drivers: Vec<(&str, u8)> = Vec::new();
+
+        if control_word.has(ControlLine::ROE) {
+            let register = register_index(control_word.has(ControlLine::ROH), control_word.has(ControlLine::ROL));
+            drivers.push(("ROE", self.state.registers[register]));
+        }
+        if control_word.has(ControlLine::MO) {
+            let address = self.memory_address(control_word);
+            let value = self.state.memory.read(address);
+            self.record_access(control_word, address, value, false);
+            drivers.push(("MO", value));
+        }
+        if control_word.has(ControlLine::AO) {
+            drivers.push(("AO", alu_output.result));
+        }
+        if control_word.has(ControlLine::ONEO) {
+            drivers.push(("ONEO", 0x01));
+        }
+        if control_word.has(ControlLine::FFO) {
+            drivers.push(("FFO", 0xFF));
+        }
+        if control_word.has(ControlLine::IPE) && control_word.has(ControlLine::IPO) {
+            let high = control_word.has(ControlLine::IPS);
+            drivers.push(("IPO", self.state.instruction_pointer.byte(high)));
+        }
+        if control_word.has(ControlLine::SPE) && !control_word.has(ControlLine::SPI) {
+            let high = control_word.has(ControlLine::SPS);
+            drivers.push(("SPE", self.state.stack_pointer.byte(high)));
+        }
+        if control_word.has(ControlLine::JMPE) && !control_word.has(ControlLine::JMPI) {
+            let high = control_word.has(ControlLine::JMPS);
+            drivers.push(("JMPE", self.state.jump_register.byte(high)));
+        }
+        if control_word.has(ControlLine::RETE) && !control_word.has(ControlLine::RETI) {
+            let high = control_word.has(ControlLine::RETS);
+            drivers.push(("RETE", self.state.return_register.byte(high)));
+        }
+
+        match drivers.as_slice() {
+            // Nobody drives the bus, the pull resistor keeps it at 0x00.
+            [] => Ok(0x00),
+            [(_, value), rest @ ..] if rest.iter().all(|(_, other)| other == value) => Ok(*value),
+            _ => bail!(
+                "bus conflict for instruction {:0>2x} at step {}: {:?}",
+                self.instruction_register,
+                self.step,
+                drivers
+            ),
+        }
+    }
+
+    fn latch(&mut self, control_word: &ControlWord, bus: u8) {
+        if control_word.has(ControlLine::RST) {
+            self.state.reset();
+            self.instruction_register = 0;
+            self.step = 0;
+            return;
+        }
+
+        if control_word.has(ControlLine::RIE) {
+            let register = register_index(control_word.has(ControlLine::RIH), control_word.has(ControlLine::RIL));
+            self.state.registers[register] = bus;
+        }
+        if control_word.has(ControlLine::MI) {
+            let address = self.memory_address(control_word);
+            self.state.memory.write(address, bus);
+            self.record_access(control_word, address, bus, true);
+        }
+        if control_word.has(ControlLine::WME) {
+            let high = control_word.has(ControlLine::WMS);
+            self.state.memory_register = self.state.memory_register.with_byte(high, bus);
+        }
+        if control_word.has(ControlLine::IRE) {
+            self.instruction_register = bus;
+        }
+        if control_word.has(ControlLine::A1I) {
+            self.state.alu1 = bus;
+        }
+        if control_word.has(ControlLine::A2I) {
+            self.state.alu2 = bus;
+        }
+        if control_word.has(ControlLine::IPE) && !control_word.has(ControlLine::IPO) {
+            let high = control_word.has(ControlLine::IPS);
+            self.state.instruction_pointer = self.state.instruction_pointer.with_byte(high, bus);
+        } else if control_word.has(ControlLine::IPA) {
+            self.state.instruction_pointer = self.state.instruction_pointer.wrapping_add(1);
+        }
+        if control_word.has(ControlLine::SPE) && control_word.has(ControlLine::SPI) {
+            let high = control_word.has(ControlLine::SPS);
+            self.state.stack_pointer = self.state.stack_pointer.with_byte(high, bus);
+        }
+        if control_word.has(ControlLine::JMPE) && control_word.has(ControlLine::JMPI) {
+            let high = control_word.has(ControlLine::JMPS);
+            self.state.jump_register = self.state.jump_register.with_byte(high, bus);
+        }
+        if control_word.has(ControlLine::RETE) && control_word.has(ControlLine::RETI) {
+            let high = control_word.has(ControlLine::RETS);
+            self.state.return_register = self.state.return_register.with_byte(high, bus);
+        }
+
+        self.step = if control_word.has(ControlLine::MRST) {
+            0
+        } else {
+            (self.step + 1) & 0b11_11
+        };
+    }
+}
+
+impl Emulator for MicrocodeEmulator {
+    fn state(&self) -> &MachineState {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut MachineState {
+        &mut self.state
+    }
+
+    fn halted(&self) -> bool {
+        self.halted
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot::new(&self.state, self.halted);
+        snapshot.set("IR", format!("{:0>2x}", self.instruction_register));
+        snapshot.set("step", self.step);
+        snapshot.set("cycles", self.cycles);
+        snapshot
+    }
+
+    /// Snapshots of the instruction level emulator are taken between instructions: they start from a fetch.
+    fn restore(&mut self, snapshot: &Snapshot) -> eyre::Result<()> {
+        (self.state, self.halted) = snapshot.state()?;
+        self.instruction_register = match snapshot.get::<String>("IR")? {
+            Some(_) => snapshot.hex("IR")? as u8,
+            None => 0,
+        };
+        self.step = snapshot.get("step")?.unwrap_or(0);
+        self.cycles = snapshot.get("cycles")?.unwrap_or(0);
+        Ok(())
+    }
+
+    fn cycles(&self) -> Option<u64> {
+        Some(self.cycles)
+    }
+
+    fn clocks(&self) -> &[Clock] {
+        self.clocks.as_deref().unwrap_or_default()
+    }
+
+    /// Execute clock cycles until the step counter is reset.
+    fn step_instruction(&mut self) -> eyre::Result<()> {
+        if let Some(clocks) = &mut self.clocks {
+            clocks.clear();
+        }
+        loop {
+            self.clock()?;
+
+            if self.halted || self.step == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn register_index(high: bool, low: bool) -> usize {
+    ((high as usize) << 1) | (low as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_program;
+    use crate::emulate::devices::Keyboard;
+
+    fn emulator(program: &'static str) -> eyre::Result<MicrocodeEmulator> {
+        let program = assemble_program(program)?;
+        MicrocodeEmulator::new(Arc::new(Microcode::from_steps()), &program.0)
+    }
+
+    #[test]
+    fn test_fibonacci() -> eyre::Result<()> {
+        let input = include_str!("../../../examples/fib.as");
+        let mut emulator = emulator(input)?;
+
+        emulator.run(10_000, |_| Ok(()))?;
+
+        assert_eq!(emulator.state.registers, [0xe9, 0x79, 0x00, 0x79]);
+        assert_eq!(emulator.state.instruction_pointer, 0x0f);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_instruction_cycles() -> eyre::Result<()> {
+        let mut emulator = emulator("NOP\nLI A, 0x12\nMV B, A\nHLT")?;
+
+        let mut cycles = Vec::new();
+        while !emulator.halted {
+            let start = emulator.cycles;
+            emulator.step_instruction()?;
+            cycles.push(emulator.cycles - start);
+        }
+
+        assert_eq!(cycles, vec![3, 5, 4, 3]);
+        assert_eq!(emulator.state.registers, [0x12, 0x12, 0x00, 0x00]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_and_stack() -> eyre::Result<()> {
+        let mut emulator = emulator(
+            "\
+            LI A, 0x00
+            LI B, 0x80
+            SPSL A
+            SPSH B
+            LI C, 0x42
+            PUSH
+            MEMW C
+            PUSH
+            PULL
+            PULL
+            MEMR D
+            HLT",
+        )?;
+
+        emulator.run(1_000, |_| Ok(()))?;
+
+        assert_eq!(emulator.state.stack_pointer, 0x8000);
+        assert_eq!(emulator.state.memory_register, 0x8000);
+        assert_eq!(emulator.state.memory.read(0x8000), 0x42);
+        assert_eq!(emulator.state.registers[3], 0x42);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_accesses() -> eyre::Result<()> {
+        let mut emulator = emulator("LI A, 0x00\nLI B, 0x80\nMSRL A\nMSRH B\nLI C, 0x42\nMEMW C\nMEMR D\nHLT")?;
+        emulator.accesses = Some(Vec::new());
+
+        emulator.run(1_000, |_| Ok(()))?;
+
+        // The instruction fetches are not data accesses.
+        let write = Access {
+            address: 0x8000,
+            value: 0x42,
+            write: true,
+        };
+        assert_eq!(emulator.accesses, Some(vec![write, Access { write: false, ..write }]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_console() -> eyre::Result<()> {
+        let mut emulator = emulator(include_str!("../../../examples/digits.as"))?;
+
+        emulator.run(1_000, |_| Ok(()))?;
+
+        assert_eq!(emulator.state.memory.console.output, b"0123456789\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_host() -> eyre::Result<()> {
+        let mut emulator = emulator(include_str!("../../../examples/host.as"))?;
+
+        emulator.run(1_000, |_| Ok(()))?;
+
+        assert_eq!(emulator.state.memory.console.output, b"0c\n0000: 21 ff 1d 22\n");
+        assert_eq!(emulator.state.memory.host.exit, Some(0x00));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyboard() -> eyre::Result<()> {
+        let mut emulator = emulator(include_str!("../../../examples/echo.as"))?;
+        emulator.state.memory.keyboard = Keyboard::scripted(b"echo\n");
+
+        emulator.run(1_000, |_| Ok(()))?;
+
+        assert_eq!(emulator.state.memory.console.output, b"echo\n");
+        assert!(emulator.state.memory.keyboard.input.is_empty());
+
+        Ok(())
+    }
+}
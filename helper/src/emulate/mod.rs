@@ -0,0 +1,24 @@
+pub mod alu;
+#[cfg(test)]
+mod differential;
+#[cfg(test)]
+mod equivalence;
+pub mod coverage;
+pub mod devices;
+#[allow(clippy::module_inception)]
+pub mod emulate;
+pub mod emulator;
+pub mod framebuffer;
+pub mod isa_emulator;
+pub mod machine_state;
+pub mod memory;
+pub mod microcode;
+pub mod microcode_emulator;
+pub mod profile;
+pub mod program_test;
+pub mod snapshot;
+pub mod stack;
+pub mod strict;
+pub mod trace;
+pub mod vcd;
+pub mod watchpoint;
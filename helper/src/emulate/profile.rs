@@ -0,0 +1,143 @@
+use crate::assemble::symbols::Symbols;
+use crate::constants::machine_instruction::MachineInstruction;
+use crate::emulate::memory::Memory;
+use crate::emulate::microcode_emulator::MicrocodeEmulator;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// How many instructions the report lists, starting from the most expensive.
+const HOTTEST_INSTRUCTIONS: usize = 10;
+
+/// Where the clock cycles of a run went, as counted by the microcode emulator: fetch included, until `MRST`.
+pub struct Profile {
+    /// Cycles and executions of the instruction at each address.
+    pub instructions: BTreeMap<u16, (u64, u64)>,
+    address: u16,
+    cycles: u64,
+}
+
+impl Profile {
+    /// Start profiling from the current state of the emulator.
+    pub fn new(emulator: &MicrocodeEmulator) -> Self {
+        Profile {
+            instructions: BTreeMap::new(),
+            address: emulator.state.instruction_pointer,
+            cycles: emulator.cycles,
+        }
+    }
+
+    /// Account for the instruction the emulator just executed.
+    pub fn after_instruction(&mut self, emulator: &MicrocodeEmulator) {
+        let (cycles, executions) = self.instructions.entry(self.address).or_default();
+        *cycles += emulator.cycles - self.cycles;
+        *executions += 1;
+
+        self.address = emulator.state.instruction_pointer;
+        self.cycles = emulator.cycles;
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.instructions.values().map(|(cycles, _)| cycles).sum()
+    }
+
+    /// Cycles and executed instructions of each part of the program, keyed by the address of its label. What comes
+    /// before the first label is keyed by `None`.
+    pub fn per_label(&self, symbols: &Symbols) -> BTreeMap<Option<u16>, (u64, u64)> {
+        let mut labels: BTreeMap<Option<u16>, (u64, u64)> = BTreeMap::new();
+        for (address, (cycles, executions)) in &self.instructions {
+            let (label_cycles, label_executions) = labels.entry(symbols.enclosing_label(*address)).or_default();
+            *label_cycles += cycles;
+            *label_executions += executions;
+        }
+        labels
+    }
+
+    pub fn report(&self, symbols: &Symbols, memory: &Memory) -> String {
+        let total = self.total_cycles();
+        let percentage = |cycles: u64| 100.0 * cycles as f64 / total.max(1) as f64;
+        let mut report = String::new();
+
+        let mut labels: Vec<_> = self.per_label(symbols).into_iter().collect();
+        labels.sort_by_key(|(label, (cycles, _))| (std::cmp::Reverse(*cycles), *label));
+        report += "Cycles per label:\n";
+        report += "    cycles       %  instructions  label\n";
+        for (label, (cycles, executions)) in labels {
+            let name = match label {
+                Some(address) => format!("{:0>4x} {}", address, symbols.labels_at(address).join(" ")),
+                None => "(before the first label)".to_string(),
+            };
+            // Writing to a String cannot fail.
+            let _ = writeln!(
+                report,
+                "{:>10} {:>6.1}% {:>13}  {}",
+                cycles,
+                percentage(cycles),
+                executions,
+                name
+            );
+        }
+
+        let mut instructions: Vec<_> = self.instructions.iter().collect();
+        instructions.sort_by_key(|(address, (cycles, _))| (std::cmp::Reverse(*cycles), **address));
+        report += "\nHottest instructions:\n";
+        report += "    cycles       %    executions  address\n";
+        for (address, (cycles, executions)) in instructions.into_iter().take(HOTTEST_INSTRUCTIONS) {
+            let instruction = MachineInstruction::from(memory.peek(*address));
+            let labels = symbols.labels_at(*address);
+            let location = if labels.is_empty() {
+                format!("{:0>4x}", address)
+            } else {
+                format!("{:0>4x} {}", address, labels.join(" "))
+            };
+            let _ = writeln!(
+                report,
+                "{:>10} {:>6.1}% {:>13}  {}  {:?}",
+                cycles,
+                percentage(*cycles),
+                executions,
+                location,
+                instruction
+            );
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_with_symbols;
+    use crate::emulate::emulator::Emulator;
+    use crate::emulate::microcode::Microcode;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_fibonacci() -> eyre::Result<()> {
+        let (program, symbols) = assemble_with_symbols(include_str!("../../../examples/fib.as"))?;
+        let mut emulator = MicrocodeEmulator::new(Arc::new(Microcode::from_steps()), &program.0)?;
+
+        let mut profile = Profile::new(&emulator);
+        emulator.run(10_000, |emulator| {
+            profile.after_instruction(emulator);
+            Ok(())
+        })?;
+
+        assert_eq!(profile.total_cycles(), emulator.cycles);
+        // LI takes 5 cycles, the first ADD runs 14 times.
+        assert_eq!(profile.instructions[&0x00], (5, 1));
+        assert_eq!(profile.instructions[&0x04].1, 14);
+
+        let per_label = profile.per_label(&symbols);
+        assert_eq!(per_label[&None], (10, 2));
+        assert_eq!(per_label[&Some(0x0e)], (3, 1));
+        assert_eq!(per_label.values().map(|(_, executions)| executions).sum::<u64>(), 99);
+
+        let report = profile.report(&symbols, &emulator.state.memory);
+        assert!(report.starts_with("Cycles per label:\n    cycles       %  instructions  label\n"));
+        assert!(report.contains("0004 :loop\n"));
+        assert!(report.contains("0004 :loop  ADD { acc: A, val: B }\n"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+use crate::assemble::assemble::assemble_program;
+use crate::emulate::devices::Keyboard;
+use crate::emulate::emulator::Emulator;
+use crate::emulate::microcode::Microcode;
+use crate::emulate::microcode_emulator::MicrocodeEmulator;
+use crate::emulate::watchpoint::Operand;
+use eyre::{bail, eyre, WrapErr};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A test program that has not halted after this many clock cycles is stuck.
+const MAX_CYCLES: u64 = 100_000_000;
+
+/// What a test program declares about itself, in comments:
+/// - `# input="..."` is typed on the keyboard;
+/// - `# expect A=0x90` checks a register or a flag at the end of the run;
+/// - `# expect mem[0x8000]=0x01` checks a byte of memory;
+/// - `# expect stdout="..."` checks everything the program printed on the console;
+/// - `# expect exit=0` checks the exit status given to the host: without it, any status but 0 is a failure.
+///
+/// Numbers are decimal, or hexadecimal with `0x`. Strings understand `\n`, `\t`, `\\`, `\"` and `\xNN`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TestProgram {
+    pub input: Vec<u8>,
+    pub expectations: Vec<Expectation>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Expectation {
+    Register(Operand, u16),
+    Memory(u16, u8),
+    Stdout(Vec<u8>),
+    Exit(u8),
+}
+
+impl TestProgram {
+    pub fn parse(source: &str) -> eyre::Result<Self> {
+        let mut program = TestProgram::default();
+        for (index, line) in source.lines().enumerate() {
+            let Some(comment) = line.trim().strip_prefix('#') else {
+                continue;
+            };
+            let comment = comment.trim();
+            let result = if let Some(input) = comment.strip_prefix("input=") {
+                parse_string(input).map(|input| program.input = input)
+            } else if let Some(expectation) = comment.strip_prefix("expect ") {
+                Expectation::parse(expectation.trim()).map(|expectation| program.expectations.push(expectation))
+            } else {
+                Ok(())
+            };
+            result.wrap_err_with(|| format!("line {}: {}", index + 1, line.trim()))?;
+        }
+        Ok(program)
+    }
+
+    /// Run the program until it halts, and describe every expectation it does not meet.
+    pub fn run(&self, program: &[u8], max_cycles: u64) -> Vec<String> {
+        let mut emulator = match MicrocodeEmulator::new(Arc::new(Microcode::from_steps()), program) {
+            Ok(emulator) => emulator,
+            Err(error) => return vec![error.to_string()],
+        };
+        emulator.state.memory.keyboard = Keyboard::scripted(&self.input);
+        while !emulator.halted {
+            if emulator.cycles >= max_cycles {
+                return vec![format!("did not halt within {} cycles", max_cycles)];
+            }
+            if let Err(error) = emulator.step_instruction() {
+                return vec![format!("the emulator stopped: {}", error)];
+            }
+        }
+
+        let mut failures: Vec<String> = self
+            .expectations
+            .iter()
+            .filter_map(|expectation| expectation.check(&emulator))
+            .collect();
+        let exit_expected = self
+            .expectations
+            .iter()
+            .any(|expectation| matches!(expectation, Expectation::Exit(_)));
+        match emulator.state.memory.host.exit {
+            Some(status) if status != 0 && !exit_expected => failures.push(format!("exited with status {}", status)),
+            _ => {}
+        }
+        failures
+    }
+}
+
+impl Expectation {
+    pub fn parse(expectation: &str) -> eyre::Result<Self> {
+        let Some((name, value)) = expectation.split_once('=') else {
+            bail!("expected <name>=<value>");
+        };
+        let (name, value) = (name.trim(), value.trim());
+
+        if name == "stdout" {
+            return Ok(Expectation::Stdout(parse_string(value)?));
+        }
+        if name == "exit" {
+            return Ok(Expectation::Exit(parse_number(value)?.try_into()?));
+        }
+        if let Some(address) = name.strip_prefix("mem[").and_then(|name| name.strip_suffix(']')) {
+            return Ok(Expectation::Memory(
+                parse_number(address)?,
+                parse_number(value)?.try_into()?,
+            ));
+        }
+        Ok(Expectation::Register(Operand::parse(name)?, parse_number(value)?))
+    }
+
+    /// What is wrong, if anything.
+    fn check(&self, emulator: &MicrocodeEmulator) -> Option<String> {
+        let state = &emulator.state;
+        match self {
+            Expectation::Register(operand, expected) => {
+                let actual = operand.value(state);
+                (actual != *expected).then(|| format!("{}: expected {:#x}, got {:#x}", operand, expected, actual))
+            }
+            Expectation::Memory(address, expected) => {
+                let actual = state.memory.peek(*address);
+                (actual != *expected)
+                    .then(|| format!("mem[{:#06x}]: expected {:#04x}, got {:#04x}", address, expected, actual))
+            }
+            Expectation::Stdout(expected) => (state.memory.console.output != *expected).then(|| {
+                format!(
+                    "stdout: expected {:?}, got {:?}",
+                    String::from_utf8_lossy(expected),
+                    String::from_utf8_lossy(&state.memory.console.output)
+                )
+            }),
+            Expectation::Exit(expected) => match state.memory.host.exit {
+                Some(status) if status == *expected => None,
+                Some(status) => Some(format!("exit: expected {}, got {}", expected, status)),
+                None => Some(format!("exit: expected {}, but the program stopped with HLT", expected)),
+            },
+        }
+    }
+}
+
+fn parse_number(input: &str) -> eyre::Result<u16> {
+    let result = match input.strip_prefix("0x") {
+        Some(digits) => u16::from_str_radix(digits, 16),
+        None => input.parse(),
+    };
+    result.wrap_err_with(|| format!("Invalid number: {}", input))
+}
+
+/// A string between double quotes, with its escapes.
+fn parse_string(input: &str) -> eyre::Result<Vec<u8>> {
+    let Some(content) = input.strip_prefix('"').and_then(|input| input.strip_suffix('"')) else {
+        bail!("Strings go between double quotes: {}", input);
+    };
+
+    let mut bytes = Vec::new();
+    let mut chars = content.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(char.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('\\') => bytes.push(b'\\'),
+            Some('"') => bytes.push(b'"'),
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&digits, 16).wrap_err_with(|| format!("Invalid escape: \\x{}", digits))?);
+            }
+            other => bail!("Invalid escape: \\{}", other.map(String::from).unwrap_or_default()),
+        }
+    }
+    Ok(bytes)
+}
+
+/// The `.as` files to test: the file itself, or the ones in the directory.
+fn test_files(path: &Path) -> eyre::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let file = entry?.path();
+        if file.extension().is_some_and(|extension| extension == "as") {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Test one file: the failures, or an error if it cannot even run.
+pub fn test_file(file: &Path) -> eyre::Result<Vec<String>> {
+    let source = fs::read_to_string(file)?;
+    let test = TestProgram::parse(&source)?;
+    let program = assemble_program(source.leak())?;
+    Ok(test.run(&program.0, MAX_CYCLES))
+}
+
+/// `helper test <dir>`: returns whether every program passed.
+pub fn test(args: &[String]) -> eyre::Result<bool> {
+    let [path] = args else {
+        bail!("Usage: test <dir|file.as>");
+    };
+    let files = test_files(Path::new(path))?;
+    if files.is_empty() {
+        return Err(eyre!("No .as files in {}", path));
+    }
+
+    let mut failed = 0;
+    for file in &files {
+        let failures = match test_file(file) {
+            Ok(failures) => failures,
+            Err(error) => vec![format!("{:#}", error)],
+        };
+        if failures.is_empty() {
+            println!("test {} ... ok", file.display());
+        } else {
+            failed += 1;
+            println!("test {} ... FAILED", file.display());
+            for failure in failures {
+                println!("    {}", failure);
+            }
+        }
+    }
+
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        if failed == 0 { "ok" } else { "FAILED" },
+        files.len() - failed,
+        failed
+    );
+    Ok(failed == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> eyre::Result<()> {
+        let test = TestProgram::parse(
+            "# input=\"hi\\n\"\nLI A, 0x90\n# expect A=0x90\n#expect mem[0x8000] = 1\n# expect stdout=\"a\\\"b\\x21\"\n",
+        )?;
+        assert_eq!(test.input, b"hi\n");
+        assert_eq!(
+            test.expectations,
+            vec![
+                Expectation::Register(Operand::Register(0), 0x90),
+                Expectation::Memory(0x8000, 0x01),
+                Expectation::Stdout(b"a\"b!".to_vec()),
+            ]
+        );
+
+        assert!(TestProgram::parse("# expect A").is_err());
+        assert!(TestProgram::parse("# expect X=1").is_err());
+        assert!(TestProgram::parse("# expect mem[0x8000]=0x100").is_err());
+        assert!(TestProgram::parse("# expect stdout=hi").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failures() -> eyre::Result<()> {
+        let source = "LI A, 0x90\nHLT\n# expect A=0x91\n# expect B=0\n# expect FZ=0\n# expect stdout=\"\"";
+        let test = TestProgram::parse(source)?;
+        let program = assemble_program(source)?;
+        assert_eq!(
+            test.run(&program.0, 1_000),
+            vec!["A: expected 0x91, got 0x90", "FZ: expected 0x0, got 0x1"]
+        );
+
+        let source = "LI A, 0x10\nLI B, 0xFF\nMSRL A\nMSRH B\nMEMW B\n# expect mem[0x8000]=2";
+        let program = assemble_program(source)?;
+        assert_eq!(
+            TestProgram::parse(source)?.run(&program.0, 1_000),
+            vec!["mem[0x8000]: expected 0x02, got 0x00", "exited with status 255"]
+        );
+
+        let program = assemble_program(":loop\nPJMP :loop\nJMP")?;
+        assert_eq!(
+            TestProgram::default().run(&program.0, 1_000),
+            vec!["did not halt within 1000 cycles"]
+        );
+
+        Ok(())
+    }
+
+    /// Every example declares how it behaves.
+    #[test]
+    fn test_examples() -> eyre::Result<()> {
+        let files = test_files(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples"))?;
+        assert!(files.len() >= 5);
+        for file in files {
+            assert_eq!(test_file(&file)?, Vec::<String>::new(), "{}", file.display());
+        }
+        Ok(())
+    }
+}
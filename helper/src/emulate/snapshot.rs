@@ -0,0 +1,245 @@
+use crate::emulate::devices::Keyboard;
+use crate::emulate::machine_state::MachineState;
+use crate::emulate::memory::MEMORY_SIZE;
+use eyre::{bail, eyre, WrapErr};
+use std::str::FromStr;
+
+const HEADER: &str = "mypc snapshot v1";
+const RAM_LINE_LENGTH: usize = 0x20;
+
+/// Everything needed to resume a run, in a text format that can be read and attached to a bug report: one `key value`
+/// per line, then the RAM in lines of 32 bytes (the lines that are all zeros are left out).
+///
+/// The devices are kept too: the keyboard input that arrived but was not read yet, what the console printed, and the
+/// registers of the host calls with the exit status if the program asked to stop.
+pub struct Snapshot {
+    values: Vec<(String, String)>,
+    pub ram: Vec<u8>,
+}
+
+impl Snapshot {
+    /// The state the two emulators share.
+    pub fn new(state: &MachineState, halted: bool) -> Self {
+        let mut snapshot = Snapshot {
+            values: Vec::new(),
+            ram: state.memory.ram().to_vec(),
+        };
+        for (name, value) in ["A", "B", "C", "D"].into_iter().zip(state.registers) {
+            snapshot.set(name, format!("{:0>2x}", value));
+        }
+        snapshot.set("A1", format!("{:0>2x}", state.alu1));
+        snapshot.set("A2", format!("{:0>2x}", state.alu2));
+        snapshot.set("IP", format!("{:0>4x}", state.instruction_pointer));
+        snapshot.set("SP", format!("{:0>4x}", state.stack_pointer));
+        snapshot.set("JMP", format!("{:0>4x}", state.jump_register));
+        snapshot.set("RET", format!("{:0>4x}", state.return_register));
+        snapshot.set("MEM", format!("{:0>4x}", state.memory_register));
+        snapshot.set("halted", halted);
+        let input: Vec<String> = state
+            .memory
+            .keyboard
+            .input
+            .iter()
+            .map(|byte| format!("{:0>2x}", byte))
+            .collect();
+        snapshot.set("keyboard", input.concat());
+        let output: Vec<String> = state
+            .memory
+            .console
+            .output
+            .iter()
+            .map(|byte| format!("{:0>2x}", byte))
+            .collect();
+        snapshot.set("console", output.concat());
+        snapshot.set("host_address", format!("{:0>4x}", state.memory.host.address));
+        snapshot.set("host_expected", format!("{:0>2x}", state.memory.host.expected));
+        if let Some(exit) = state.memory.host.exit {
+            snapshot.set("exit", format!("{:0>2x}", exit));
+        }
+        snapshot
+    }
+
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        self.values.retain(|(candidate, _)| candidate != key);
+        self.values.push((key.to_string(), value.to_string()));
+    }
+
+    pub fn get<T: FromStr>(&self, key: &str) -> eyre::Result<Option<T>> {
+        let Some((_, value)) = self.values.iter().find(|(candidate, _)| candidate == key) else {
+            return Ok(None);
+        };
+        match value.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => bail!("Invalid value for {} in the snapshot: {}", key, value),
+        }
+    }
+
+    pub fn hex(&self, key: &str) -> eyre::Result<u16> {
+        let value: String = self.get(key)?.ok_or_else(|| eyre!("Missing {} in the snapshot", key))?;
+        u16::from_str_radix(&value, 16).wrap_err_with(|| format!("Invalid value for {} in the snapshot", key))
+    }
+
+    /// Rebuild the shared state, and whether the machine was halted.
+    pub fn state(&self) -> eyre::Result<(MachineState, bool)> {
+        let mut state = MachineState::new(&self.ram)?;
+        for (index, name) in ["A", "B", "C", "D"].into_iter().enumerate() {
+            state.registers[index] = self.hex(name)? as u8;
+        }
+        state.alu1 = self.hex("A1")? as u8;
+        state.alu2 = self.hex("A2")? as u8;
+        state.instruction_pointer = self.hex("IP")?;
+        state.stack_pointer = self.hex("SP")?;
+        state.jump_register = self.hex("JMP")?;
+        state.return_register = self.hex("RET")?;
+        state.memory_register = self.hex("MEM")?;
+        let input: String = self.get("keyboard")?.unwrap_or_default();
+        state.memory.keyboard = Keyboard::scripted(&parse_bytes(&input)?);
+        let output: String = self.get("console")?.unwrap_or_default();
+        state.memory.console.output = parse_bytes(&output)?;
+        state.memory.host.address = self.hex("host_address")?;
+        state.memory.host.expected = self.hex("host_expected")? as u8;
+        state.memory.host.exit = match self.get::<String>("exit")? {
+            Some(_) => Some(self.hex("exit")? as u8),
+            None => None,
+        };
+        let halted = self.get("halted")?.unwrap_or(false);
+        Ok((state, halted))
+    }
+
+    pub fn parse(input: &str) -> eyre::Result<Self> {
+        let mut lines = input.lines();
+        if lines.next() != Some(HEADER) {
+            bail!("Not a snapshot: the first line should be \"{}\"", HEADER);
+        }
+
+        let mut snapshot = Snapshot {
+            values: Vec::new(),
+            ram: vec![0; MEMORY_SIZE],
+        };
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            if key != "ram" {
+                snapshot.set(key, value);
+                continue;
+            }
+
+            let Some((address, bytes)) = value.split_once(' ') else {
+                bail!("Malformed RAM line in the snapshot: {}", line);
+            };
+            let address = usize::from_str_radix(address, 16)?;
+            let bytes = parse_bytes(bytes)?;
+            if address + bytes.len() > MEMORY_SIZE {
+                bail!("RAM line out of bounds in the snapshot: {}", line);
+            }
+            snapshot.ram[address..address + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        Ok(snapshot)
+    }
+}
+
+impl core::fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for (key, value) in &self.values {
+            writeln!(f, "{} {}", key, value)?;
+        }
+        for (index, line) in self.ram.chunks(RAM_LINE_LENGTH).enumerate() {
+            if line.iter().any(|byte| *byte != 0) {
+                let bytes: Vec<String> = line.iter().map(|byte| format!("{:0>2x}", byte)).collect();
+                writeln!(f, "ram {:0>4x} {}", index * RAM_LINE_LENGTH, bytes.concat())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_bytes(input: &str) -> eyre::Result<Vec<u8>> {
+    if !input.len().is_multiple_of(2) {
+        bail!("Odd number of hexadecimal digits: {}", input);
+    }
+    (0..input.len())
+        .step_by(2)
+        .map(|index| {
+            u8::from_str_radix(&input[index..index + 2], 16).wrap_err_with(|| format!("Invalid bytes: {}", input))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_program;
+    use crate::emulate::devices::{HOST_ADDRESS_HIGH, HOST_EXPECTED};
+    use crate::emulate::emulator::Emulator;
+    use crate::emulate::isa_emulator::IsaEmulator;
+    use crate::emulate::microcode::Microcode;
+    use crate::emulate::microcode_emulator::MicrocodeEmulator;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_round_trip() -> eyre::Result<()> {
+        let program = assemble_program(include_str!("../../../examples/echo.as"))?;
+        let microcode = Arc::new(Microcode::from_steps());
+        let mut emulator = MicrocodeEmulator::new(microcode.clone(), &program.0)?;
+        emulator.state.memory.keyboard = Keyboard::scripted(b"abc");
+        emulator.state.memory.write(0x8000, 0x42);
+        emulator.state.memory.write(HOST_ADDRESS_HIGH, 0x80);
+        emulator.state.memory.write(HOST_EXPECTED, 0x2a);
+
+        // Stop in the middle of an instruction, after the first byte has been echoed.
+        while emulator.state.memory.console.output.is_empty() || emulator.step == 0 {
+            emulator.clock()?;
+        }
+        let text = emulator.snapshot().to_string();
+        assert!(text.starts_with("mypc snapshot v1\nA 00\nB ff\nC 61\n"));
+        assert!(text.contains("\nkeyboard 6263\nconsole 61\nhost_address 8000\nhost_expected 2a\n"));
+        assert!(!text.contains("\nexit "));
+        assert!(text.contains("\nram 8000 42000000"));
+
+        let mut restored = MicrocodeEmulator::new(microcode.clone(), &[])?;
+        restored.restore(&Snapshot::parse(&text)?)?;
+        assert_eq!(restored.snapshot().to_string(), text);
+
+        // Both finish the same way: echoing the rest of the input.
+        emulator.run(1_000, |_| Ok(()))?;
+        restored.run(1_000, |_| Ok(()))?;
+        assert_eq!(restored.state.memory.console.output, b"abc");
+        assert_eq!(restored.snapshot().to_string(), emulator.snapshot().to_string());
+
+        // The instruction level emulator cannot resume from the middle of an instruction.
+        let mut isa_emulator = IsaEmulator::new(&[])?;
+        assert!(isa_emulator.restore(&Snapshot::parse(&text)?).is_err());
+        isa_emulator.restore(&emulator.snapshot())?;
+        assert!(isa_emulator.halted);
+
+        // A program stopped by a host call stays stopped, with its exit status.
+        let program = assemble_program(include_str!("../../../examples/host.as"))?;
+        let mut emulator = MicrocodeEmulator::new(microcode.clone(), &program.0)?;
+        emulator.run(1_000, |_| Ok(()))?;
+        let text = emulator.snapshot().to_string();
+        assert!(text.contains("\nexit 00\n"));
+        let mut restored = MicrocodeEmulator::new(microcode, &[])?;
+        restored.restore(&Snapshot::parse(&text)?)?;
+        assert_eq!(restored.state.memory.host.exit, Some(0x00));
+        assert_eq!(
+            restored.state.memory.console.output,
+            emulator.state.memory.console.output
+        );
+        assert_eq!(restored.snapshot().to_string(), text);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Snapshot::parse("A 00").is_err());
+        assert!(Snapshot::parse("mypc snapshot v1\nram 0000 0").is_err());
+        assert!(Snapshot::parse("mypc snapshot v1\nram fff0 00000000000000000000000000000000000000").is_err());
+
+        let Ok(snapshot) = Snapshot::parse("mypc snapshot v1\nA 0g") else {
+            panic!("the values are only parsed when read");
+        };
+        assert!(snapshot.state().is_err());
+    }
+}
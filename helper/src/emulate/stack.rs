@@ -0,0 +1,211 @@
+use crate::assemble::symbols::Symbols;
+use crate::constants::machine_instruction::MachineInstruction;
+use crate::emulate::devices::is_device;
+use crate::emulate::emulator::Emulator;
+use eyre::bail;
+use std::collections::BTreeSet;
+
+/// Catches the stack mistakes the hardware would let through, right after the instruction that makes them and before
+/// the memory access that would corrupt the RAM:
+/// - a PUSH that grows the stack into the program, into the data or onto the devices;
+/// - a PULL past the stack pointer set by the program with SPSL and SPSH;
+/// - an SPOF outside of what is on the stack.
+pub struct StackCheck {
+    /// The stack pointer set by the program, i.e. the bottom of the stack: unknown until SPSL or SPSH.
+    base: Option<u16>,
+    program_length: usize,
+    /// The addresses written through a memory register set by MSRL and MSRH.
+    data: BTreeSet<u16>,
+    /// Whether the memory register was last set by PUSH, PULL, PEEK or SPOF rather than by MSRL and MSRH.
+    stack_address: bool,
+    /// The instruction about to be executed, and the stack pointer before it.
+    next: (u16, MachineInstruction, u16),
+}
+
+impl StackCheck {
+    /// Start checking from the current state of the emulator.
+    pub fn new(emulator: &impl Emulator, program: &[u8]) -> Self {
+        StackCheck {
+            base: None,
+            program_length: program.len(),
+            data: BTreeSet::new(),
+            stack_address: false,
+            next: next_instruction(emulator),
+        }
+    }
+
+    /// Check the instruction the emulator just executed, and fail with its address if it broke the stack.
+    pub fn after_instruction(&mut self, emulator: &impl Emulator, symbols: &Symbols) -> eyre::Result<()> {
+        let (address, instruction, old_stack_pointer) = self.next;
+        self.next = next_instruction(emulator);
+        let state = emulator.state();
+        let stack_pointer = state.stack_pointer;
+        let memory_register = state.memory_register;
+
+        match instruction {
+            MachineInstruction::SPSL { .. } | MachineInstruction::SPSH { .. } => self.base = Some(stack_pointer),
+            MachineInstruction::MSRL { .. } | MachineInstruction::MSRH { .. } => self.stack_address = false,
+            MachineInstruction::MEMW { .. } | MachineInstruction::RTWL | MachineInstruction::RTWH
+                if !self.stack_address =>
+            {
+                self.data.insert(memory_register);
+            }
+            MachineInstruction::PUSH => {
+                self.stack_address = true;
+                // The value goes where the stack pointer was.
+                let slot = old_stack_pointer;
+                let region = if (slot as usize) < self.program_length {
+                    "inside the program"
+                } else if self.data.contains(&slot) {
+                    "where the program keeps data"
+                } else if is_device(slot) {
+                    "onto the devices"
+                } else {
+                    return Ok(());
+                };
+                bail!(
+                    "stack overflow: PUSH at {} grows the stack to {:0>4x}, {}",
+                    symbols.describe(address),
+                    slot,
+                    region
+                );
+            }
+            MachineInstruction::PULL => {
+                self.stack_address = true;
+                if let Some(base) = self.base {
+                    if stack_pointer > base || stack_pointer < old_stack_pointer {
+                        bail!(
+                            "stack underflow: PULL at {} with the stack empty, its bottom is at {:0>4x}",
+                            symbols.describe(address),
+                            base
+                        );
+                    }
+                }
+            }
+            MachineInstruction::PEEK => self.stack_address = true,
+            MachineInstruction::SPOF => {
+                self.stack_address = true;
+                // What is on the stack is above the stack pointer, up to the bottom of the stack.
+                let offset = memory_register.wrapping_sub(stack_pointer);
+                let outside = match self.base {
+                    Some(base) => offset == 0 || offset > base.wrapping_sub(stack_pointer),
+                    None => offset == 0,
+                };
+                if outside {
+                    let stack = match self.base {
+                        Some(base) if base == stack_pointer => "the stack is empty".to_string(),
+                        Some(base) if base == stack_pointer.wrapping_add(1) => format!("the stack is {:0>4x}", base),
+                        Some(base) => format!("the stack is {:0>4x}-{:0>4x}", stack_pointer.wrapping_add(1), base),
+                        None => format!("the stack starts at {:0>4x}", stack_pointer.wrapping_add(1)),
+                    };
+                    bail!(
+                        "SPOF {} at {} reaches {:0>4x}, outside the stack: {}",
+                        offset,
+                        symbols.describe(address),
+                        memory_register,
+                        stack
+                    );
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn next_instruction(emulator: &impl Emulator) -> (u16, MachineInstruction, u16) {
+    let state = emulator.state();
+    let address = state.instruction_pointer;
+    (
+        address,
+        MachineInstruction::from(state.memory.peek(address)),
+        state.stack_pointer,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_with_symbols;
+    use crate::emulate::isa_emulator::IsaEmulator;
+
+    const SETUP: &str = "LI A, 0x00\nLI B, 0x80\nSPSL A\nSPSH B\n:main\n";
+
+    fn check(source: String) -> eyre::Result<()> {
+        // The assembler wants a static source, like the one read by the emulate command.
+        let (program, symbols) = assemble_with_symbols(source.leak())?;
+        let mut emulator = IsaEmulator::new(&program.0)?;
+        let mut stack = StackCheck::new(&emulator, &program.0);
+        emulator.run(1_000, |emulator| stack.after_instruction(emulator, &symbols))?;
+        Ok(())
+    }
+
+    fn error(source: String) -> String {
+        match check(source) {
+            Ok(()) => panic!("the check should fail"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_balanced() -> eyre::Result<()> {
+        check(format!("{}PUSH\nMEMW A\nPUSH\nSPOF 2\nMEMR C\nPULL\nPULL\nHLT", SETUP))
+    }
+
+    #[test]
+    fn test_underflow() {
+        assert_eq!(
+            error(format!("{}PUSH\nPULL\nPULL\nHLT", SETUP)),
+            "stack underflow: PULL at 0008 :main+2 with the stack empty, its bottom is at 8000"
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        // Without SPSL and SPSH the stack pointer is 0x0000, right on the program.
+        assert_eq!(
+            error("PUSH\nHLT".to_string()),
+            "stack overflow: PUSH at 0000 grows the stack to 0000, inside the program"
+        );
+        let data = "LI A, 0xff\nLI B, 0x7f\nMSRL A\nMSRH B\nMEMW A\n";
+        assert_eq!(
+            error(format!("{}{}PUSH\nPUSH\nHLT", SETUP, data)),
+            "stack overflow: PUSH at 000e :main+8 grows the stack to 7fff, where the program keeps data"
+        );
+        let devices = "LI A, 0x10\nLI B, 0xff\nSPSL A\nSPSH B\n";
+        assert_eq!(
+            error(format!("{}PUSH\nHLT", devices)),
+            "stack overflow: PUSH at 0006 grows the stack to ff10, onto the devices"
+        );
+    }
+
+    #[test]
+    fn test_outside_frame() {
+        assert_eq!(
+            error(format!("{}PUSH\nSPOF 2\nHLT", SETUP)),
+            "SPOF 2 at 0007 :main+1 reaches 8001, outside the stack: the stack is 8000"
+        );
+        assert_eq!(
+            error(format!("{}SPOF 0\nHLT", SETUP)),
+            "SPOF 0 at 0006 :main reaches 8000, outside the stack: the stack is empty"
+        );
+    }
+    #[test]
+    fn test_stack_around_the_end_of_memory() -> eyre::Result<()> {
+        let (program, symbols) = assemble_with_symbols("SPOF 0\nHLT")?;
+        let mut emulator = IsaEmulator::new(&program.0)?;
+        emulator.state.stack_pointer = 0xFFFF;
+        let mut stack = StackCheck::new(&emulator, &program.0);
+        stack.base = Some(0x0001);
+
+        emulator.step_instruction()?;
+        let error = stack.after_instruction(&emulator, &symbols).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "SPOF 0 at 0000 reaches ffff, outside the stack: the stack is 0000-0001"
+        );
+
+        Ok(())
+    }
+}
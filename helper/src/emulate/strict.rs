@@ -0,0 +1,148 @@
+use crate::assemble::symbols::Symbols;
+use crate::constants::machine_instruction::MachineInstruction;
+use crate::emulate::devices::is_device;
+use crate::emulate::emulator::Emulator;
+use crate::emulate::memory::MEMORY_SIZE;
+use eyre::bail;
+use std::collections::BTreeSet;
+
+/// Catches what the hardware lets programs do but is almost always a bug, before the instruction runs:
+/// - reading RAM that was never written, neither by the program image nor at run time;
+/// - executing the bytes the assembler emitted as values or label addresses, or RAM that was never written;
+/// - executing an unused opcode, that the machine silently runs as a NOP.
+pub struct StrictCheck {
+    /// The addresses the assembler put an instruction at.
+    instructions: BTreeSet<u16>,
+    program_length: usize,
+    initialized: Vec<bool>,
+}
+
+impl StrictCheck {
+    pub fn new(program: &[u8], symbols: &Symbols) -> Self {
+        let mut initialized = vec![false; MEMORY_SIZE];
+        initialized[..program.len()].fill(true);
+        StrictCheck {
+            instructions: symbols.lines().iter().map(|(address, _)| *address).collect(),
+            program_length: program.len(),
+            initialized,
+        }
+    }
+
+    /// Check the instruction the emulator is about to execute: call it before the first one, then after each.
+    pub fn check_next(&mut self, emulator: &impl Emulator, symbols: &Symbols) -> eyre::Result<()> {
+        if emulator.halted() {
+            return Ok(());
+        }
+        let state = emulator.state();
+        let address = state.instruction_pointer;
+        let opcode = state.memory.peek(address);
+
+        if (address as usize) < self.program_length {
+            if !self.instructions.contains(&address) {
+                bail!(
+                    "executing data: {} is not an instruction, the assembler emitted it as a value or a label",
+                    symbols.describe(address)
+                );
+            }
+        } else if !self.initialized[address as usize] {
+            bail!("executing uninitialized RAM at {}", symbols.describe(address));
+        }
+        if MachineInstruction::is_unused_opcode(opcode) {
+            bail!(
+                "unused opcode {:0>2x} at {}, the machine would run it as a NOP",
+                opcode,
+                symbols.describe(address)
+            );
+        }
+
+        let memory_register = state.memory_register;
+        match MachineInstruction::from(opcode) {
+            MachineInstruction::MEMR { .. } | MachineInstruction::RTRL | MachineInstruction::RTRH
+                if !is_device(memory_register) && !self.initialized[memory_register as usize] =>
+            {
+                bail!(
+                    "read of uninitialized RAM at {:0>4x} by {}",
+                    memory_register,
+                    symbols.describe(address)
+                );
+            }
+            MachineInstruction::MEMW { .. } | MachineInstruction::RTWL | MachineInstruction::RTWH => {
+                self.initialized[memory_register as usize] = true;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_with_symbols;
+    use crate::emulate::isa_emulator::IsaEmulator;
+
+    /// Run the program from the given address.
+    fn check(source: &'static str, start: u16) -> eyre::Result<()> {
+        let (program, symbols) = assemble_with_symbols(source)?;
+        let mut emulator = IsaEmulator::new(&program.0)?;
+        emulator.state.instruction_pointer = start;
+        let mut strict = StrictCheck::new(&program.0, &symbols);
+        strict.check_next(&emulator, &symbols)?;
+        emulator.run(1_000, |emulator| strict.check_next(emulator, &symbols))?;
+        Ok(())
+    }
+
+    fn error(source: &'static str, start: u16) -> String {
+        match check(source, start) {
+            Ok(()) => panic!("the check should fail"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_clean() -> eyre::Result<()> {
+        check(include_str!("../../../examples/fib.as"), 0x0000)?;
+        check(
+            "NOP\nLI A, 0x00\nLI B, 0x80\nMSRL A\nMSRH B\nMEMW A\nMEMR C\nHLT",
+            0x0000,
+        )?;
+        // The devices are not RAM, reading them is fine.
+        check(
+            "LI A, 0x10\nLI B, 0xFF\nMSRL A\nMSRH B\nMEMR C\nLI B, 0xF8\nMSRH B\nMEMR C\nHLT",
+            0x0000,
+        )?;
+        // The program image counts as initialized.
+        check("LI A, 0x00\nMSRL A\nMSRH A\nMEMR C\nHLT", 0x0000)
+    }
+
+    #[test]
+    fn test_uninitialized() {
+        assert_eq!(
+            error("LI A, 0x00\nLI B, 0x80\nMSRL A\nMSRH B\n:read\nMEMR C\nHLT", 0x0000),
+            "read of uninitialized RAM at 8000 by 0006 :read"
+        );
+        assert_eq!(error("HLT", 0x8000), "executing uninitialized RAM at 8000");
+    }
+
+    #[test]
+    fn test_executing_data() {
+        assert_eq!(
+            error("LI A, 0x01\nHLT", 0x0001),
+            "executing data: 0001 is not an instruction, the assembler emitted it as a value or a label"
+        );
+    }
+
+    #[test]
+    fn test_unused_opcode() {
+        assert!(MachineInstruction::is_unused_opcode(0b1001_0000));
+        assert!(!MachineInstruction::is_unused_opcode(0b1111_1110));
+        assert_eq!(
+            error(
+                ":start\nLI A, 0x94\nLI B, 0x00\nMSRL B\nMSRH B\nMEMW A\nPJMP :start\nJMP",
+                0x0000
+            ),
+            "unused opcode 94 at 0000 :start, the machine would run it as a NOP"
+        );
+    }
+}
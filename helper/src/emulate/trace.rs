@@ -0,0 +1,332 @@
+use crate::constants::control_line::ControlLine;
+use crate::constants::control_word::ControlWord;
+use crate::constants::flag::{Flag, Flags};
+use crate::constants::machine_instruction::MachineInstruction;
+use crate::emulate::emulator::Emulator;
+use crate::emulate::machine_state::MachineState;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+const FLAGS: [Flag; 4] = [Flag::FZ, Flag::CO, Flag::A2G1, Flag::NEG];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per entry, `key=value` separated by spaces.
+    Text,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+/// The registers at some point of the run, without the RAM: cheap enough to be copied at every clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Values {
+    pub registers: [u8; 4],
+    pub alu1: u8,
+    pub alu2: u8,
+    pub instruction_pointer: u16,
+    pub stack_pointer: u16,
+    pub jump_register: u16,
+    pub return_register: u16,
+    pub memory_register: u16,
+}
+
+/// A clock cycle of the microcode emulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    /// The step counter when the control word was executed.
+    pub step: u8,
+    pub control_word: ControlWord,
+    pub bus: u8,
+    /// The flags the control ROM was addressed with, once they settled under the ALU operation of the control word.
+    pub flags: Flags,
+    /// The registers once the clock latched the bus.
+    pub after: Values,
+}
+
+/// Writes what every instruction did, and optionally every clock of it, in a format that stays the same from one run
+/// to the next: no timestamps and no counters, so that two traces can be diffed and the first different line is where
+/// the behaviour changed.
+pub struct Trace<W: Write> {
+    pub output: W,
+    format: TraceFormat,
+    microsteps: bool,
+    /// The instruction about to be executed: its address and opcode.
+    next: (u16, u8),
+}
+
+impl Values {
+    pub fn capture(state: &MachineState) -> Self {
+        Values {
+            registers: state.registers,
+            alu1: state.alu1,
+            alu2: state.alu2,
+            instruction_pointer: state.instruction_pointer,
+            stack_pointer: state.stack_pointer,
+            jump_register: state.jump_register,
+            return_register: state.return_register,
+            memory_register: state.memory_register,
+        }
+    }
+
+    fn text(&self, flags: Flags) -> String {
+        format!(
+            "A={:0>2x} B={:0>2x} C={:0>2x} D={:0>2x} A1={:0>2x} A2={:0>2x} IP={:0>4x} SP={:0>4x} JMP={:0>4x} RET={:0>4x} MEM={:0>4x} flags=[{}]",
+            self.registers[0],
+            self.registers[1],
+            self.registers[2],
+            self.registers[3],
+            self.alu1,
+            self.alu2,
+            self.instruction_pointer,
+            self.stack_pointer,
+            self.jump_register,
+            self.return_register,
+            self.memory_register,
+            flag_names(flags).join(" ")
+        )
+    }
+
+    fn json(&self, flags: Flags) -> String {
+        format!(
+            "\"registers\":{{\"A\":{},\"B\":{},\"C\":{},\"D\":{},\"A1\":{},\"A2\":{},\"IP\":{},\"SP\":{},\"JMP\":{},\"RET\":{},\"MEM\":{}}},\"flags\":{}",
+            self.registers[0],
+            self.registers[1],
+            self.registers[2],
+            self.registers[3],
+            self.alu1,
+            self.alu2,
+            self.instruction_pointer,
+            self.stack_pointer,
+            self.jump_register,
+            self.return_register,
+            self.memory_register,
+            json_strings(&flag_names(flags))
+        )
+    }
+}
+
+impl Trace<BufWriter<File>> {
+    /// Trace to the file: JSON Lines if its extension is `.jsonl`, text otherwise.
+    pub fn create(file: &str, microsteps: bool, emulator: &impl Emulator) -> eyre::Result<Self> {
+        let format = if file.ends_with(".jsonl") {
+            TraceFormat::JsonLines
+        } else {
+            TraceFormat::Text
+        };
+        Ok(Trace::new(
+            BufWriter::new(File::create(file)?),
+            format,
+            microsteps,
+            emulator,
+        ))
+    }
+}
+
+impl<W: Write> Trace<W> {
+    /// Start tracing from the current state of the emulator.
+    pub fn new(output: W, format: TraceFormat, microsteps: bool, emulator: &impl Emulator) -> Self {
+        Trace {
+            output,
+            format,
+            microsteps,
+            next: next_instruction(emulator),
+        }
+    }
+
+    /// Write the instruction the emulator just executed, with the clocks it took: none for the instruction level
+    /// emulator.
+    pub fn after_instruction(&mut self, emulator: &impl Emulator, clocks: &[Clock]) -> eyre::Result<()> {
+        let (address, opcode) = self.next;
+        self.next = next_instruction(emulator);
+        let instruction = MachineInstruction::from(opcode);
+        let after = Values::capture(emulator.state());
+        // Between two instructions the ALU is idle.
+        let flags = emulator.state().flags();
+        let steps: Vec<Vec<String>> = clocks.iter().map(|clock| line_names(&clock.control_word)).collect();
+
+        match self.format {
+            TraceFormat::Text => {
+                write!(
+                    self.output,
+                    "{:0>4x} {:0>2x} {:?}  {}",
+                    address,
+                    opcode,
+                    instruction,
+                    after.text(flags)
+                )?;
+                if !steps.is_empty() {
+                    let steps: Vec<String> = steps.iter().map(|lines| format!("[{}]", lines.join(" "))).collect();
+                    write!(self.output, "  steps={}", steps.join(" "))?;
+                }
+                writeln!(self.output)?;
+            }
+            TraceFormat::JsonLines => {
+                write!(
+                    self.output,
+                    "{{\"ip\":{},\"opcode\":{},\"instruction\":\"{:?}\",{}",
+                    address,
+                    opcode,
+                    instruction,
+                    after.json(flags)
+                )?;
+                if !steps.is_empty() {
+                    let steps: Vec<String> = steps.iter().map(|lines| json_strings(lines)).collect();
+                    write!(self.output, ",\"steps\":[{}]", steps.join(","))?;
+                }
+                writeln!(self.output, "}}")?;
+            }
+        }
+
+        if self.microsteps {
+            for clock in clocks {
+                self.clock(address, opcode, clock)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn clock(&mut self, address: u16, opcode: u8, clock: &Clock) -> eyre::Result<()> {
+        let lines = line_names(&clock.control_word);
+        match self.format {
+            TraceFormat::Text => writeln!(
+                self.output,
+                "  step {} bus={:0>2x} [{}]  {}",
+                clock.step,
+                clock.bus,
+                lines.join(" "),
+                clock.after.text(clock.flags)
+            )?,
+            TraceFormat::JsonLines => writeln!(
+                self.output,
+                "{{\"ip\":{},\"opcode\":{},\"step\":{},\"bus\":{},\"lines\":{},{}}}",
+                address,
+                opcode,
+                clock.step,
+                clock.bus,
+                json_strings(&lines),
+                clock.after.json(clock.flags)
+            )?,
+        }
+        Ok(())
+    }
+}
+
+fn next_instruction(emulator: &impl Emulator) -> (u16, u8) {
+    let state = emulator.state();
+    let address = state.instruction_pointer;
+    (address, state.memory.peek(address))
+}
+
+fn flag_names(flags: Flags) -> Vec<String> {
+    FLAGS
+        .into_iter()
+        .filter(|flag| flags.has(*flag))
+        .map(|flag| format!("{:?}", flag))
+        .collect()
+}
+
+fn line_names(control_word: &ControlWord) -> Vec<String> {
+    control_word
+        .lines()
+        .iter()
+        .map(|line: &ControlLine| format!("{:?}", line))
+        .collect()
+}
+
+/// Names only: nothing to escape.
+fn json_strings(strings: &[String]) -> String {
+    let quoted: Vec<String> = strings.iter().map(|string| format!("\"{}\"", string)).collect();
+    format!("[{}]", quoted.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_program;
+    use crate::emulate::isa_emulator::IsaEmulator;
+    use crate::emulate::microcode::Microcode;
+    use crate::emulate::microcode_emulator::MicrocodeEmulator;
+    use std::sync::Arc;
+
+    const PROGRAM: &str = "LI A, 0x12\nMV B, A\nHLT";
+
+    fn microcode_trace(format: TraceFormat, microsteps: bool) -> eyre::Result<String> {
+        trace_program(PROGRAM, format, microsteps)
+    }
+
+    fn trace_program(program: &'static str, format: TraceFormat, microsteps: bool) -> eyre::Result<String> {
+        let program = assemble_program(program)?;
+        let mut emulator = MicrocodeEmulator::new(Arc::new(Microcode::from_steps()), &program.0)?;
+        emulator.clocks = Some(Vec::new());
+        let mut trace = Trace::new(Vec::new(), format, microsteps, &emulator);
+        emulator.run(100, |emulator| trace.after_instruction(emulator, emulator.clocks()))?;
+        Ok(String::from_utf8(trace.output)?)
+    }
+
+    #[test]
+    fn test_text() -> eyre::Result<()> {
+        let trace = microcode_trace(TraceFormat::Text, false)?;
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(
+            "0000 20 LI { dst: A }  A=12 B=00 C=00 D=00 A1=00 A2=00 IP=0002 SP=0000 JMP=0000 RET=0000 MEM=0000 flags=[FZ]  steps=[MO IRE] [IPA] [RIE MO] [IPA] [MRST]"
+        ));
+        assert!(lines[1].starts_with("0002 04 MV { dst: B, src: A }  A=12 B=12"));
+        assert!(lines[2].starts_with("0003 ff HLT "));
+
+        // Without the microcode, the same instructions and registers.
+        let program = assemble_program(PROGRAM)?;
+        let mut emulator = IsaEmulator::new(&program.0)?;
+        let mut trace = Trace::new(Vec::new(), TraceFormat::Text, false, &emulator);
+        emulator.run(100, |emulator| trace.after_instruction(emulator, &[]))?;
+        let isa = String::from_utf8(trace.output)?;
+        let isa: Vec<&str> = isa.lines().collect();
+        assert_eq!(isa.len(), 3);
+        assert!(lines[0].starts_with(isa[0]));
+        assert!(!isa[0].contains("steps"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_microsteps() -> eyre::Result<()> {
+        let trace = microcode_trace(TraceFormat::Text, true)?;
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines[1], "  step 0 bus=20 [MO IRE]  A=00 B=00 C=00 D=00 A1=00 A2=00 IP=0000 SP=0000 JMP=0000 RET=0000 MEM=0000 flags=[FZ]");
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("  step 2 bus=12 [RIE MO]  A=12 ")));
+        // Every instruction is followed by its clocks.
+        assert_eq!(lines.iter().filter(|line| !line.starts_with("  ")).count(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_settled_flags() -> eyre::Result<()> {
+        let trace = trace_program("LI A, 0x01\nLI B, 0x01\nSUB A, B\nHLT", TraceFormat::Text, true)?;
+        let lines: Vec<&str> = trace.lines().collect();
+        // While AO drives the difference the control ROM sees its zero flag, not the one of the idle addition.
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("  step 4 bus=00 [RIE AOPL AO]  A=00 ") && line.ends_with(" flags=[FZ]")));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("0004 51 SUB { acc: A, val: B }  A=00 ") && line.contains(" flags=[]  ")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_lines() -> eyre::Result<()> {
+        let trace = microcode_trace(TraceFormat::JsonLines, true)?;
+        let lines: Vec<&str> = trace.lines().collect();
+        assert!(lines[0].starts_with(
+            "{\"ip\":0,\"opcode\":32,\"instruction\":\"LI { dst: A }\",\"registers\":{\"A\":18,\"B\":0,\"C\":0,\"D\":0,\"A1\":0,\"A2\":0,\"IP\":2,\"SP\":0,\"JMP\":0,\"RET\":0,\"MEM\":0},\"flags\":[\"FZ\"],\"steps\":[[\"MO\",\"IRE\"],"
+        ));
+        assert!(lines[1].starts_with("{\"ip\":0,\"opcode\":32,\"step\":0,\"bus\":32,\"lines\":[\"MO\",\"IRE\"],"));
+        assert!(lines.iter().all(|line| line.starts_with('{') && line.ends_with('}')));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use crate::constants::control_line::CONTROL_LINES;
+use crate::emulate::microcode_emulator::MicrocodeEmulator;
+use crate::emulate::trace::{Clock, Values};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// The step counter drives the 4 step bits of the control ROM address.
+const STEP_WIDTH: usize = 4;
+
+/// A signal of the dump: its name, its width in bits and its value.
+struct Signal {
+    name: String,
+    width: usize,
+    value: Option<u64>,
+}
+
+/// Writes the clock cycles of the microcode emulator as a Value Change Dump, for GTKWave: one signal per control line,
+/// the bus, the step counter, the instruction pointer and the flags. Every clock cycle lasts two time units, with
+/// `clk` rising at the start of the cycle and the registers latched at the next rising edge, like in the circuit.
+pub struct Vcd<W: Write> {
+    pub output: W,
+    signals: Vec<Signal>,
+    /// The registers before the next clock cycle.
+    current: Values,
+    time: u64,
+}
+
+impl Vcd<BufWriter<File>> {
+    pub fn create(file: &str, emulator: &MicrocodeEmulator) -> eyre::Result<Self> {
+        Vcd::new(BufWriter::new(File::create(file)?), emulator)
+    }
+}
+
+impl<W: Write> Vcd<W> {
+    /// Write the header, starting from the current state of the emulator.
+    pub fn new(mut output: W, emulator: &MicrocodeEmulator) -> eyre::Result<Self> {
+        let mut signals = vec![signal("clk", 1)];
+        signals.extend(CONTROL_LINES.iter().map(|line| signal(&format!("{:?}", line), 1)));
+        signals.extend([
+            signal("bus", 8),
+            signal("step", STEP_WIDTH),
+            signal("IP", 16),
+            signal("FZ", 1),
+            signal("CO", 1),
+            signal("A2G1", 1),
+            signal("NEG", 1),
+        ]);
+
+        writeln!(output, "$version helper $end")?;
+        writeln!(output, "$timescale 1us $end")?;
+        writeln!(output, "$scope module mypc $end")?;
+        for (index, signal) in signals.iter().enumerate() {
+            writeln!(
+                output,
+                "$var wire {} {} {} $end",
+                signal.width,
+                identifier(index),
+                signal.name
+            )?;
+        }
+        writeln!(output, "$upscope $end")?;
+        writeln!(output, "$enddefinitions $end")?;
+
+        Ok(Vcd {
+            output,
+            signals,
+            current: Values::capture(&emulator.state),
+            time: 0,
+        })
+    }
+
+    /// Write the clock cycles of the instruction the emulator just executed.
+    pub fn after_instruction(&mut self, clocks: &[Clock]) -> eyre::Result<()> {
+        for clock in clocks {
+            let mut values = vec![1];
+            values.extend(CONTROL_LINES.iter().map(|line| clock.control_word.has(*line) as u64));
+            // What the rest of the machine sees during the cycle: the registers latch at its end.
+            let flags = clock.flags;
+            values.extend([
+                clock.bus as u64,
+                clock.step as u64,
+                self.current.instruction_pointer as u64,
+                flags.value() as u64 & 0b0001,
+                (flags.value() as u64 & 0b0010) >> 1,
+                (flags.value() as u64 & 0b0100) >> 2,
+                (flags.value() as u64 & 0b1000) >> 3,
+            ]);
+            self.change(&values)?;
+            self.change(&[0])?;
+            self.current = clock.after;
+        }
+        Ok(())
+    }
+
+    /// Move to the next time unit, writing the signals that changed, starting from the first one.
+    fn change(&mut self, values: &[u64]) -> eyre::Result<()> {
+        let first = self.time == 0;
+        writeln!(self.output, "#{}", self.time)?;
+        if first {
+            writeln!(self.output, "$dumpvars")?;
+        }
+        for (index, (signal, value)) in self.signals.iter_mut().zip(values).enumerate() {
+            if signal.value == Some(*value) {
+                continue;
+            }
+            signal.value = Some(*value);
+            if signal.width == 1 {
+                writeln!(self.output, "{}{}", value, identifier(index))?;
+            } else {
+                writeln!(self.output, "b{:b} {}", value, identifier(index))?;
+            }
+        }
+        if first {
+            writeln!(self.output, "$end")?;
+        }
+        self.time += 1;
+        Ok(())
+    }
+}
+
+fn signal(name: &str, width: usize) -> Signal {
+    Signal {
+        name: name.to_string(),
+        width,
+        value: None,
+    }
+}
+
+/// The short code the changes refer to the signal with: printable ASCII characters, from `!`.
+fn identifier(index: usize) -> char {
+    (b'!' + index as u8) as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::assemble::assemble_program;
+    use crate::emulate::emulator::Emulator;
+    use crate::emulate::microcode::Microcode;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_dump() -> eyre::Result<()> {
+        let program = assemble_program("LI A, 0x12\nHLT")?;
+        let mut emulator = MicrocodeEmulator::new(Arc::new(Microcode::from_steps()), &program.0)?;
+        emulator.clocks = Some(Vec::new());
+        let mut vcd = Vcd::new(Vec::new(), &emulator)?;
+        emulator.run(100, |emulator| vcd.after_instruction(emulator.clocks()))?;
+        let dump = String::from_utf8(vcd.output)?;
+
+        // clk, 36 control lines, bus, step, IP and the 4 flags.
+        assert_eq!(dump.matches("$var wire ").count(), 44);
+        assert!(dump.contains("$var wire 1 \" RST $end\n"));
+        assert!(dump.contains("$var wire 8 F bus $end\n"));
+        assert!(dump.contains("$var wire 16 H IP $end\n"));
+
+        // LI takes 5 cycles and HLT 3, two time units each.
+        assert!(dump.contains("\n#15\n"));
+        assert!(!dump.contains("\n#16\n"));
+
+        // The first step drives the opcode on the bus, the third the operand.
+        let cycle = |time: u64| {
+            let start = dump.find(&format!("\n#{}\n", time)).unwrap() + 1;
+            let end = dump[start..].find("\n#").map_or(dump.len(), |end| start + end + 1);
+            dump[start..end].to_string()
+        };
+        assert!(cycle(0).contains("b100000 F\n"));
+        assert!(cycle(4).contains("b10010 F\n"));
+        assert_eq!(cycle(1), "#1\n0!\n");
+        // Only the changes are written: IP stays 0002 during the fetch of HLT.
+        assert!(cycle(8).contains("b10 H\n"));
+        assert!(!cycle(10).contains(" H\n"));
+
+        Ok(())
+    }
+}
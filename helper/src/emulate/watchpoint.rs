@@ -0,0 +1,284 @@
+use crate::constants::flag::Flag;
+use crate::emulate::machine_state::MachineState;
+use eyre::bail;
+
+/// A data access of the machine: the ones going through the memory register (MEMR, MEMW, RTWL, RTWH, RTRL, RTRH),
+/// not the instruction fetches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+/// Which accesses stop the machine, like gdb's `watch`, `rwatch` and `awatch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(usize),
+    Alu1,
+    Alu2,
+    InstructionPointer,
+    StackPointer,
+    JumpRegister,
+    ReturnRegister,
+    MemoryRegister,
+    Flag(Flag),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A register or a flag compared with a constant, like `A == 0x0d` or `SP < 0xFE00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub operand: Operand,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+    Memory {
+        start: u16,
+        length: u16,
+        kind: WatchKind,
+    },
+    /// Stops when the condition becomes true, not at every step while it stays true.
+    Condition(Condition),
+}
+
+impl WatchKind {
+    pub fn matches(&self, access: &Access) -> bool {
+        match self {
+            WatchKind::Write => access.write,
+            WatchKind::Read => !access.write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+impl Operand {
+    pub fn parse(name: &str) -> eyre::Result<Self> {
+        let operand = match name.to_uppercase().as_str() {
+            "A" => Operand::Register(0),
+            "B" => Operand::Register(1),
+            "C" => Operand::Register(2),
+            "D" => Operand::Register(3),
+            "A1" => Operand::Alu1,
+            "A2" => Operand::Alu2,
+            "IP" => Operand::InstructionPointer,
+            "SP" => Operand::StackPointer,
+            "JMP" => Operand::JumpRegister,
+            "RET" => Operand::ReturnRegister,
+            "MEM" => Operand::MemoryRegister,
+            "FZ" => Operand::Flag(Flag::FZ),
+            "CO" => Operand::Flag(Flag::CO),
+            "A2G1" => Operand::Flag(Flag::A2G1),
+            "NEG" => Operand::Flag(Flag::NEG),
+            _ => bail!("Unknown register or flag: {}", name),
+        };
+        Ok(operand)
+    }
+
+    pub fn value(&self, state: &MachineState) -> u16 {
+        match self {
+            Operand::Register(index) => state.registers[*index] as u16,
+            Operand::Alu1 => state.alu1 as u16,
+            Operand::Alu2 => state.alu2 as u16,
+            Operand::InstructionPointer => state.instruction_pointer,
+            Operand::StackPointer => state.stack_pointer,
+            Operand::JumpRegister => state.jump_register,
+            Operand::ReturnRegister => state.return_register,
+            Operand::MemoryRegister => state.memory_register,
+            Operand::Flag(flag) => state.flags().has(*flag) as u16,
+        }
+    }
+}
+
+impl core::fmt::Display for Operand {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Operand::Register(index) => write!(f, "{}", ["A", "B", "C", "D"][*index]),
+            Operand::Alu1 => write!(f, "A1"),
+            Operand::Alu2 => write!(f, "A2"),
+            Operand::InstructionPointer => write!(f, "IP"),
+            Operand::StackPointer => write!(f, "SP"),
+            Operand::JumpRegister => write!(f, "JMP"),
+            Operand::ReturnRegister => write!(f, "RET"),
+            Operand::MemoryRegister => write!(f, "MEM"),
+            Operand::Flag(flag) => write!(f, "{:?}", flag),
+        }
+    }
+}
+
+impl Comparison {
+    pub fn parse(operator: &str) -> eyre::Result<Self> {
+        let comparison = match operator {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterOrEqual,
+            _ => bail!("Unknown comparison: {}", operator),
+        };
+        Ok(comparison)
+    }
+
+    pub fn operator(&self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+impl Condition {
+    pub fn holds(&self, state: &MachineState) -> bool {
+        let value = self.operand.value(state);
+        match self.comparison {
+            Comparison::Equal => value == self.value,
+            Comparison::NotEqual => value != self.value,
+            Comparison::Less => value < self.value,
+            Comparison::LessOrEqual => value <= self.value,
+            Comparison::Greater => value > self.value,
+            Comparison::GreaterOrEqual => value >= self.value,
+        }
+    }
+}
+
+impl core::fmt::Display for Condition {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} {} 0x{:x}", self.operand, self.comparison.operator(), self.value)
+    }
+}
+
+impl Watchpoint {
+    /// The access that hit the watchpoint, if any.
+    pub fn hit<'a>(&self, accesses: &'a [Access]) -> Option<&'a Access> {
+        let Watchpoint::Memory { start, length, kind } = self else {
+            return None;
+        };
+        accesses.iter().find(|access| {
+            kind.matches(access) && (access.address.wrapping_sub(*start) as u32) < (*length).max(1) as u32
+        })
+    }
+
+    /// Whether the watchpoint stops a step: `before` is whether its condition held before the step.
+    pub fn triggered(&self, before: bool, state: &MachineState, accesses: &[Access]) -> bool {
+        match self {
+            Watchpoint::Memory { .. } => self.hit(accesses).is_some(),
+            Watchpoint::Condition(condition) => !before && condition.holds(state),
+        }
+    }
+}
+
+impl core::fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Watchpoint::Memory { start, length, kind } => {
+                let kind = match kind {
+                    WatchKind::Write => "writes to",
+                    WatchKind::Read => "reads from",
+                    WatchKind::Access => "accesses to",
+                };
+                if *length <= 1 {
+                    write!(f, "{} {:0>4x}", kind, start)
+                } else {
+                    let end = start.wrapping_add(length - 1);
+                    write!(f, "{} {:0>4x}-{:0>4x}", kind, start, end)
+                }
+            }
+            Watchpoint::Condition(condition) => write!(f, "{}", condition),
+        }
+    }
+}
+
+impl core::fmt::Display for Access {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.write {
+            write!(f, "wrote {:0>2x} to {:0>4x}", self.value, self.address)
+        } else {
+            write!(f, "read {:0>2x} from {:0>4x}", self.value, self.address)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word_bytes::WordBytes;
+
+    #[test]
+    fn test_condition() -> eyre::Result<()> {
+        let mut state = MachineState::new(&[])?;
+        let condition = Condition {
+            operand: Operand::parse("sp")?,
+            comparison: Comparison::parse("<")?,
+            value: 0xFE00,
+        };
+        assert_eq!(condition.to_string(), "SP < 0xfe00");
+
+        state.stack_pointer = 0xFE00;
+        assert!(!condition.holds(&state));
+        state.stack_pointer = state.stack_pointer.with_byte(true, 0xFD);
+        assert!(condition.holds(&state));
+
+        let watchpoint = Watchpoint::Condition(condition);
+        assert!(watchpoint.triggered(false, &state, &[]));
+        assert!(!watchpoint.triggered(true, &state, &[]));
+
+        assert!(Operand::parse("X").is_err());
+        assert!(Comparison::parse("=").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory() -> eyre::Result<()> {
+        let watchpoint = Watchpoint::Memory {
+            start: 0x8000,
+            length: 0x10,
+            kind: WatchKind::Write,
+        };
+        let read = Access {
+            address: 0x8004,
+            value: 0x42,
+            write: false,
+        };
+        let write = Access { write: true, ..read };
+
+        assert_eq!(watchpoint.hit(&[read]), None);
+        assert_eq!(watchpoint.hit(&[read, write]), Some(&write));
+        assert!(!watchpoint.triggered(
+            false,
+            &MachineState::new(&[])?,
+            &[Access {
+                address: 0x8010,
+                ..write
+            }]
+        ));
+        assert_eq!(watchpoint.to_string(), "writes to 8000-800f");
+        assert_eq!(write.to_string(), "wrote 42 to 8004");
+
+        Ok(())
+    }
+}
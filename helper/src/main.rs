@@ -7,6 +7,10 @@ mod starts_with;
 mod burn;
 mod assemble;
 mod hex_u8;
+mod emulate;
+mod debug;
+mod circuit;
+mod word_bytes;
 
 fn main() -> eyre::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -15,7 +19,8 @@ fn main() -> eyre::Result<()> {
     }
 
     match args[1].as_str() {
-        "burn" => burn::burn(),
+        "burn" => burn::burn(&args[2..]),
+        "assemble" if args.len() > 3 => circuit::contents::assemble(&args[2..]),
         "assemble" if args.len() < 3 => {
             let buffer = {
                 let mut buffer = String::new();
@@ -31,6 +36,35 @@ fn main() -> eyre::Result<()> {
             let file_contents: &'static str = file_contents.leak();
             assemble::assemble::assemble(file_contents)
         }
+        "emulate" => {
+            let options = emulate::emulate::EmulateOptions::parse(&args[2..])?;
+            let status = emulate::emulate::emulate(options)?;
+            if status != 0 {
+                // Let scripts and CI tell a failed test program apart.
+                std::process::exit(status.into());
+            }
+            Ok(())
+        }
+        "test" => {
+            if !emulate::program_test::test(&args[2..])? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        "circuit" => {
+            if !circuit::cross_check::circuit(&args[2..])? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        "wiring" => {
+            if !circuit::wiring::wiring(&args[2..])? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        "debug" => debug::debug::debug(&args[2..]),
+        "gdb" => debug::debug::gdb(&args[2..]),
         other => bail!("Unknown command: {}", other),
     }
 }
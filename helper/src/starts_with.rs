@@ -13,9 +13,16 @@ impl StartsWith for u8 {
     }
 
     fn binary_representation_starts_with(&self, prefix_str: &'static str) -> bool {
-        let cleaned = prefix_str.replace("_", "").to_ascii_lowercase();
-        let prefix = u8::from_str_radix(&cleaned, 2).unwrap();
-        self.starts_with(prefix, cleaned.len())
+        // Called for every decoded instruction: parse the digits in place instead of allocating a cleaned string.
+        let (prefix, prefix_bits) = prefix_str
+            .chars()
+            .filter(|&digit| digit != '_')
+            .fold((0, 0), |(prefix, bits), digit| match digit {
+                '0' => (prefix << 1, bits + 1),
+                '1' => ((prefix << 1) | 1, bits + 1),
+                _ => panic!("Invalid binary digit: {}", digit),
+            });
+        self.starts_with(prefix, prefix_bits)
     }
 }
 
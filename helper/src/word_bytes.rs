@@ -0,0 +1,53 @@
+pub trait WordBytes {
+    fn low(&self) -> u8;
+    fn high(&self) -> u8;
+    fn byte(&self, high: bool) -> u8;
+    fn with_byte(self, high: bool, byte: u8) -> Self;
+}
+
+impl WordBytes for u16 {
+    fn low(&self) -> u8 {
+        (self & 0xFF) as u8
+    }
+
+    fn high(&self) -> u8 {
+        (self >> 8) as u8
+    }
+
+    fn byte(&self, high: bool) -> u8 {
+        if high {
+            self.high()
+        } else {
+            self.low()
+        }
+    }
+
+    fn with_byte(self, high: bool, byte: u8) -> Self {
+        if high {
+            (self & 0x00FF) | ((byte as u16) << 8)
+        } else {
+            (self & 0xFF00) | (byte as u16)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes() {
+        let value: u16 = 0xABCD;
+        assert_eq!(value.low(), 0xCD);
+        assert_eq!(value.high(), 0xAB);
+        assert_eq!(value.byte(false), 0xCD);
+        assert_eq!(value.byte(true), 0xAB);
+    }
+
+    #[test]
+    fn with_byte() {
+        let value: u16 = 0xABCD;
+        assert_eq!(value.with_byte(false, 0x12), 0xAB12);
+        assert_eq!(value.with_byte(true, 0x12), 0x12CD);
+    }
+}